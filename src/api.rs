@@ -1,34 +1,123 @@
-use std::{collections::HashMap, net::SocketAddr, str::FromStr};
+use std::{
+    collections::HashMap,
+    convert::Infallible,
+    net::{IpAddr, SocketAddr},
+    ops::Deref,
+    str::FromStr,
+    time::Duration,
+};
 
 use axum::{
-    extract::{Path, Query},
-    http::StatusCode,
+    extract::{ConnectInfo, FromRequest, Path, Query, Request},
+    http::{
+        header::{CONTENT_DISPOSITION, CONTENT_TYPE, SET_COOKIE, USER_AGENT},
+        HeaderMap, HeaderName, HeaderValue, StatusCode,
+    },
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        Html, Redirect,
+    },
+    Form, Json,
 };
 use axum_extra::extract::CookieJar;
 use base64::{prelude::BASE64_STANDARD, Engine};
+use tokio::task::JoinSet;
+use tokio_stream::{
+    wrappers::{errors::BroadcastStreamRecvError, BroadcastStream, IntervalStream},
+    Stream, StreamExt,
+};
 use url::Url;
 
 use crate::{
+    auth::{self, Auth},
     config::CONFIG,
-    database,
+    database::{
+        self,
+        api_token::Scope,
+        audit::{self, AuditEntry, AuditLogPage},
+        record::{MonitorRecord, RecordResult, HIDDEN_INFO},
+        user::Role,
+    },
+    export::{self, ImportMode, ImportSummary},
+    live, login_throttle,
     monitor::{
         http::{self as http_mon, HeaderHashMap, HttpExpectedResponse, HttpMethod, HttpRequest},
         tcp::TcpExpectedResponse,
-        MonitorData,
+        MonitorData, TestResult, Visibility,
     },
+    oidc,
+    sla::{self, SlaReport},
+    time_util::current_unix_time,
+    totp,
 };
 
-// Query q fields
+// Parameters sent in the request body, either as a form (application/x-www-form-urlencoded) or
+// as a JSON object (application/json) whose values are strings, numbers or booleans
+pub struct BodyParams(HashMap<String, String>);
+
+impl Deref for BodyParams {
+    type Target = HashMap<String, String>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<S: Send + Sync> FromRequest<S> for BodyParams {
+    type Rejection = (StatusCode, String);
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let content_type = req
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|c| c.to_str().ok())
+            .unwrap_or_default();
+
+        if content_type.starts_with("application/json") {
+            let Json(params) = Json::<HashMap<String, serde_json::Value>>::from_request(req, state)
+                .await
+                .map_err(|e| (StatusCode::BAD_REQUEST, format!("bad JSON body: {e}")))?;
+
+            let params = params
+                .into_iter()
+                .map(|(k, v)| match v {
+                    serde_json::Value::String(s) => (k, s),
+                    v => (k, v.to_string()),
+                })
+                .collect();
+
+            return Ok(Self(params));
+        }
+
+        if content_type.starts_with("application/x-www-form-urlencoded") {
+            let Form(params) = Form::<HashMap<String, String>>::from_request(req, state)
+                .await
+                .map_err(|e| (StatusCode::BAD_REQUEST, format!("bad form body: {e}")))?;
+
+            return Ok(Self(params));
+        }
+
+        Err((
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            "expected a form (application/x-www-form-urlencoded) or JSON (application/json) body"
+                .to_string(),
+        ))
+    }
+}
+
+// Body q fields (form or JSON)
 // ty: service type
 // in: check interval in minutes
 // to: timeout in seconds
 // na: service name / description, only used for the frontend (empty if none given)
+// vi: visibility, one of {public, private, admin} (public if not given)
+// hl: hide the service location from guests, true or false (false if not given)
 //
 // tcp query
 // sa: socket address (host:port)
 // exre: expected response
 //       open port: op,
-//       bytes: bytes as hex
+//       bytes: bytes as hex (not supported yet)
 //         sh: sent bytes as hex, must be even
 //         ex: expected response as string of hex + ?, must be divisible by 2
 //
@@ -44,21 +133,9 @@ use crate::{
 // met: http method, must be one of {get, post, put, delete, options, head, trace, connect, patch} (GET if not given)
 // hds: header map, looks like this: content-type:application/json\naccept:*/* (empty if not given)
 // body: base64 encoded request body (empty if none given)
-pub async fn add_monitor_route(
-    q: Query<HashMap<String, String>>,
-    cookies: CookieJar,
-) -> (StatusCode, String) {
-    let is_logged_in = match cookies.get("token") {
-        None => false,
-        Some(c) => database::session::is_valid(c.value())
-            .await
-            .unwrap_or_default(),
-    };
-    if !is_logged_in {
-        return (
-            StatusCode::UNAUTHORIZED,
-            "Unauthorized (set `token` cookie to log in)".to_string(),
-        );
+pub async fn add_monitor_route(user: Auth, q: BodyParams) -> (StatusCode, String) {
+    if let Err(e) = user.require(Role::Editor, Some(Scope::WriteMonitors)) {
+        return e;
     }
 
     let Some(Ok(interval_mins)) = q.get("in").map(|i: &String| i.parse::<u16>()) else {
@@ -68,15 +145,18 @@ pub async fn add_monitor_route(
         );
     };
 
-    let Some(Ok(timeout_s)) = q.get("to").map(|to| to.parse::<u16>()) else {
-        return (
-            StatusCode::BAD_REQUEST,
-            "bad or missing param `to` (timeout)".to_string(),
-        );
+    let timeout_s = match timeout_param(&q) {
+        Ok(t) => t,
+        Err(e) => return e,
     };
 
     let service_name = q.get("na").cloned().unwrap_or_default();
 
+    let (visibility, hide_location) = match visibility_params(&q) {
+        Ok(v) => v,
+        Err(e) => return e,
+    };
+
     if !(1..=60 * 24 * 7).contains(&interval_mins)
     /* 7 days */
     {
@@ -86,74 +166,94 @@ pub async fn add_monitor_route(
         );
     }
 
-    if !(1..=60).contains(&timeout_s) {
+    let service_data = match monitor_data_params(&q) {
+        Ok(d) => d,
+        Err(e) => return e,
+    };
+
+    let id =
+        match database::monitor::add(&service_data, interval_mins, service_name, timeout_s).await {
+            Err(e) => {
+                return (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    format!("Failed to add monitor: {e}"),
+                );
+            }
+            Ok(id) => id,
+        };
+
+    if let Err(e) = database::monitor::set_visibility(id, visibility, hide_location).await {
         return (
-            StatusCode::BAD_REQUEST,
-            "bad param `to` (timeout), must be within 1..60".to_string(),
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to set the visibility of the monitor: {e}"),
         );
     }
 
-    let id = match q.get("ty").map(String::as_str) {
+    let mon = database::monitor::get_by_id(id).await.unwrap();
+    let res = mon.service_data.run(mon.timeout_secs).await;
+    database::record::util_add_result(res, id).await.unwrap();
+
+    tracing::info!("{} added monitor {id}", user.username);
+    audit::record(AuditEntry {
+        target: Some(id.to_string()),
+        after: audit::monitor_snapshot(id).await,
+        ..AuditEntry::by(&user, "monitor.add")
+    })
+    .await;
+    (StatusCode::CREATED, "Monitor was added".to_string())
+}
+
+// Service data of a monitor from the params described at add_monitor_route
+fn monitor_data_params(q: &BodyParams) -> Result<MonitorData, (StatusCode, String)> {
+    let data = match q.get("ty").map(String::as_str) {
         None => {
-            return (
+            return Err((
                 StatusCode::BAD_REQUEST,
                 "missing param `ty` (service type)".to_string(),
-            );
+            ));
         }
         Some("tcp") => {
             let Some(Ok(socket_addr)) = q.get("sa").map(|sa| SocketAddr::from_str(sa)) else {
-                return (
+                return Err((
                     StatusCode::BAD_REQUEST,
                     "bad or missing param `sa` (socket address)".to_string(),
-                );
+                ));
             };
 
             let expected_response = match q.get("exre").map(String::as_str) {
                 Some("op") => TcpExpectedResponse::OpenPort,
                 Some("bytes") => {
-                    todo!()
+                    return Err((
+                        StatusCode::BAD_REQUEST,
+                        "`exre` bytes (expected response bytes) is not supported yet".to_string(),
+                    ));
                 }
                 None => {
-                    return (
+                    return Err((
                         StatusCode::BAD_REQUEST,
                         "missing param `exre` (expected response)".to_string(),
-                    );
+                    ));
                 }
                 _ => {
-                    return (
+                    return Err((
                         StatusCode::BAD_REQUEST,
-                        "bad param `exre` (expected response), must be one of: {op, bits}"
+                        "bad param `exre` (expected response), must be one of: {op, bytes}"
                             .to_string(),
-                    );
+                    ));
                 }
             };
 
-            match database::monitor::add(
-                MonitorData::Tcp {
-                    addr: socket_addr,
-                    expected: expected_response,
-                },
-                interval_mins,
-                service_name,
-                timeout_s,
-            )
-            .await
-            {
-                Err(e) => {
-                    return (
-                        StatusCode::INTERNAL_SERVER_ERROR,
-                        format!("Failed to add monitor: {e}"),
-                    );
-                }
-                Ok(id) => id,
+            MonitorData::Tcp {
+                addr: socket_addr,
+                expected: expected_response,
             }
         }
         Some("http") => {
             let Some(Ok(url)) = q.get("url").map(|u| Url::parse(u)) else {
-                return (
+                return Err((
                     StatusCode::BAD_REQUEST,
                     "bad param `url`, failed to parse valid url".to_string(),
-                );
+                ));
             };
             let url = url.to_string();
 
@@ -161,40 +261,40 @@ pub async fn add_monitor_route(
                 Some("any") => HttpExpectedResponse::Any,
                 Some("sc") => {
                     let Some(codes) = q.get("co") else {
-                        return (
+                        return Err((
                             StatusCode::BAD_REQUEST,
                             "missing param `co` (status codes)".to_string(),
-                        );
+                        ));
                     };
                     if codes.len() > 48 {
-                        return (
+                        return Err((
                             StatusCode::BAD_REQUEST,
                             "bad param `co` (status codes), must be at most 48 characters long"
                                 .to_string(),
-                        );
+                        ));
                     }
                     if http_mon::parse_codes(codes).is_none() {
-                        return (
+                        return Err((
                             StatusCode::BAD_REQUEST,
                             "bad param `co` (status codes), failed to parse".to_string(),
-                        );
+                        ));
                     }
                     HttpExpectedResponse::StatusCode(codes.to_string())
                 }
                 Some("res") => {
                     let codes = if let Some(codes) = q.get("co") {
                         if codes.len() > 48 {
-                            return (
+                            return Err((
                                 StatusCode::BAD_REQUEST,
                                 "bad param `co` (status codes), must be at most 48 characters long"
                                     .to_string(),
-                            );
+                            ));
                         }
                         if http_mon::parse_codes(codes).is_none() {
-                            return (
+                            return Err((
                                 StatusCode::BAD_REQUEST,
                                 "bad param `co` (status codes), failed to parse".to_string(),
-                            );
+                            ));
                         }
                         Some(codes.to_string())
                     } else {
@@ -202,35 +302,35 @@ pub async fn add_monitor_route(
                     };
 
                     let Some(Ok(body_checksum)) = q.get("bch").map(|bc| bc.parse::<u32>()) else {
-                        return (
+                        return Err((
                             StatusCode::BAD_REQUEST,
                             "bad or missing param `bch` (body adler32 checksum)".to_string(),
-                        );
+                        ));
                     };
 
                     HttpExpectedResponse::Response(codes, body_checksum)
                 }
                 None => {
-                    return (
+                    return Err((
                         StatusCode::BAD_REQUEST,
                         "missing param `exre` (expected response)".to_string(),
-                    );
+                    ));
                 }
                 _ => {
-                    return (
-                        StatusCode::BAD_GATEWAY,
-                        "bad param `exre` (expected response), must be one of {res, sc}"
+                    return Err((
+                        StatusCode::BAD_REQUEST,
+                        "bad param `exre` (expected response), must be one of {any, res, sc}"
                             .to_string(),
-                    );
+                    ));
                 }
             };
 
             let method = if let Some(method) = q.get("met") {
                 let Some(method) = HttpMethod::from_str(method) else {
-                    return (
+                    return Err((
                         StatusCode::BAD_REQUEST,
                         "bad param `met`, must be one of {get, post, put, delete, options, head, trace, connect, patch}".to_string(),
-                    );
+                    ));
                 };
 
                 method
@@ -240,17 +340,17 @@ pub async fn add_monitor_route(
 
             let headers = if let Some(headers) = q.get("hds") {
                 if headers.len() > 2048 {
-                    return (
+                    return Err((
                         StatusCode::BAD_REQUEST,
                         "bad param `hds` (headers), must be at most 2048 characters long"
                             .to_string(),
-                    );
+                    ));
                 }
                 let Some(hhm) = HeaderHashMap::try_parse_str(headers) else {
-                    return (
+                    return Err((
                         StatusCode::BAD_REQUEST,
                         "bad param `hds` (headers), failed to parse".to_string(),
-                    );
+                    ));
                 };
 
                 hhm
@@ -260,125 +360,1491 @@ pub async fn add_monitor_route(
 
             let body = q.get("body").map(String::from).unwrap_or_default();
             let Ok(body) = BASE64_STANDARD.decode(body) else {
-                return (
+                return Err((
                     StatusCode::BAD_REQUEST,
                     "bad param `body`, failed to decode base64".to_string(),
-                );
+                ));
             };
 
-            match database::monitor::add(
-                MonitorData::Http {
-                    url,
-                    expected: expected_response,
-                    request: HttpRequest {
-                        method,
-                        headers,
-                        body,
-                    },
+            MonitorData::Http {
+                url,
+                expected: expected_response,
+                request: HttpRequest {
+                    method,
+                    headers,
+                    body,
                 },
-                interval_mins,
-                service_name,
-                timeout_s,
-            )
-            .await
-            {
-                Err(e) => {
-                    return (
-                        StatusCode::INTERNAL_SERVER_ERROR,
-                        format!("Failed to add monitor: {e}"),
-                    );
-                }
-                Ok(id) => id,
             }
         }
         _ => {
-            return (
+            return Err((
                 StatusCode::BAD_REQUEST,
                 "bad param `ty` (service type), must be one of: {tcp, http}".to_string(),
-            );
+            ));
         }
     };
 
-    let mon = database::monitor::get_by_id(id).await.unwrap();
-    let res = mon.service_data.run(mon.timeout_secs).await;
-    database::record::util_add_result(res, id).await.unwrap();
+    Ok(data)
+}
 
-    (StatusCode::CREATED, "Monitor was added".to_string())
+fn timeout_param(q: &BodyParams) -> Result<u16, (StatusCode, String)> {
+    let Some(Ok(timeout_s)) = q.get("to").map(|to| to.parse::<u16>()) else {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "bad or missing param `to` (timeout)".to_string(),
+        ));
+    };
+
+    if !(1..=60).contains(&timeout_s) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "bad param `to` (timeout), must be within 1..60".to_string(),
+        ));
+    }
+
+    Ok(timeout_s)
+}
+
+// Runs a check with the params of add_monitor_route without storing anything (`in` is not needed),
+// returns the result with what the service responded
+pub async fn test_monitor_route(
+    user: Auth,
+    q: BodyParams,
+) -> Result<Json<TestResult>, (StatusCode, String)> {
+    user.require(Role::Editor, Some(Scope::WriteMonitors))?;
+
+    let timeout_s = timeout_param(&q)?;
+    let service_data = monitor_data_params(&q)?;
+
+    tracing::info!(
+        "{} tested a monitor for {}",
+        user.username,
+        service_data.service_location_str()
+    );
+    Ok(Json(service_data.test(timeout_s).await))
 }
 
-pub async fn create_session_route(q: Query<HashMap<String, String>>) -> (StatusCode, String) {
+// Response of create_session_route if the password is correct but a TOTP code is missing
+pub static TOTP_REQUIRED: &str = "TOTP code required";
+
+// Body q fields (form or JSON)
+// un: username (log in with the password from the config if not given)
+// pw: password
+// otp: TOTP or recovery code, only if two-factor authentication is enabled
+//      (responds with 401 and TOTP_REQUIRED if it is missing)
+pub async fn create_session_route(
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    q: BodyParams,
+) -> Result<(HeaderMap, String), (StatusCode, String)> {
+    let password_login_disabled = CONFIG
+        .get()
+        .unwrap()
+        .lock()
+        .await
+        .oidc
+        .as_ref()
+        .is_some_and(|o| o.disable_password_login);
+    if password_login_disabled {
+        return Err((
+            StatusCode::FORBIDDEN,
+            "Password login is disabled, use single sign-on".to_string(),
+        ));
+    }
+
     let Some(password) = q.get("pw") else {
-        return (
+        return Err((
             StatusCode::BAD_REQUEST,
             "missing param `pw` (password)".to_string(),
-        );
+        ));
     };
 
-    if !CONFIG.get().unwrap().lock().await.check_password(password) {
-        return (StatusCode::UNAUTHORIZED, "wrong password".to_string());
+    let ip = auth::client_ip(addr, &headers).await;
+    if let Some(secs) = login_throttle::check(ip).await {
+        return Err((
+            StatusCode::TOO_MANY_REQUESTS,
+            format!("Too many failed login attempts, try again in {secs} seconds"),
+        ));
+    }
+
+    let otp = q.get("otp").filter(|otp| !otp.is_empty());
+    let user_id = match q.get("un").filter(|un| !un.is_empty()) {
+        Some(username) => match database::user::check_password(username, password).await {
+            Ok(Some(user)) if !user.totp_enabled => Some(user.id),
+            Ok(Some(user)) => {
+                let Some(otp) = otp else {
+                    return Err((StatusCode::UNAUTHORIZED, TOTP_REQUIRED.to_string()));
+                };
+                match database::user::check_second_factor(user.id, otp).await {
+                    Ok(true) => Some(user.id),
+                    Ok(false) => {
+                        tracing::warn!("Failed login as {username} from {ip} (wrong TOTP code)");
+                        audit_login_failure(username, ip, "wrong TOTP code").await;
+                        login_throttle::record_failure(ip).await;
+                        return Err((
+                            StatusCode::UNAUTHORIZED,
+                            "wrong TOTP or recovery code".to_string(),
+                        ));
+                    }
+                    Err(e) => {
+                        return Err((
+                            StatusCode::INTERNAL_SERVER_ERROR,
+                            format!("Failed to check TOTP code: {e}"),
+                        ));
+                    }
+                }
+            }
+            Ok(None) => {
+                tracing::warn!("Failed login as {username} from {ip}");
+                audit_login_failure(username, ip, "wrong username or password").await;
+                login_throttle::record_failure(ip).await;
+                return Err((
+                    StatusCode::UNAUTHORIZED,
+                    "wrong username or password".to_string(),
+                ));
+            }
+            Err(e) => {
+                return Err((
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    format!("Failed to check password: {e}"),
+                ));
+            }
+        },
+        None => {
+            if !CONFIG.get().unwrap().lock().await.check_password(password) {
+                tracing::warn!("Failed login with the config password from {ip}");
+                audit_login_failure(CONFIG_ACTOR, ip, "wrong password").await;
+                login_throttle::record_failure(ip).await;
+                return Err((StatusCode::UNAUTHORIZED, "wrong password".to_string()));
+            };
+            match otp {
+                _ if CONFIG
+                    .get()
+                    .unwrap()
+                    .lock()
+                    .await
+                    .login
+                    .totp_secret
+                    .is_none() => {}
+                None => return Err((StatusCode::UNAUTHORIZED, TOTP_REQUIRED.to_string())),
+                Some(otp) => {
+                    if totp::verify_config(otp).await != Some(true) {
+                        tracing::warn!(
+                            "Failed login with the config password from {ip} (wrong TOTP code)"
+                        );
+                        audit_login_failure(CONFIG_ACTOR, ip, "wrong TOTP code").await;
+                        login_throttle::record_failure(ip).await;
+                        return Err((StatusCode::UNAUTHORIZED, "wrong TOTP code".to_string()));
+                    }
+                }
+            }
+            None
+        }
     };
 
-    let token = match database::session::create().await {
+    login_throttle::record_success(ip).await;
+
+    let user_agent = headers.get(USER_AGENT).and_then(|ua| ua.to_str().ok());
+    let token = match database::session::create(user_id, &ip.to_string(), user_agent).await {
         Ok(token) => token,
         Err(e) => {
-            return (
+            return Err((
                 StatusCode::INTERNAL_SERVER_ERROR,
                 format!("Failed to add session to database: {e}"),
-            );
+            ));
         }
     };
 
-    (StatusCode::OK, token)
+    let actor = q.get("un").filter(|un| !un.is_empty()).map(String::as_str);
+    audit_login_success(actor.unwrap_or(CONFIG_ACTOR), user_id, ip, "password").await;
+
+    Ok((
+        HeaderMap::from_iter([(SET_COOKIE, session_cookie(&token).await)]),
+        "Logged in".to_string(),
+    ))
 }
 
-pub async fn delete_monitor_route(id: Path<u64>, cookies: CookieJar) -> (StatusCode, String) {
-    let is_logged_in = match cookies.get("token") {
-        None => false,
-        Some(c) => database::session::is_valid(c.value())
-            .await
-            .unwrap_or_default(),
+// Actor of logins with the password from the config
+static CONFIG_ACTOR: &str = "admin (config)";
+
+async fn audit_login_success(username: &str, user_id: Option<u64>, ip: IpAddr, method: &str) {
+    for action in ["login.success", "session.create"] {
+        audit::record(AuditEntry {
+            actor: username.to_string(),
+            user_id,
+            ip: Some(ip.to_string()),
+            action,
+            details: Some(format!("via {method}")),
+            ..Default::default()
+        })
+        .await;
+    }
+}
+
+async fn audit_login_failure(username: &str, ip: IpAddr, reason: &str) {
+    audit::record(AuditEntry {
+        actor: username.to_string(),
+        ip: Some(ip.to_string()),
+        action: "login.failed",
+        details: Some(reason.to_string()),
+        ..Default::default()
+    })
+    .await;
+}
+
+async fn session_cookie(token: &str) -> HeaderValue {
+    let secure = CONFIG.get().unwrap().lock().await.login.secure_cookie;
+    let cookie = format!(
+        "token={token}; Max-Age={}; Path=/; HttpOnly; SameSite=Strict{}",
+        database::session::SESSION_LIFETIME_SECS,
+        if secure { "; Secure" } else { "" }
+    );
+
+    HeaderValue::from_str(&cookie).unwrap()
+}
+
+// Redirects to the identity provider
+pub async fn oidc_login_route() -> Result<(HeaderMap, Redirect), (StatusCode, String)> {
+    let (url, state) = match oidc::begin_login().await {
+        Ok(l) => l,
+        Err(e) => {
+            tracing::error!("Failed to start single sign-on: {e:#}");
+            return Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to start single sign-on: {e}"),
+            ));
+        }
     };
-    if !is_logged_in {
-        return (
+
+    // Lax, the cookie has to be sent when the identity provider redirects back
+    let secure = CONFIG.get().unwrap().lock().await.login.secure_cookie;
+    let cookie = format!(
+        "oidc_state={state}; Max-Age=600; Path=/api/oidc; HttpOnly; SameSite=Lax{}",
+        if secure { "; Secure" } else { "" }
+    );
+
+    Ok((
+        HeaderMap::from_iter([(SET_COOKIE, HeaderValue::from_str(&cookie).unwrap())]),
+        Redirect::to(&url),
+    ))
+}
+
+// Query q fields (set by the identity provider)
+// code: authorization code
+// state: must match the `oidc_state` cookie
+// error, error_description: set instead of code if the login failed
+pub async fn oidc_callback_route(
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    cookies: CookieJar,
+    q: Query<HashMap<String, String>>,
+) -> Result<(HeaderMap, Html<&'static str>), (StatusCode, String)> {
+    if let Some(error) = q.get("error") {
+        return Err((
             StatusCode::UNAUTHORIZED,
-            "Unauthorized (set `token` cookie to log in)".to_string(),
+            format!(
+                "Single sign-on failed: {error} {}",
+                q.get("error_description").cloned().unwrap_or_default()
+            ),
+        ));
+    }
+
+    let (Some(code), Some(state)) = (q.get("code"), q.get("state")) else {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "missing param `code` or `state`".to_string(),
+        ));
+    };
+    // the login has to be finished in the browser it was started in
+    if cookies.get("oidc_state").map(|c| c.value()) != Some(state.as_str()) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "Login was started in another browser, please try again".to_string(),
+        ));
+    }
+
+    let ip = auth::client_ip(addr, &headers).await;
+    let user = match oidc::finish_login(code, state).await {
+        Ok(user) => user,
+        Err(e) => {
+            tracing::warn!("Failed single sign-on from {ip}: {e:#}");
+            audit_login_failure("(single sign-on)", ip, &format!("{e:#}")).await;
+            return Err((
+                StatusCode::UNAUTHORIZED,
+                format!("Single sign-on failed: {e}"),
+            ));
+        }
+    };
+
+    let user_id = match database::user::upsert_sso(&user.subject, &user.username, user.role).await {
+        Ok(id) => id,
+        Err(e) => {
+            return Err((
+                StatusCode::CONFLICT,
+                format!("Failed to log in {}: {e}", user.username),
+            ));
+        }
+    };
+
+    let user_agent = headers.get(USER_AGENT).and_then(|ua| ua.to_str().ok());
+    let token = match database::session::create(Some(user_id), &ip.to_string(), user_agent).await {
+        Ok(token) => token,
+        Err(e) => {
+            return Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to add session to database: {e}"),
+            ));
+        }
+    };
+
+    tracing::info!(
+        "{} logged in with single sign-on as {} from {ip}",
+        user.username,
+        user.role.as_str()
+    );
+    audit_login_success(&user.username, Some(user_id), ip, "single sign-on").await;
+
+    // not a redirect, browsers would not send the SameSite=Strict cookie when following a
+    // redirect that started at the identity provider
+    let mut headers = HeaderMap::from_iter([(SET_COOKIE, session_cookie(&token).await)]);
+    headers.append(
+        SET_COOKIE,
+        HeaderValue::from_static("oidc_state=; Max-Age=0; Path=/api/oidc; HttpOnly; SameSite=Lax"),
+    );
+    Ok((
+        headers,
+        Html(
+            r#"<!DOCTYPE html><meta http-equiv="refresh" content="0; url=/"><a href="/">Continue</a>"#,
+        ),
+    ))
+}
+
+// Deletes the session of the `token` cookie
+pub async fn logout_route(user: Option<Auth>) -> (StatusCode, HeaderMap, String) {
+    let removal = HeaderMap::from_iter([(
+        SET_COOKIE,
+        HeaderValue::from_static("token=; Max-Age=0; Path=/; HttpOnly; SameSite=Strict"),
+    )]);
+
+    let Some(session_id) = user.and_then(|u| u.session_id()) else {
+        return (StatusCode::OK, removal, "Not logged in".to_string());
+    };
+
+    if let Err(e) = database::session::util_delete(session_id).await {
+        return (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            HeaderMap::new(),
+            format!("Failed to delete session: {e}"),
         );
+    }
+
+    (StatusCode::OK, removal, "Logged out".to_string())
+}
+
+// Admins can revoke all sessions, everyone else only their own ones
+pub async fn revoke_session_route(id: Path<u64>, user: Auth) -> (StatusCode, String) {
+    if let Err(e) = user.require(Role::Viewer, None) {
+        return e;
+    }
+
+    let Some(session) = database::session::get_by_id(*id).await else {
+        return (StatusCode::NOT_FOUND, "No such session".to_string());
     };
+    if user.role < Role::Admin && session.user_id != user.user_id {
+        return (
+            StatusCode::FORBIDDEN,
+            "Forbidden (requires role admin to revoke sessions of other users)".to_string(),
+        );
+    }
 
-    if let Err(e) = database::monitor::util_delete(*id).await {
+    if let Err(e) = database::session::util_delete(*id).await {
         return (
             StatusCode::INTERNAL_SERVER_ERROR,
-            format!("Failed to remove monitor: {e}"),
+            format!("Failed to revoke session: {e}"),
         );
     }
 
-    (StatusCode::OK, "Monitor was deleted".to_string())
+    tracing::info!(
+        "{} revoked session {} of {}",
+        user.username,
+        *id,
+        session.username
+    );
+    (StatusCode::OK, "Session was revoked".to_string())
 }
 
-pub async fn toggle_monitor(id: Path<u64>, cookies: CookieJar) -> (StatusCode, String) {
-    let is_logged_in = match cookies.get("token") {
-        None => false,
-        Some(c) => database::session::is_valid(c.value())
-            .await
-            .unwrap_or_default(),
+// Body q fields (form or JSON)
+// na: name of the token, must be 1 to 64 characters long
+// sc: comma separated scopes, out of {read, write_monitors, push}
+// ex (opt): days until the token expires, must be within 1..3650 (never expires if not given)
+//
+// Responds with the token, it can not be retrieved later
+pub async fn add_api_token_route(user: Auth, q: BodyParams) -> (StatusCode, String) {
+    if let Err(e) = user.require(Role::Viewer, None) {
+        return e;
+    }
+
+    let Some(name) = q
+        .get("na")
+        .map(|na| na.trim())
+        .filter(|na| (1..=64).contains(&na.len()))
+    else {
+        return (
+            StatusCode::BAD_REQUEST,
+            "bad or missing param `na` (name), must be 1 to 64 characters long".to_string(),
+        );
     };
-    if !is_logged_in {
+
+    let Some(scopes) = q
+        .get("sc")
+        .and_then(|sc| database::api_token::parse_scopes(sc))
+        .filter(|sc| !sc.is_empty())
+    else {
         return (
-            StatusCode::UNAUTHORIZED,
-            "Unauthorized (set `token` cookie to log in)".to_string(),
+            StatusCode::BAD_REQUEST,
+            "bad or missing param `sc` (scopes), must be a comma separated list out of {read, write_monitors, push}".to_string(),
         );
     };
 
-    let new_status = match database::monitor::toggle(*id).await {
-        Ok(true) => "enabled",
-        Ok(false) => "disabled",
-        Err(e) => {
-            return (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                format!("Failed to toggle monitor: {e}"),
-            );
-        }
+    let expires_at = match q.get("ex").filter(|ex| !ex.is_empty()) {
+        None => None,
+        Some(ex) => match ex.parse::<u64>() {
+            Ok(days) if (1..=3650).contains(&days) => {
+                Some(current_unix_time() + days * 60 * 60 * 24)
+            }
+            _ => {
+                return (
+                    StatusCode::BAD_REQUEST,
+                    "bad param `ex` (days until expiry), must be within 1..3650".to_string(),
+                );
+            }
+        },
     };
 
-    (StatusCode::OK, format!("Monitor is now {new_status}"))
+    let (id, token) =
+        match database::api_token::create(name, user.user_id, &scopes, expires_at).await {
+            Ok(t) => t,
+            Err(e) => {
+                return (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    format!("Failed to add API token: {e}"),
+                );
+            }
+        };
+
+    tracing::info!("{} added API token {id} ({name})", user.username);
+    (StatusCode::CREATED, token)
+}
+
+// Admins can revoke all API tokens, everyone else only their own ones
+pub async fn revoke_api_token_route(id: Path<u64>, user: Auth) -> (StatusCode, String) {
+    if let Err(e) = user.require(Role::Viewer, None) {
+        return e;
+    }
+
+    let Some(token) = database::api_token::get_by_id(*id).await else {
+        return (StatusCode::NOT_FOUND, "No such API token".to_string());
+    };
+    if user.role < Role::Admin && token.user_id != user.user_id {
+        return (
+            StatusCode::FORBIDDEN,
+            "Forbidden (requires role admin to revoke API tokens of other users)".to_string(),
+        );
+    }
+
+    if let Err(e) = database::api_token::util_delete(*id).await {
+        return (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to revoke API token: {e}"),
+        );
+    }
+
+    tracing::info!(
+        "{} revoked API token {} ({}) of {}",
+        user.username,
+        *id,
+        token.name,
+        token.username
+    );
+    (StatusCode::OK, "API token was revoked".to_string())
+}
+
+// Starts setting up two-factor authentication for the logged in user, the account page shows the
+// QR code afterwards
+pub async fn begin_totp_route(user: Auth) -> (StatusCode, String) {
+    if let Err(e) = user.require(Role::Viewer, None) {
+        return e;
+    }
+    let Some(user_id) = user.user_id else {
+        return (
+            StatusCode::BAD_REQUEST,
+            "Set `login.totp_secret` in the config to use two-factor authentication with the config password".to_string(),
+        );
+    };
+
+    if let Err(e) = database::user::begin_totp(user_id).await {
+        return (
+            StatusCode::BAD_REQUEST,
+            format!("Failed to set up two-factor authentication: {e}"),
+        );
+    }
+
+    (
+        StatusCode::CREATED,
+        "Scan the QR code and enter a code to finish the setup".to_string(),
+    )
+}
+
+// Body q fields (form or JSON)
+// otp: code from the authenticator app
+//
+// Responds with the recovery codes, one per line
+pub async fn enable_totp_route(user: Auth, q: BodyParams) -> (StatusCode, String) {
+    if let Err(e) = user.require(Role::Viewer, None) {
+        return e;
+    }
+    let Some(user_id) = user.user_id else {
+        return (StatusCode::BAD_REQUEST, "Not a user account".to_string());
+    };
+
+    let Some(otp) = q.get("otp") else {
+        return (
+            StatusCode::BAD_REQUEST,
+            "missing param `otp` (TOTP code)".to_string(),
+        );
+    };
+
+    match database::user::enable_totp(user_id, otp).await {
+        Ok(Some(recovery_codes)) => {
+            tracing::info!("{} enabled two-factor authentication", user.username);
+            (StatusCode::OK, recovery_codes.join("\n"))
+        }
+        Ok(None) => (
+            StatusCode::BAD_REQUEST,
+            "wrong TOTP code, check the time of your device".to_string(),
+        ),
+        Err(e) => (
+            StatusCode::BAD_REQUEST,
+            format!("Failed to enable two-factor authentication: {e}"),
+        ),
+    }
+}
+
+// Body q fields (form or JSON)
+// otp: TOTP or recovery code
+pub async fn disable_totp_route(user: Auth, q: BodyParams) -> (StatusCode, String) {
+    if let Err(e) = user.require(Role::Viewer, None) {
+        return e;
+    }
+    let Some(user_id) = user.user_id else {
+        return (StatusCode::BAD_REQUEST, "Not a user account".to_string());
+    };
+
+    let Some(otp) = q.get("otp") else {
+        return (
+            StatusCode::BAD_REQUEST,
+            "missing param `otp` (TOTP or recovery code)".to_string(),
+        );
+    };
+
+    match database::user::check_second_factor(user_id, otp).await {
+        Ok(true) => {}
+        Ok(false) => {
+            return (
+                StatusCode::FORBIDDEN,
+                "wrong TOTP or recovery code".to_string(),
+            );
+        }
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to check TOTP code: {e}"),
+            );
+        }
+    }
+
+    if let Err(e) = database::user::disable_totp(user_id).await {
+        return (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to disable two-factor authentication: {e}"),
+        );
+    }
+
+    tracing::info!("{} disabled two-factor authentication", user.username);
+    (
+        StatusCode::OK,
+        "Two-factor authentication was disabled".to_string(),
+    )
+}
+
+// For users that lost their authenticator and recovery codes
+pub async fn reset_user_totp_route(id: Path<u64>, user: Auth) -> (StatusCode, String) {
+    if let Err(e) = user.require(Role::Admin, None) {
+        return e;
+    }
+
+    if let Err(e) = database::user::disable_totp(*id).await {
+        return (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to reset two-factor authentication: {e}"),
+        );
+    }
+
+    tracing::info!(
+        "{} reset the two-factor authentication of user {}",
+        user.username,
+        *id
+    );
+    (
+        StatusCode::OK,
+        "Two-factor authentication of the user was reset".to_string(),
+    )
+}
+
+// Monitors defined in the config can only be changed there
+async fn check_not_config_managed(id: u64) -> Result<(), (StatusCode, String)> {
+    let managed = database::monitor::get_by_id(id)
+        .await
+        .and_then(|m| m.config_key);
+    match managed {
+        Some(key) => Err((
+            StatusCode::CONFLICT,
+            format!("Monitor is managed by the config (key `{key}`), change it there"),
+        )),
+        None => Ok(()),
+    }
+}
+
+pub async fn delete_monitor_route(id: Path<u64>, user: Auth) -> (StatusCode, String) {
+    if let Err(e) = user.require(Role::Editor, Some(Scope::WriteMonitors)) {
+        return e;
+    }
+
+    if let Err(e) = check_not_config_managed(*id).await {
+        return e;
+    }
+
+    let before = audit::monitor_snapshot(*id).await;
+    if let Err(e) = database::monitor::util_delete(*id).await {
+        return (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to remove monitor: {e}"),
+        );
+    }
+
+    tracing::info!("{} deleted monitor {}", user.username, *id);
+    audit::record(AuditEntry {
+        target: Some(id.to_string()),
+        before,
+        ..AuditEntry::by(&user, "monitor.delete")
+    })
+    .await;
+    (StatusCode::OK, "Monitor was deleted".to_string())
+}
+
+pub async fn toggle_monitor(id: Path<u64>, user: Auth) -> (StatusCode, String) {
+    if let Err(e) = user.require(Role::Editor, Some(Scope::WriteMonitors)) {
+        return e;
+    }
+
+    if let Err(e) = check_not_config_managed(*id).await {
+        return e;
+    }
+
+    let before = audit::monitor_snapshot(*id).await;
+    let new_status = match database::monitor::toggle(*id).await {
+        Ok(true) => "enabled",
+        Ok(false) => "disabled",
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to toggle monitor: {e}"),
+            );
+        }
+    };
+
+    tracing::info!("{} {new_status} monitor {}", user.username, *id);
+    audit::record(AuditEntry {
+        target: Some(id.to_string()),
+        details: Some(new_status.to_string()),
+        before,
+        after: audit::monitor_snapshot(*id).await,
+        ..AuditEntry::by(&user, "monitor.toggle")
+    })
+    .await;
+    (StatusCode::OK, format!("Monitor is now {new_status}"))
+}
+
+// Runs a check right away instead of waiting for the interval, returns the new record
+pub async fn check_monitor_route(
+    id: Path<u64>,
+    user: Auth,
+) -> Result<Json<MonitorRecord>, (StatusCode, String)> {
+    user.require(Role::Editor, Some(Scope::WriteMonitors))?;
+
+    let Some(mon) = database::monitor::get_by_id(*id)
+        .await
+        .filter(|m| m.is_visible_to(Some(user.role)))
+    else {
+        return Err((StatusCode::NOT_FOUND, "No such monitor".to_string()));
+    };
+    if !mon.enabled {
+        return Err((
+            StatusCode::CONFLICT,
+            "Monitor is disabled, enable it first".to_string(),
+        ));
+    }
+
+    let res = mon.service_data.run(mon.timeout_secs).await;
+    match database::record::util_add_result(res, *id).await {
+        Ok(record) => {
+            tracing::info!("{} checked monitor {}", user.username, *id);
+            Ok(Json(record))
+        }
+        Err(e) => Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to store the result: {e}"),
+        )),
+    }
+}
+
+// Checks all enabled monitors whose last result is not up at once, returns their new records
+pub async fn check_down_monitors_route(
+    user: Auth,
+) -> Result<Json<Vec<MonitorRecord>>, (StatusCode, String)> {
+    user.require(Role::Editor, Some(Scope::WriteMonitors))?;
+
+    let mons = database::monitor::get_all(true).await.map_err(|e| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to get monitors: {e}"),
+        )
+    })?;
+
+    let mut checks = JoinSet::new();
+    for (id, mon) in mons {
+        if !mon.is_visible_to(Some(user.role)) {
+            continue;
+        }
+        let is_up = database::record::util_last_record(id)
+            .await
+            .is_ok_and(|r| r.result == RecordResult::Ok);
+        if is_up {
+            continue;
+        }
+
+        checks.spawn(async move {
+            let res = mon.service_data.run(mon.timeout_secs).await;
+            database::record::util_add_result(res, id).await
+        });
+    }
+
+    let mut records = vec![];
+    while let Some(res) = checks.join_next().await {
+        match res {
+            Ok(Ok(record)) => records.push(record),
+            Ok(Err(e)) => tracing::error!("Failed to store the result of a check: {e}"),
+            Err(e) => tracing::error!("Check failed: {e}"),
+        }
+    }
+    records.sort_by_key(|r| r.monitor_id);
+
+    tracing::info!(
+        "{} checked {} monitors that were not up",
+        user.username,
+        records.len()
+    );
+    Ok(Json(records))
+}
+
+fn visibility_params(q: &BodyParams) -> Result<(Visibility, bool), (StatusCode, String)> {
+    let visibility = match q.get("vi") {
+        Some(vi) => Visibility::from_str(vi).ok_or((
+            StatusCode::BAD_REQUEST,
+            "bad param `vi` (visibility), must be one of {public, private, admin}".to_string(),
+        ))?,
+        None => Visibility::default(),
+    };
+
+    let hide_location = match q.get("hl") {
+        Some(hl) => hl.parse::<bool>().map_err(|_| {
+            (
+                StatusCode::BAD_REQUEST,
+                "bad param `hl` (hide location), must be true or false".to_string(),
+            )
+        })?,
+        None => false,
+    };
+
+    Ok((visibility, hide_location))
+}
+
+// Body q fields (form or JSON)
+// vi: visibility, one of {public, private, admin} (public if not given)
+// hl: hide the service location from guests, true or false (false if not given)
+pub async fn set_monitor_visibility_route(
+    id: Path<u64>,
+    user: Auth,
+    q: BodyParams,
+) -> (StatusCode, String) {
+    if let Err(e) = user.require(Role::Editor, Some(Scope::WriteMonitors)) {
+        return e;
+    }
+
+    if let Err(e) = check_not_config_managed(*id).await {
+        return e;
+    }
+
+    let (visibility, hide_location) = match visibility_params(&q) {
+        Ok(v) => v,
+        Err(e) => return e,
+    };
+
+    let before = audit::monitor_snapshot(*id).await;
+    if let Err(e) = database::monitor::set_visibility(*id, visibility, hide_location).await {
+        return (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to set the visibility of the monitor: {e}"),
+        );
+    }
+
+    tracing::info!(
+        "{} set the visibility of monitor {} to {}",
+        user.username,
+        *id,
+        visibility.as_str()
+    );
+    audit::record(AuditEntry {
+        target: Some(id.to_string()),
+        details: Some(visibility.as_str().to_string()),
+        before,
+        after: audit::monitor_snapshot(*id).await,
+        ..AuditEntry::by(&user, "monitor.visibility")
+    })
+    .await;
+    (StatusCode::OK, "Visibility was changed".to_string())
+}
+
+// Replaces the secret request fields of an HTTP monitor, they are never sent back
+// Body q fields (form or JSON)
+// hds: header map like in add_monitor_route (kept if not given, removes all headers if empty)
+// body: base64 encoded request body (kept if not given, removes the body if empty)
+pub async fn edit_monitor_request_route(
+    id: Path<u64>,
+    user: Auth,
+    q: BodyParams,
+) -> (StatusCode, String) {
+    if let Err(e) = user.require(Role::Editor, Some(Scope::WriteMonitors)) {
+        return e;
+    }
+
+    if let Err(e) = check_not_config_managed(*id).await {
+        return e;
+    }
+
+    let Some(mut mon) = database::monitor::get_by_id(*id).await else {
+        return (StatusCode::NOT_FOUND, "No such monitor".to_string());
+    };
+    let before = serde_json::to_value(&mon).ok();
+
+    let MonitorData::Http { request, .. } = &mut mon.service_data else {
+        return (
+            StatusCode::BAD_REQUEST,
+            "Only HTTP monitors have request headers and a body".to_string(),
+        );
+    };
+
+    let mut changed = vec![];
+    if let Some(headers) = q.get("hds") {
+        if headers.len() > 2048 {
+            return (
+                StatusCode::BAD_REQUEST,
+                "bad param `hds` (headers), must be at most 2048 characters long".to_string(),
+            );
+        }
+        let Some(hhm) = HeaderHashMap::try_parse_str(headers) else {
+            return (
+                StatusCode::BAD_REQUEST,
+                "bad param `hds` (headers), failed to parse".to_string(),
+            );
+        };
+
+        request.headers = hhm;
+        changed.push("headers");
+    }
+
+    if let Some(body) = q.get("body") {
+        let Ok(body) = BASE64_STANDARD.decode(body) else {
+            return (
+                StatusCode::BAD_REQUEST,
+                "bad param `body`, failed to decode base64".to_string(),
+            );
+        };
+
+        request.body = body;
+        changed.push("body");
+    }
+
+    if changed.is_empty() {
+        return (
+            StatusCode::BAD_REQUEST,
+            "missing param `hds` (headers) or `body`".to_string(),
+        );
+    }
+
+    if let Err(e) = database::monitor::update(*id, &mon).await {
+        return (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to update monitor: {e}"),
+        );
+    }
+
+    tracing::info!(
+        "{} replaced the request {} of monitor {}",
+        user.username,
+        changed.join(" and "),
+        *id
+    );
+    audit::record(AuditEntry {
+        target: Some(id.to_string()),
+        details: Some(format!("replaced {}", changed.join(" and "))),
+        before,
+        after: serde_json::to_value(&mon).ok(),
+        ..AuditEntry::by(&user, "monitor.edit")
+    })
+    .await;
+    (StatusCode::OK, "Monitor was updated".to_string())
+}
+
+// Body q fields (form or JSON)
+// cm: comment / postmortem of the incident (removes the comment if empty)
+pub async fn comment_incident_route(
+    id: Path<u64>,
+    user: Auth,
+    q: BodyParams,
+) -> (StatusCode, String) {
+    if let Err(e) = user.require(Role::Editor, Some(Scope::WriteMonitors)) {
+        return e;
+    }
+
+    let Some(comment) = q.get("cm") else {
+        return (
+            StatusCode::BAD_REQUEST,
+            "missing param `cm` (comment)".to_string(),
+        );
+    };
+
+    if comment.len() > 4096 {
+        return (
+            StatusCode::BAD_REQUEST,
+            "bad param `cm` (comment), must be at most 4096 characters long".to_string(),
+        );
+    }
+
+    let comment = Some(comment.trim().to_string()).filter(|c| !c.is_empty());
+    match database::incident::set_comment(*id, comment).await {
+        Ok(true) => {
+            tracing::info!("{} updated the comment of incident {}", user.username, *id);
+            (StatusCode::OK, "Incident comment was updated".to_string())
+        }
+        Ok(false) => (StatusCode::NOT_FOUND, "No such incident".to_string()),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to update incident: {e}"),
+        ),
+    }
+}
+
+pub async fn toggle_incident_published(id: Path<u64>, user: Auth) -> (StatusCode, String) {
+    if let Err(e) = user.require(Role::Editor, Some(Scope::WriteMonitors)) {
+        return e;
+    }
+
+    let new_status = match database::incident::toggle_published(*id).await {
+        Ok(Some(true)) => "published",
+        Ok(Some(false)) => "unpublished",
+        Ok(None) => return (StatusCode::NOT_FOUND, "No such incident".to_string()),
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to toggle incident: {e}"),
+            );
+        }
+    };
+
+    tracing::info!("{} {new_status} incident {}", user.username, *id);
+    (StatusCode::OK, format!("Incident is now {new_status}"))
+}
+
+// Query q fields
+// month: month of the report as YYYY-MM (current month if not given)
+pub async fn sla_route(
+    q: Query<HashMap<String, String>>,
+    user: Option<Auth>,
+) -> Result<Json<Vec<SlaReport>>, (StatusCode, String)> {
+    // treated as a guest without the read scope
+    let role = user
+        .filter(|u| u.require(Role::Viewer, Some(Scope::Read)).is_ok())
+        .map(|u| u.role);
+    let is_logged_in = role.is_some();
+    let (allow_guest, target) = {
+        let config = CONFIG.get().unwrap().lock().await;
+        (config.allow_guest, config.sla_target)
+    };
+    if !allow_guest && !is_logged_in {
+        return Err((
+            StatusCode::UNAUTHORIZED,
+            "Unauthorized (set `token` cookie to log in or send an API token with scope read)"
+                .to_string(),
+        ));
+    }
+
+    let month = q.get("month").cloned().unwrap_or_else(sla::current_month);
+    if sla::parse_month(&month).is_none() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "bad param `month`, must look like YYYY-MM".to_string(),
+        ));
+    }
+
+    match sla::monthly_reports(&month, target, role).await {
+        Ok(reports) => Ok(Json(reports)),
+        Err(e) => Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to create SLA report: {e}"),
+        )),
+    }
+}
+
+// How often events_route checks that the session or API token is still valid
+static EVENTS_AUTH_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+// Server-sent events with the results of checks as the checker produces them
+// event record: the new record as JSON, like in the monitor records
+// event state: {"monitor_id", "from", "to", "incident"} if the result differs from the previous
+// check, `incident` is the incident the check opened, extended or closed (or null)
+// event lagged: events were dropped because the client was too slow, it should reload
+// The credentials are checked again every EVENTS_AUTH_CHECK_INTERVAL, the stream ends once the
+// session or API token was revoked or the role changed
+pub async fn events_route(
+    user: Option<Auth>,
+    headers: HeaderMap,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, (StatusCode, String)> {
+    let role = events_role(user, &headers).await?;
+
+    let events = BroadcastStream::new(live::subscribe()).filter_map(move |e| match e {
+        Ok((visibility, hide_location, event)) if visibility.allows(role) => {
+            // like Monitor::shows_location_to
+            let event = if hide_location && role.is_none() {
+                event.without_info()
+            } else {
+                event
+            };
+            Event::default()
+                .event(event.name())
+                .json_data(&event)
+                .ok()
+                .map(Ok)
+        }
+        Ok(_) => None,
+        Err(BroadcastStreamRecvError::Lagged(n)) => {
+            Some(Ok(Event::default().event("lagged").data(n.to_string())))
+        }
+    });
+
+    let start = tokio::time::Instant::now() + EVENTS_AUTH_CHECK_INTERVAL;
+    let auth_checks =
+        IntervalStream::new(tokio::time::interval_at(start, EVENTS_AUTH_CHECK_INTERVAL))
+            .then(move |_| {
+                let headers = headers.clone();
+                async move { events_role(None, &headers).await.ok() == Some(role) }
+            })
+            .filter(|still_allowed| !still_allowed);
+
+    // None ends the stream
+    let events = events
+        .map(Some)
+        .merge(auth_checks.map(|_| None))
+        .map_while(|e| e);
+
+    Ok(Sse::new(events).keep_alive(KeepAlive::default()))
+}
+
+// Role the events are filtered for (None for guests), the credentials of `headers` are checked if
+// `user` is not given
+async fn events_role(
+    user: Option<Auth>,
+    headers: &HeaderMap,
+) -> Result<Option<Role>, (StatusCode, String)> {
+    let user = match user {
+        Some(user) => Some(user),
+        None => Auth::from_headers(headers, None).await.ok(),
+    };
+    // treated as a guest without the read scope
+    let role = user
+        .filter(|u| u.require(Role::Viewer, Some(Scope::Read)).is_ok())
+        .map(|u| u.role);
+    let allow_guest = CONFIG.get().unwrap().lock().await.allow_guest;
+    if !allow_guest && role.is_none() {
+        return Err((
+            StatusCode::UNAUTHORIZED,
+            "Unauthorized (set `token` cookie to log in or send an API token with scope read)"
+                .to_string(),
+        ));
+    }
+
+    Ok(role)
+}
+
+// Header values and request bodies are included in plain text, so only admins can export
+// Not allowed for API tokens, like backup_route
+// Query q fields
+// format: json or toml (json if not given)
+pub async fn export_monitors_route(
+    q: Query<HashMap<String, String>>,
+    user: Auth,
+) -> Result<([(HeaderName, &'static str); 1], String), (StatusCode, String)> {
+    user.require(Role::Admin, None)?;
+
+    let toml = match q.get("format").map(String::as_str) {
+        None | Some("json") => false,
+        Some("toml") => true,
+        Some(_) => {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                "bad param `format`, must be one of {json, toml}".to_string(),
+            ));
+        }
+    };
+
+    let export = export::export_monitors().await.map_err(|e| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to export monitors: {e:#}"),
+        )
+    })?;
+    let res = if toml {
+        toml::to_string_pretty(&export)
+            .map(|t| ([(CONTENT_TYPE, "application/toml")], t))
+            .map_err(|e| e.to_string())
+    } else {
+        serde_json::to_string_pretty(&export)
+            .map(|j| ([(CONTENT_TYPE, "application/json")], j))
+            .map_err(|e| e.to_string())
+    };
+
+    tracing::info!("{} exported the monitors", user.username);
+    res.map_err(|e| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to serialize the export: {e}"),
+        )
+    })
+}
+
+// The body is an export of export_monitors_route
+// Not allowed for API tokens, replace deletes all monitors with their history
+// Query q fields
+// format: json or toml (json if not given)
+// mode: merge (add the monitors that do not exist yet) or replace (delete all monitors that are
+//       not managed by the config first) (merge if not given)
+pub async fn import_monitors_route(
+    q: Query<HashMap<String, String>>,
+    user: Auth,
+    body: String,
+) -> Result<Json<ImportSummary>, (StatusCode, String)> {
+    user.require(Role::Admin, None)?;
+
+    let toml = match q.get("format").map(String::as_str) {
+        None | Some("json") => false,
+        Some("toml") => true,
+        Some(_) => {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                "bad param `format`, must be one of {json, toml}".to_string(),
+            ));
+        }
+    };
+    let mode = match q.get("mode") {
+        None => ImportMode::Merge,
+        Some(mode) => ImportMode::from_str(mode).ok_or((
+            StatusCode::BAD_REQUEST,
+            "bad param `mode`, must be one of {merge, replace}".to_string(),
+        ))?,
+    };
+
+    let export = export::parse_export(&body, toml).map_err(|e| {
+        (
+            StatusCode::BAD_REQUEST,
+            format!("Failed to parse the export: {e:#}"),
+        )
+    })?;
+    let summary = export::import_monitors(export, mode).await.map_err(|e| {
+        (
+            StatusCode::BAD_REQUEST,
+            format!("Failed to import monitors: {e:#}"),
+        )
+    })?;
+
+    tracing::info!(
+        "{} imported {} monitors",
+        user.username,
+        summary.added.len()
+    );
+    audit::record(AuditEntry {
+        details: serde_json::to_string(&summary).ok(),
+        ..AuditEntry::by(&user, "monitor.import")
+    })
+    .await;
+    Ok(Json(summary))
+}
+
+// Longest time range monitor_records_route returns at once, 31 days
+static MAX_RECORDS_RANGE_SECS: u64 = 31 * 24 * 60 * 60;
+
+// Query q fields
+// since: unix time of the oldest record (MAX_RECORDS_RANGE_SECS before `until` if not given)
+// until: unix time of the newest record (now if not given)
+// format: json or csv (json if not given)
+pub async fn monitor_records_route(
+    id: Path<u64>,
+    q: Query<HashMap<String, String>>,
+    user: Option<Auth>,
+) -> Result<([(HeaderName, &'static str); 1], String), (StatusCode, String)> {
+    // treated as a guest without the read scope
+    let role = user
+        .filter(|u| u.require(Role::Viewer, Some(Scope::Read)).is_ok())
+        .map(|u| u.role);
+    let allow_guest = CONFIG.get().unwrap().lock().await.allow_guest;
+    if !allow_guest && role.is_none() {
+        return Err((
+            StatusCode::UNAUTHORIZED,
+            "Unauthorized (set `token` cookie to log in or send an API token with scope read)"
+                .to_string(),
+        ));
+    }
+
+    let Some(mon) = database::monitor::get_by_id(*id)
+        .await
+        .filter(|m| m.is_visible_to(role))
+    else {
+        return Err((StatusCode::NOT_FOUND, "No such monitor".to_string()));
+    };
+
+    let time_param = |name: &str, default: u64| match q.get(name) {
+        None => Ok(default),
+        Some(t) => t.parse::<u64>().map_err(|_| {
+            (
+                StatusCode::BAD_REQUEST,
+                format!("bad param `{name}`, must be a unix time"),
+            )
+        }),
+    };
+    let until = time_param("until", current_unix_time())?;
+    let since = time_param("since", until.saturating_sub(MAX_RECORDS_RANGE_SECS))?;
+    if until.saturating_sub(since) > MAX_RECORDS_RANGE_SECS {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!(
+                "bad params `since` and `until`, the range must be at most {} days",
+                MAX_RECORDS_RANGE_SECS / (24 * 60 * 60)
+            ),
+        ));
+    }
+
+    let mut records = database::record::records_from_mon_between(*id, since, until)
+        .await
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to get records: {e}"),
+            )
+        })?;
+    if !mon.shows_location_to(role) {
+        for record in &mut records {
+            record.info = HIDDEN_INFO.to_string();
+        }
+    }
+
+    match q.get("format").map(String::as_str) {
+        None | Some("json") => Ok((
+            [(CONTENT_TYPE, "application/json")],
+            serde_json::to_string(&records).unwrap(),
+        )),
+        Some("csv") => Ok(([(CONTENT_TYPE, "text/csv")], export::records_csv(&records))),
+        Some(_) => Err((
+            StatusCode::BAD_REQUEST,
+            "bad param `format`, must be one of {json, csv}".to_string(),
+        )),
+    }
+}
+
+// Query q fields
+// page: page of the audit log, newest entries first (1 if not given)
+// per_page: entries per page, at most audit::MAX_PER_PAGE (audit::DEFAULT_PER_PAGE if not given)
+pub async fn audit_log_route(
+    q: Query<HashMap<String, String>>,
+    user: Auth,
+) -> Result<Json<AuditLogPage>, (StatusCode, String)> {
+    user.require(Role::Admin, Some(Scope::Read))?;
+
+    let (page, per_page) = audit::page_params(q.get("page"), q.get("per_page"));
+    match audit::get_page(page, per_page).await {
+        Ok(page) => Ok(Json(page)),
+        Err(e) => Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to read audit log: {e}"),
+        )),
+    }
+}
+
+// A consistent copy of the database made while the checker keeps running
+// Not allowed for API tokens, the copy contains all secrets
+pub async fn backup_route(
+    user: Auth,
+) -> Result<([(HeaderName, String); 2], Vec<u8>), (StatusCode, String)> {
+    user.require(Role::Admin, None)?;
+
+    let file_name = database::backup::file_name();
+    // unique per request, concurrent downloads must not share a file
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos();
+    let path = std::env::temp_dir().join(format!("{nanos}-{file_name}"));
+    let res = match database::backup::backup_to(&path).await {
+        Ok(_) => tokio::fs::read(&path).await.map_err(anyhow::Error::from),
+        Err(e) => Err(e),
+    };
+    let _ = tokio::fs::remove_file(&path).await;
+    let bytes = res.map_err(|e| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to back up the database: {e:#}"),
+        )
+    })?;
+
+    tracing::info!("{} downloaded a backup of the database", user.username);
+    audit::record(AuditEntry::by(&user, "database.backup")).await;
+    Ok((
+        [
+            (CONTENT_TYPE, "application/vnd.sqlite3".to_string()),
+            (
+                CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{file_name}\""),
+            ),
+        ],
+        bytes,
+    ))
+}
+
+// Body q fields (form or JSON)
+// un: username, must be 1 to 32 characters out of a-z, A-Z, 0-9, `-`, `_` and `.`
+// pw: password, must be at least 8 characters long
+// ro: role, must be one of {viewer, editor, admin}
+pub async fn add_user_route(user: Auth, q: BodyParams) -> (StatusCode, String) {
+    if let Err(e) = user.require(Role::Admin, None) {
+        return e;
+    }
+
+    let Some(username) = q
+        .get("un")
+        .filter(|un| database::user::is_valid_username(un))
+    else {
+        return (
+            StatusCode::BAD_REQUEST,
+            "bad or missing param `un` (username), must be 1 to 32 characters out of a-z, A-Z, 0-9, `-`, `_` and `.`".to_string(),
+        );
+    };
+
+    let Some(password) = q.get("pw").filter(|pw| pw.len() >= 8) else {
+        return (
+            StatusCode::BAD_REQUEST,
+            "bad or missing param `pw` (password), must be at least 8 characters long".to_string(),
+        );
+    };
+
+    let Some(role) = q.get("ro").and_then(|ro| Role::from_str(ro)) else {
+        return (
+            StatusCode::BAD_REQUEST,
+            "bad or missing param `ro` (role), must be one of {viewer, editor, admin}".to_string(),
+        );
+    };
+
+    let id = match database::user::add(username, password, role).await {
+        Ok(id) => id,
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to add user: {e}"),
+            );
+        }
+    };
+
+    tracing::info!(
+        "{} added user {id} ({username}) with role {}",
+        user.username,
+        role.as_str()
+    );
+    (StatusCode::CREATED, "User was added".to_string())
+}
+
+pub async fn delete_user_route(id: Path<u64>, user: Auth) -> (StatusCode, String) {
+    if let Err(e) = user.require(Role::Admin, None) {
+        return e;
+    }
+
+    if user.user_id == Some(*id) {
+        return (
+            StatusCode::BAD_REQUEST,
+            "You can not delete yourself".to_string(),
+        );
+    }
+
+    if let Err(e) = database::user::util_delete(*id).await {
+        return (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to remove user: {e}"),
+        );
+    }
+
+    tracing::info!("{} deleted user {}", user.username, *id);
+    (StatusCode::OK, "User was deleted".to_string())
+}
+
+// Body q fields (form or JSON)
+// ro: new role, must be one of {viewer, editor, admin}
+pub async fn set_user_role_route(id: Path<u64>, user: Auth, q: BodyParams) -> (StatusCode, String) {
+    if let Err(e) = user.require(Role::Admin, None) {
+        return e;
+    }
+
+    let Some(role) = q.get("ro").and_then(|ro| Role::from_str(ro)) else {
+        return (
+            StatusCode::BAD_REQUEST,
+            "bad or missing param `ro` (role), must be one of {viewer, editor, admin}".to_string(),
+        );
+    };
+
+    if user.user_id == Some(*id) {
+        return (
+            StatusCode::BAD_REQUEST,
+            "You can not change your own role".to_string(),
+        );
+    }
+
+    if let Err(e) = database::user::set_role(*id, role).await {
+        return (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to change role: {e}"),
+        );
+    }
+
+    tracing::info!(
+        "{} changed the role of user {} to {}",
+        user.username,
+        *id,
+        role.as_str()
+    );
+    (StatusCode::OK, format!("User is now {}", role.as_str()))
 }
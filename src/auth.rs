@@ -0,0 +1,188 @@
+use std::{
+    net::{IpAddr, SocketAddr},
+    str::FromStr,
+};
+
+use axum::{
+    extract::{ConnectInfo, FromRequestParts, OptionalFromRequestParts},
+    http::{header::AUTHORIZATION, request::Parts, HeaderMap, StatusCode},
+};
+use axum_extra::extract::CookieJar;
+
+use crate::{
+    config::CONFIG,
+    database::{self, api_token::Scope, user::Role},
+};
+
+#[derive(Debug)]
+pub enum Credential {
+    // `token` cookie of a logged in user
+    Session(u64),
+    // `Authorization: Bearer` header, can only do what its scopes allow
+    ApiToken(Vec<Scope>),
+}
+
+// Who sent a request, extracted from the `Authorization: Bearer` header (API token) or the
+// `token` cookie (session)
+// Rejects the request with 401 if neither is valid, use Option<Auth> for routes that work without
+// logging in
+#[derive(Debug)]
+pub struct Auth {
+    // None = logged in with the password from the config
+    pub user_id: Option<u64>,
+    // For API tokens this includes the name of the token, only meant for logs
+    pub username: String,
+    pub role: Role,
+    pub credential: Credential,
+    // None if the server was started without connection info
+    pub ip: Option<IpAddr>,
+}
+
+impl Auth {
+    // Checks that the role is high enough and, for API tokens, that they have `scope`
+    // scope = None: API tokens can not be used at all (e.g. to manage users)
+    pub fn require(&self, role: Role, scope: Option<Scope>) -> Result<(), (StatusCode, String)> {
+        if self.role < role {
+            return Err((
+                StatusCode::FORBIDDEN,
+                format!("Forbidden (requires role {})", role.as_str()),
+            ));
+        }
+
+        if let Credential::ApiToken(scopes) = &self.credential {
+            let Some(scope) = scope else {
+                return Err((
+                    StatusCode::FORBIDDEN,
+                    "Forbidden (API tokens can not be used here, log in instead)".to_string(),
+                ));
+            };
+            if !scopes.contains(&scope) {
+                return Err((
+                    StatusCode::FORBIDDEN,
+                    format!("Forbidden (API token lacks scope {})", scope.as_str()),
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn session_id(&self) -> Option<u64> {
+        match self.credential {
+            Credential::Session(id) => Some(id),
+            Credential::ApiToken(_) => None,
+        }
+    }
+
+    // Checks the API token or session of the request headers, also used to check them again
+    // during long-running requests so revoked ones stop working
+    pub async fn from_headers(
+        headers: &HeaderMap,
+        ip: Option<IpAddr>,
+    ) -> Result<Self, (StatusCode, String)> {
+        let bearer = headers
+            .get(AUTHORIZATION)
+            .and_then(|h| h.to_str().ok())
+            .and_then(|h| h.strip_prefix("Bearer "));
+
+        if let Some(token) = bearer {
+            let Some(user) = database::api_token::get_user(token.trim())
+                .await
+                .unwrap_or_default()
+            else {
+                return Err((
+                    StatusCode::UNAUTHORIZED,
+                    "Unauthorized (invalid or expired API token)".to_string(),
+                ));
+            };
+
+            return Ok(Self {
+                user_id: user.user_id,
+                username: format!("{} (API token {})", user.username, user.token_name),
+                role: user.role,
+                credential: Credential::ApiToken(user.scopes),
+                ip,
+            });
+        }
+
+        let cookies = CookieJar::from_headers(headers);
+        let user = match cookies.get("token") {
+            None => None,
+            Some(c) => database::session::get_user(c.value())
+                .await
+                .unwrap_or_default(),
+        };
+        let Some(user) = user else {
+            return Err((
+                StatusCode::UNAUTHORIZED,
+                "Unauthorized (set `token` cookie to log in or send an API token as `Authorization: Bearer`)".to_string(),
+            ));
+        };
+
+        Ok(Self {
+            user_id: user.user_id,
+            username: user.username,
+            role: user.role,
+            credential: Credential::Session(user.session_id),
+            ip,
+        })
+    }
+}
+
+impl<S: Send + Sync> FromRequestParts<S> for Auth {
+    type Rejection = (StatusCode, String);
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let ip = match parts.extensions.get::<ConnectInfo<SocketAddr>>() {
+            Some(ConnectInfo(addr)) => Some(client_ip(*addr, &parts.headers).await),
+            None => None,
+        };
+
+        Self::from_headers(&parts.headers, ip).await
+    }
+}
+
+impl<S: Send + Sync> OptionalFromRequestParts<S> for Auth {
+    type Rejection = (StatusCode, String);
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &S,
+    ) -> Result<Option<Self>, Self::Rejection> {
+        Ok(
+            <Self as FromRequestParts<S>>::from_request_parts(parts, state)
+                .await
+                .ok(),
+        )
+    }
+}
+
+// The IP of the client, taken from the header set in `login.real_ip_header` if configured
+// The proxies append to the header, so the entry `login.trusted_proxies` from the right is used
+pub async fn client_ip(addr: SocketAddr, headers: &HeaderMap) -> IpAddr {
+    let (real_ip_header, trusted_proxies) = {
+        let config = CONFIG.get().unwrap().lock().await;
+        (
+            config.login.real_ip_header.clone(),
+            config.login.trusted_proxies,
+        )
+    };
+    let Some(real_ip_header) = real_ip_header else {
+        return addr.ip();
+    };
+
+    // the header can be sent multiple times, proxies append to the last one
+    let entries: Vec<&str> = headers
+        .get_all(real_ip_header)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .collect();
+
+    entries
+        .iter()
+        .rev()
+        .nth(trusted_proxies.saturating_sub(1))
+        .and_then(|v| IpAddr::from_str(v.trim()).ok())
+        .unwrap_or(addr.ip())
+}
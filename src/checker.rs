@@ -35,14 +35,15 @@ async fn run_pending_checks() {
     let mons = database::monitor::get_all(true).await.unwrap();
     let now = current_unix_time();
     for (mon_id, mon) in mons {
-        let Some(last_record) = last_records.get(&mon_id) else {
-            continue;
-        };
-        if last_record + 60 * mon.interval_mins < now {
+        // monitors without a record were imported disabled and enabled since
+        let is_due = last_records
+            .get(&mon_id)
+            .is_none_or(|last_record| last_record + 60 * mon.interval_mins < now);
+        if is_due {
             let res = mon.service_data.run(mon.timeout_secs).await;
-            database::record::util_add_result(res, mon_id)
-                .await
-                .unwrap();
+            if let Err(e) = database::record::util_add_result(res, mon_id).await {
+                tracing::warn!("Failed to store the result of monitor {mon_id}: {e}");
+            }
         }
     }
 }
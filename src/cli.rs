@@ -0,0 +1,493 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    net::SocketAddr,
+    path::Path,
+};
+
+use anyhow::{bail, Context};
+use clap::{Parser, Subcommand, ValueEnum};
+use itertools::Itertools;
+
+use crate::{
+    config::{self, ConfigMonitorService},
+    database::{
+        self,
+        audit::{self, AuditEntry},
+        DATABASE,
+    },
+    export::{self, ImportMode},
+    monitor::{Monitor, MonitorResult, Visibility},
+    time_util::current_unix_time,
+};
+
+// Actor of audit log entries for changes made with the CLI
+static AUDIT_ACTOR: &str = "cli";
+
+#[derive(Parser, Debug)]
+#[command(version, about = "Strawberry Monitor, a simple uptime panel")]
+pub struct Cli {
+    /// Path of the config file
+    #[arg(short, long, global = true, default_value = "./stbmon.toml")]
+    pub config: String,
+    // `stb-mon <config path>` from before there were subcommands, starts the server
+    #[arg(hide = true)]
+    pub legacy_config: Option<String>,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Run the web UI and the checker (default)
+    Serve,
+    /// Check monitors once and print the results, the results are not stored
+    Check {
+        /// ID of the monitor
+        #[arg(required_unless_present = "all")]
+        id: Option<u64>,
+        /// Check all enabled monitors
+        #[arg(long, conflicts_with = "id")]
+        all: bool,
+    },
+    /// Manage monitors
+    #[command(subcommand)]
+    Monitors(MonitorsCommand),
+    /// Export check results
+    #[command(subcommand)]
+    Records(RecordsCommand),
+    /// Check the config
+    #[command(subcommand)]
+    Config(ConfigCommand),
+    /// Maintain the database
+    #[command(subcommand)]
+    Db(DbCommand),
+    /// Read a password from stdin and print its hash for `password_hash`
+    HashPassword,
+    /// Print a new secret for `login.totp_secret`
+    TotpSecret,
+    /// Print a new key for `secret_key`
+    SecretKey,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum MonitorsCommand {
+    /// Print all monitors
+    List {
+        /// Print them as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Add a monitor and check it once
+    Add(AddMonitorArgs),
+    /// Delete a monitor including its records
+    Remove {
+        id: u64,
+    },
+    Enable {
+        id: u64,
+    },
+    Disable {
+        id: u64,
+    },
+    /// Print all monitors including their secrets in a format that can be imported
+    Export {
+        #[arg(long, value_enum, default_value_t = MonitorExportFormat::Json)]
+        format: MonitorExportFormat,
+    },
+    /// Import monitors from an export, TOML if the file ends with .toml and JSON otherwise
+    Import {
+        path: String,
+        /// Delete the monitors that are not managed by the config (including their records) first
+        #[arg(long)]
+        replace: bool,
+    },
+}
+
+#[derive(ValueEnum, Clone, Debug)]
+pub enum MonitorExportFormat {
+    Json,
+    Toml,
+}
+
+#[derive(ValueEnum, Clone, Debug)]
+pub enum MonitorType {
+    Tcp,
+    Http,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct AddMonitorArgs {
+    #[arg(long = "type", value_enum)]
+    pub ty: MonitorType,
+    #[arg(long, default_value = "")]
+    pub name: String,
+    /// Check interval in minutes
+    #[arg(long, default_value_t = 10, value_parser = clap::value_parser!(u16).range(1..=60 * 24 * 7))]
+    pub interval: u16,
+    /// Timeout in seconds
+    #[arg(long, default_value_t = 5, value_parser = clap::value_parser!(u16).range(1..=60))]
+    pub timeout: u16,
+    /// Who can see the monitor: public, private (logged in users) or admin
+    #[arg(long, default_value = "public", value_parser = parse_visibility)]
+    pub visibility: Visibility,
+    /// Hide the service location from guests
+    #[arg(long)]
+    pub hide_location: bool,
+    /// Socket address (tcp)
+    #[arg(long, required_if_eq("ty", "tcp"))]
+    pub addr: Option<SocketAddr>,
+    /// URL (http)
+    #[arg(long, required_if_eq("ty", "http"))]
+    pub url: Option<String>,
+    /// HTTP method (GET if not given)
+    #[arg(long)]
+    pub method: Option<String>,
+    /// Request header, can be given more than once
+    #[arg(long = "header", value_name = "NAME:VALUE")]
+    pub headers: Vec<String>,
+    /// Request body
+    #[arg(long, default_value = "")]
+    pub body: String,
+    /// Accepted status codes, e.g. 200-299,301 (any if not given)
+    #[arg(long)]
+    pub status_codes: Option<String>,
+    /// Adler32 checksum the response body must have
+    #[arg(long)]
+    pub body_adler32: Option<u32>,
+}
+
+fn parse_visibility(val: &str) -> Result<Visibility, String> {
+    Visibility::from_str(val).ok_or_else(|| "must be one of public, private, admin".to_string())
+}
+
+#[derive(Subcommand, Debug)]
+pub enum RecordsCommand {
+    /// Print the records to stdout, newest first
+    Export {
+        /// Only export the records of this monitor
+        #[arg(long)]
+        monitor: Option<u64>,
+        /// Only export records checked at or after this unix time
+        #[arg(long, default_value_t = 0)]
+        since: u64,
+        /// Only export records checked at or before this unix time
+        #[arg(long)]
+        until: Option<u64>,
+        #[arg(long, value_enum, default_value_t = ExportFormat::Csv)]
+        format: ExportFormat,
+    },
+}
+
+#[derive(ValueEnum, Clone, Debug)]
+pub enum ExportFormat {
+    Csv,
+    Json,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ConfigCommand {
+    /// Check the config (and monitors_dir) without starting the server
+    Validate,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum DbCommand {
+    /// Create missing tables and columns
+    Migrate,
+    /// Rebuild the database file to free unused space
+    Vacuum,
+    /// Copy the database to a file, works while the server is running
+    Backup {
+        /// Where to write the backup (stbmon-<time>.sqlite if not given)
+        path: Option<String>,
+    },
+    /// Replace the database with a backup, the server must be stopped first
+    Restore {
+        /// Backup made with `db backup`, the scheduled backups or /api/admin/backup
+        path: String,
+    },
+}
+
+// (is up, human readable result)
+fn format_result(res: &MonitorResult) -> (bool, String) {
+    match res {
+        MonitorResult::Ok(ms, info) => (true, format!("Up in {ms}ms {info}")),
+        MonitorResult::UnexpectedResponse(ms, info) => {
+            (false, format!("Unexpected response in {ms}ms: {info}"))
+        }
+        MonitorResult::Down(info) => (false, format!("Down: {info}")),
+        MonitorResult::IoError(e) => (false, format!("Error: {e}")),
+    }
+}
+
+// Fails if any monitor is not up, so scripts can use the exit code
+pub async fn check(id: Option<u64>, all: bool) -> anyhow::Result<()> {
+    let mons: Vec<(u64, Monitor)> = match id {
+        // clap requires an ID unless --all is given
+        None if !all => bail!("Give the ID of a monitor or --all"),
+        Some(id) => {
+            let mon = database::monitor::get_by_id(id)
+                .await
+                .context("No such monitor")?;
+            vec![(id, mon)]
+        }
+        None => database::monitor::get_all(true)
+            .await?
+            .into_iter()
+            .sorted_by_key(|(id, _)| *id)
+            .collect(),
+    };
+
+    let mut failed = 0;
+    for (id, mon) in mons {
+        let (up, text) = format_result(&mon.service_data.run(mon.timeout_secs).await);
+        if !up {
+            failed += 1;
+        }
+        println!(
+            "{id}\t{}\t{}\t{text}",
+            mon.service_name,
+            mon.service_data.service_location_str()
+        );
+    }
+
+    if failed > 0 {
+        bail!("{failed} monitors are not up");
+    }
+
+    Ok(())
+}
+
+pub async fn monitors(command: MonitorsCommand) -> anyhow::Result<()> {
+    match command {
+        MonitorsCommand::List { json } => {
+            let mons: Vec<(u64, Monitor)> = database::monitor::get_all(false)
+                .await?
+                .into_iter()
+                .sorted_by_key(|(id, _)| *id)
+                .collect();
+
+            if json {
+                let mons: BTreeMap<u64, Monitor> = mons.into_iter().collect();
+                println!("{}", serde_json::to_string_pretty(&mons)?);
+                return Ok(());
+            }
+
+            println!("ID\tENABLED\tVISIBILITY\tINTERVAL\tNAME\tLOCATION\tCONFIG KEY");
+            for (id, mon) in mons {
+                println!(
+                    "{id}\t{}\t{}\t{}m\t{}\t{}\t{}",
+                    mon.enabled,
+                    mon.visibility.as_str(),
+                    mon.interval_mins,
+                    mon.service_name,
+                    mon.service_data.service_location_str(),
+                    mon.config_key.unwrap_or_default()
+                );
+            }
+        }
+        MonitorsCommand::Add(args) => {
+            let service = match args.ty {
+                MonitorType::Tcp => ConfigMonitorService::Tcp {
+                    addr: args.addr.context("--addr is required")?,
+                },
+                MonitorType::Http => {
+                    let mut headers = HashMap::new();
+                    for header in &args.headers {
+                        let (name, value) = header
+                            .split_once(':')
+                            .with_context(|| format!("Header `{header}` must be NAME:VALUE"))?;
+                        headers.insert(name.trim().to_string(), value.trim().to_string());
+                    }
+
+                    ConfigMonitorService::Http {
+                        url: args.url.context("--url is required")?,
+                        method: args.method,
+                        headers,
+                        body: args.body,
+                        status_codes: args.status_codes,
+                        body_adler32: args.body_adler32,
+                    }
+                }
+            };
+            let service_data = service.to_monitor_data()?;
+
+            let id = database::monitor::add(&service_data, args.interval, args.name, args.timeout)
+                .await?;
+            database::monitor::set_visibility(id, args.visibility, args.hide_location).await?;
+
+            // checked right away instead of when the checker gets to it
+            let res = service_data.run(args.timeout).await;
+            println!("Added monitor {id} ({})", format_result(&res).1);
+            database::record::util_add_result(res, id).await?;
+
+            record(id, "monitor.add", None, audit::monitor_snapshot(id).await).await;
+        }
+        MonitorsCommand::Remove { id } => {
+            let before = get_unmanaged(id).await?;
+            database::monitor::util_delete(id).await?;
+
+            println!("Deleted monitor {id}");
+            record(id, "monitor.delete", before, None).await;
+        }
+        MonitorsCommand::Enable { id } | MonitorsCommand::Disable { id } => {
+            let enable = matches!(command, MonitorsCommand::Enable { .. });
+            let before = get_unmanaged(id).await?;
+            if database::monitor::is_enabled(id).await? == enable {
+                println!(
+                    "Monitor {id} is already {}",
+                    if enable { "enabled" } else { "disabled" }
+                );
+                return Ok(());
+            }
+            database::monitor::toggle(id).await?;
+
+            println!(
+                "Monitor {id} is now {}",
+                if enable { "enabled" } else { "disabled" }
+            );
+            record(
+                id,
+                "monitor.toggle",
+                before,
+                audit::monitor_snapshot(id).await,
+            )
+            .await;
+        }
+        MonitorsCommand::Export { format } => {
+            let export = export::export_monitors().await?;
+            match format {
+                MonitorExportFormat::Json => {
+                    println!("{}", serde_json::to_string_pretty(&export)?)
+                }
+                MonitorExportFormat::Toml => print!("{}", toml::to_string_pretty(&export)?),
+            }
+        }
+        MonitorsCommand::Import { path, replace } => {
+            let text =
+                std::fs::read_to_string(&path).with_context(|| format!("Failed to read {path}"))?;
+            let export = export::parse_export(&text, path.ends_with(".toml"))
+                .with_context(|| format!("{path} is not a valid export"))?;
+            let mode = if replace {
+                ImportMode::Replace
+            } else {
+                ImportMode::Merge
+            };
+
+            let summary = export::import_monitors(export, mode).await?;
+            println!(
+                "Added {} monitors, skipped {}, deleted {}",
+                summary.added.len(),
+                summary.skipped,
+                summary.deleted
+            );
+            audit::record(AuditEntry {
+                actor: AUDIT_ACTOR.to_string(),
+                action: "monitor.import",
+                details: serde_json::to_string(&summary).ok(),
+                ..Default::default()
+            })
+            .await;
+        }
+    }
+
+    Ok(())
+}
+
+// Snapshot of a monitor for the audit log, fails if the monitor is managed by the config
+async fn get_unmanaged(id: u64) -> anyhow::Result<Option<serde_json::Value>> {
+    let mon = database::monitor::get_by_id(id)
+        .await
+        .context("No such monitor")?;
+    if let Some(key) = &mon.config_key {
+        bail!("Monitor is managed by the config (key `{key}`), change it there");
+    }
+
+    Ok(serde_json::to_value(mon).ok())
+}
+
+async fn record(
+    id: u64,
+    action: &'static str,
+    before: Option<serde_json::Value>,
+    after: Option<serde_json::Value>,
+) {
+    audit::record(AuditEntry {
+        actor: AUDIT_ACTOR.to_string(),
+        action,
+        target: Some(id.to_string()),
+        before,
+        after,
+        ..Default::default()
+    })
+    .await;
+}
+
+pub async fn records(command: RecordsCommand) -> anyhow::Result<()> {
+    let RecordsCommand::Export {
+        monitor,
+        since,
+        until,
+        format,
+    } = command;
+    let until = until.unwrap_or_else(current_unix_time);
+
+    let ids = match monitor {
+        Some(id) => vec![id],
+        None => database::monitor::get_all(false)
+            .await?
+            .into_keys()
+            .sorted()
+            .collect(),
+    };
+    let mut records = vec![];
+    for id in ids {
+        records.extend(database::record::records_from_mon_between(id, since, until).await?);
+    }
+
+    match format {
+        ExportFormat::Json => println!("{}", serde_json::to_string_pretty(&records)?),
+        ExportFormat::Csv => print!("{}", export::records_csv(&records)),
+    }
+
+    Ok(())
+}
+
+pub async fn validate_config(path: &str) -> anyhow::Result<()> {
+    let config = config::load_config(path)
+        .await
+        .with_context(|| format!("{path} is invalid"))?;
+
+    println!(
+        "{path} is valid ({} monitors, {} status pages)",
+        config.monitors.len(),
+        config.status_pages.len()
+    );
+    Ok(())
+}
+
+pub async fn db(command: DbCommand) -> anyhow::Result<()> {
+    // opening the database creates missing tables and columns
+    match command {
+        DbCommand::Migrate => {
+            let _ = DATABASE.lock().await;
+            println!("Database is up to date");
+        }
+        DbCommand::Vacuum => {
+            DATABASE.lock().await.execute("VACUUM", [])?;
+            println!("Database was vacuumed");
+        }
+        DbCommand::Backup { path } => {
+            let path = path.unwrap_or_else(database::backup::file_name);
+            database::backup::backup_to(Path::new(&path)).await?;
+            println!("Backed up the database to {path}");
+        }
+        // the database is not opened, its file is replaced
+        DbCommand::Restore { path } => {
+            let version = database::backup::restore(Path::new(&path))?;
+            println!("Restored {path} (schema version {version}), the previous database was kept as stbmon.sqlite.before-restore");
+        }
+    }
+
+    Ok(())
+}
@@ -1,22 +1,80 @@
-use anyhow::bail;
-use serde::Deserialize;
-use sha2::{Digest, Sha256};
+use anyhow::{anyhow, bail, Context};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::env;
 use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, OnceLock};
 use tokio::fs;
 use tokio::sync::Mutex;
+use toml::{Table, Value};
+use url::Url;
+
+use crate::database::user::Role;
+use crate::monitor::{
+    http::{self as http_mon, HeaderHashMap, HttpExpectedResponse, HttpMethod, HttpRequest},
+    tcp::TcpExpectedResponse,
+    MonitorData, Visibility,
+};
+use crate::password::{self, HashKind};
+use crate::secrets::{self, SecretKey};
+use crate::totp;
+
+// Prefix of environment variables overriding config values
+static ENV_PREFIX: &str = "STBMON_";
+// Only environment variables with this prefix can be used as ${VAR} in monitor requests, so
+// editors can not send other secrets of the server (e.g. STBMON_SECRET_KEY) to a host they control
+// They are never used as config overrides
+pub static MONITOR_ENV_PREFIX: &str = "STBMON_MONITOR_";
+// `<key>_file` reads the value of `<key>` from a file
+static SECRET_FILE_SUFFIX: &str = "_file";
 
 #[derive(Deserialize, Debug)]
 pub struct Config {
     pub bind_addr: SocketAddr,
     pub instance_name: String,
-    #[serde(rename = "password_sha256")]
+    // Argon2/bcrypt PHC string or legacy unsalted SHA-256 hex digest
+    #[serde(rename = "password_hash", alias = "password_sha256")]
     pub password: String,
     pub allow_guest: bool,
+    // Serve the badges of public monitors to guests even if allow_guest is off
+    #[serde(default)]
+    pub guest_badges: bool,
     pub http: ConfigHttp,
+    #[serde(default)]
+    pub login: ConfigLogin,
+    // Availability target in percent for the SLA reports
+    #[serde(default = "default_sla_target")]
+    pub sla_target: f64,
+    #[serde(default, rename = "status_page")]
+    pub status_pages: Vec<ConfigStatusPage>,
+    // Single sign-on with an OpenID Connect provider, disabled if not set
+    pub oidc: Option<ConfigOidc>,
+    // Monitors managed by the config, the ones in `monitors_dir` are added to them
+    #[serde(default, rename = "monitor")]
+    pub monitors: Vec<ConfigMonitor>,
+    // Directory with more *.toml files containing [[monitor]] tables, relative to the config file
+    #[serde(default = "default_monitors_dir")]
+    pub monitors_dir: String,
+    // Base64 encoded 32 byte key encrypting secret monitor fields (HTTP header values and request
+    // bodies) in the database, they are stored in plain text if not set
+    pub secret_key: Option<String>,
+    // Scheduled backups of the database, disabled if not set
+    pub backup: Option<ConfigBackup>,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Clone)]
+pub struct ConfigBackup {
+    // Relative to the config file, created if it does not exist
+    pub dir: String,
+    #[serde(default = "default_backup_interval_hours")]
+    pub interval_hours: u64,
+    // Older backups in `dir` are deleted
+    #[serde(default = "default_backup_keep")]
+    pub keep: usize,
+}
+
+#[derive(Deserialize, Debug, Clone)]
 pub struct ConfigHttp {
     #[serde(rename = "5xx_status_code_down")]
     pub fivexx_status_code_down: bool,
@@ -24,13 +82,489 @@ pub struct ConfigHttp {
     pub max_follow_redirects: Option<u16>,
 }
 
+// Throttling of failed login attempts
+#[derive(Deserialize, Debug)]
+#[serde(default)]
+pub struct ConfigLogin {
+    // An IP is locked out after this many failed attempts within `lockout_secs`
+    pub max_attempts_per_ip: u32,
+    pub lockout_secs: u64,
+    // All logins are rejected for a minute after this many failed attempts within a minute
+    pub max_attempts_global_per_min: u32,
+    // Header containing the client IP if stbmon runs behind a reverse proxy (e.g. X-Forwarded-For)
+    pub real_ip_header: Option<String>,
+    // Number of reverse proxies in front of stbmon, each appends to `real_ip_header`
+    // The client IP is taken this many entries from the right, entries further left are set by
+    // the client and can be spoofed
+    pub trusted_proxies: usize,
+    // Only send the session cookie over HTTPS
+    pub secure_cookie: bool,
+    // Origins (scheme://host[:port]) besides the requested host that may send state-changing
+    // requests, e.g. if a reverse proxy rewrites the Host header
+    pub allowed_origins: Vec<String>,
+    // Admins without two-factor authentication only get the rights of viewers
+    pub require_totp_for_admins: bool,
+    // Base32 TOTP secret for logging in with `password_hash`, a code is required if set
+    pub totp_secret: Option<String>,
+}
+
+impl Default for ConfigLogin {
+    fn default() -> Self {
+        Self {
+            max_attempts_per_ip: 5,
+            lockout_secs: 60 * 15,
+            max_attempts_global_per_min: 30,
+            real_ip_header: None,
+            trusted_proxies: 1,
+            secure_cookie: false,
+            allowed_origins: vec![],
+            require_totp_for_admins: false,
+            totp_secret: None,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct ConfigOidc {
+    // URL of the provider, /.well-known/openid-configuration is appended to it
+    pub issuer: String,
+    pub client_id: String,
+    // Not needed for public clients, PKCE is used either way
+    pub client_secret: Option<String>,
+    // Must point to /api/oidc/callback of this instance, e.g. https://stbmon.example.com/api/oidc/callback
+    pub redirect_url: String,
+    #[serde(default = "default_oidc_scopes")]
+    pub scopes: Vec<String>,
+    // Claim used as username
+    #[serde(default = "default_oidc_username_claim")]
+    pub username_claim: String,
+    // Claim containing the groups or roles of the user, nested claims are separated by dots
+    // (e.g. realm_access.roles for Keycloak)
+    pub roles_claim: Option<String>,
+    // Value of the roles claim -> stbmon role (viewer, editor or admin), the highest one is used
+    #[serde(default)]
+    pub role_mapping: HashMap<String, String>,
+    // Role of users without a mapped value, they can not log in if not set
+    pub default_role: Option<String>,
+    // Only allow logging in with single sign-on, including the config password
+    #[serde(default)]
+    pub disable_password_login: bool,
+    #[serde(default = "default_oidc_button_label")]
+    pub button_label: String,
+}
+
+fn default_oidc_scopes() -> Vec<String> {
+    vec!["openid".to_string(), "profile".to_string()]
+}
+
+fn default_oidc_username_claim() -> String {
+    "preferred_username".to_string()
+}
+
+fn default_oidc_button_label() -> String {
+    "Log in with SSO".to_string()
+}
+
+fn default_sla_target() -> f64 {
+    99.9
+}
+
+fn default_backup_interval_hours() -> u64 {
+    24
+}
+
+fn default_backup_keep() -> usize {
+    7
+}
+
+fn default_monitors_dir() -> String {
+    "monitors.d".to_string()
+}
+
+fn default_true() -> bool {
+    true
+}
+
+// A monitor defined in the config, it can not be changed in the web UI
+#[derive(Deserialize, Debug)]
+pub struct ConfigMonitor {
+    // Identifies the monitor across restarts, changing it creates a new monitor
+    pub key: String,
+    #[serde(default)]
+    pub name: String,
+    pub interval_mins: u16,
+    pub timeout_secs: u16,
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    // public, private (logged in users) or admin
+    #[serde(default)]
+    pub visibility: Visibility,
+    // Hide the service location from guests
+    #[serde(default)]
+    pub hide_location: bool,
+    #[serde(flatten)]
+    pub service: ConfigMonitorService,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum ConfigMonitorService {
+    Tcp {
+        addr: SocketAddr,
+    },
+    Http {
+        url: String,
+        #[serde(default)]
+        method: Option<String>,
+        #[serde(default)]
+        headers: HashMap<String, String>,
+        // Sent as is (not base64 encoded like in the API)
+        #[serde(default)]
+        body: String,
+        // e.g. 200-299,301, any status code is accepted if not set
+        status_codes: Option<String>,
+        // Adler32 checksum the response body must have
+        body_adler32: Option<u32>,
+    },
+}
+
+// Contents of a file in `monitors_dir`
+#[derive(Deserialize, Debug)]
+struct ConfigMonitorFile {
+    #[serde(default, rename = "monitor")]
+    monitors: Vec<ConfigMonitor>,
+}
+
+impl ConfigMonitor {
+    pub fn to_monitor_data(&self) -> anyhow::Result<MonitorData> {
+        self.service.to_monitor_data()
+    }
+
+    fn validate(&self) -> anyhow::Result<()> {
+        if self.key.is_empty()
+            || !self
+                .key
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.')
+        {
+            bail!("key must only contain letters, digits, `-`, `_` and `.`");
+        }
+        if !(1..=60 * 24 * 7).contains(&self.interval_mins) {
+            bail!("interval_mins must be within 1..10080");
+        }
+        if !(1..=60).contains(&self.timeout_secs) {
+            bail!("timeout_secs must be within 1..60");
+        }
+        self.to_monitor_data()?;
+
+        Ok(())
+    }
+}
+
+impl ConfigMonitorService {
+    // Fails for monitors that can not be defined in the config
+    pub fn from_monitor_data(data: &MonitorData) -> anyhow::Result<Self> {
+        let service = match data {
+            MonitorData::Tcp { addr, expected } => {
+                if *expected != TcpExpectedResponse::OpenPort {
+                    bail!("only TCP monitors expecting an open port are supported");
+                }
+                Self::Tcp { addr: *addr }
+            }
+            MonitorData::Http {
+                url,
+                request,
+                expected,
+            } => {
+                let (status_codes, body_adler32) = match expected {
+                    HttpExpectedResponse::Any => (None, None),
+                    HttpExpectedResponse::StatusCode(codes) => (Some(codes.clone()), None),
+                    HttpExpectedResponse::Response(codes, checksum) => {
+                        (codes.clone(), Some(*checksum))
+                    }
+                };
+
+                Self::Http {
+                    url: url.clone(),
+                    method: Some(request.method.to_reqwest().as_str().to_lowercase()),
+                    headers: request.headers.to_map(),
+                    body: String::from_utf8(request.body.clone())
+                        .context("the request body is not valid UTF-8")?,
+                    status_codes,
+                    body_adler32,
+                }
+            }
+        };
+
+        Ok(service)
+    }
+
+    pub fn to_monitor_data(&self) -> anyhow::Result<MonitorData> {
+        let data = match self {
+            Self::Tcp { addr } => MonitorData::Tcp {
+                addr: *addr,
+                expected: TcpExpectedResponse::OpenPort,
+            },
+            Self::Http {
+                url,
+                method,
+                headers,
+                body,
+                status_codes,
+                body_adler32,
+            } => {
+                let url = Url::parse(url).context("invalid url")?.to_string();
+                let method = match method {
+                    None => HttpMethod::default(),
+                    Some(m) => HttpMethod::from_str(m).context(
+                        "method must be one of {get, post, put, delete, options, head, trace, connect, patch}",
+                    )?,
+                };
+                let headers = HeaderHashMap::try_from_map(headers.clone())
+                    .context("invalid header name or value")?;
+                if let Some(codes) = status_codes {
+                    if http_mon::parse_codes(codes).is_none() {
+                        bail!("failed to parse status_codes");
+                    }
+                }
+                let expected = match (status_codes, body_adler32) {
+                    (codes, Some(checksum)) => {
+                        HttpExpectedResponse::Response(codes.clone(), *checksum)
+                    }
+                    (Some(codes), None) => HttpExpectedResponse::StatusCode(codes.clone()),
+                    (None, None) => HttpExpectedResponse::Any,
+                };
+
+                MonitorData::Http {
+                    url,
+                    request: HttpRequest {
+                        method,
+                        headers,
+                        body: body.as_bytes().to_vec(),
+                    },
+                    expected,
+                }
+            }
+        };
+
+        Ok(data)
+    }
+}
+
+// Sets config values from STBMON_* environment variables, nested keys are separated by two
+// underscores, e.g. STBMON_LOGIN__SECURE_COOKIE=true sets `secure_cookie` in `[login]`
+// Values are parsed as TOML (numbers, booleans, arrays) and used as string if that fails
+// Returns the values that were not parsed as string by their dotted key, parse_config uses the
+// raw value instead if their field is a string (e.g. a numeric secret)
+fn apply_env_overrides(
+    table: &mut Table,
+    vars: impl Iterator<Item = (String, String)>,
+) -> anyhow::Result<HashMap<String, (Vec<String>, String)>> {
+    let mut typed = HashMap::new();
+    for (var, raw) in vars {
+        if var.starts_with(MONITOR_ENV_PREFIX) {
+            continue;
+        }
+        let Some(path) = var.strip_prefix(ENV_PREFIX) else {
+            continue;
+        };
+        let path: Vec<String> = path.split("__").map(str::to_lowercase).collect();
+        if path.iter().any(String::is_empty) {
+            bail!("Invalid environment variable name {var}");
+        }
+
+        let value = toml::from_str::<Table>(&format!("v = {raw}"))
+            .ok()
+            .and_then(|mut t| t.remove("v"))
+            .unwrap_or_else(|| Value::String(raw.clone()));
+
+        tracing::debug!("Setting {} from {var}", path.join("."));
+        if !value.is_str() {
+            typed.insert(path.join("."), (path.clone(), raw));
+        }
+        set_value(table, &path, value).with_context(|| format!("{var} can not be set"))?;
+    }
+
+    Ok(typed)
+}
+
+fn set_value(table: &mut Table, path: &[String], value: Value) -> anyhow::Result<()> {
+    let (key, parents) = path.split_last().unwrap();
+    let mut current = table;
+    for parent in parents {
+        let entry = current
+            .entry(parent.clone())
+            .or_insert_with(|| Value::Table(Table::new()));
+        let Value::Table(t) = entry else {
+            bail!("{parent} is not a table");
+        };
+        current = t;
+    }
+    current.insert(key.clone(), value);
+
+    Ok(())
+}
+
+// Deserializes the config, an override that was parsed as a number, boolean or array is used as
+// string if its field has the wrong type for it, e.g. STBMON_OIDC__CLIENT_SECRET=123456
+fn parse_config(
+    mut table: Table,
+    mut typed_overrides: HashMap<String, (Vec<String>, String)>,
+) -> anyhow::Result<Config> {
+    // key and error of an override that is retried as string
+    let mut retried: Option<(String, anyhow::Error)> = None;
+    loop {
+        let err = match serde_path_to_error::deserialize(Value::Table(table.clone())) {
+            Ok(config) => return Ok(config),
+            Err(e) => e,
+        };
+
+        let key = err.path().to_string();
+        // the string did not work either, the error about the parsed value is more helpful
+        if let Some((retried_key, typed_err)) = retried.take() {
+            if retried_key == key {
+                return Err(typed_err);
+            }
+        }
+        // toml only names the key for some errors
+        let err = err.into_inner();
+        let err = if key != "." && !err.to_string().contains(&format!("in `{key}`")) {
+            anyhow!("{err}\nin `{key}`")
+        } else {
+            err.into()
+        };
+
+        let Some((path, raw)) = typed_overrides.remove(&key) else {
+            return Err(err);
+        };
+        set_value(&mut table, &path, Value::String(raw))?;
+        retried = Some((key, err));
+    }
+}
+
+// Replaces `<key>_file = "path"` with `<key> = "<contents of the file>"`, e.g. password_hash_file
+// or oidc.client_secret_file for secrets mounted into containers
+// Only top level keys and keys of tables like [login] are checked, not the ones of [[monitor]]
+async fn read_secret_files(table: &mut Table) -> anyhow::Result<()> {
+    read_secret_files_of(table).await?;
+    for (_, value) in table.iter_mut() {
+        if let Value::Table(t) = value {
+            read_secret_files_of(t).await?;
+        }
+    }
+
+    Ok(())
+}
+
+async fn read_secret_files_of(table: &mut Table) -> anyhow::Result<()> {
+    let file_keys: Vec<String> = table
+        .keys()
+        .filter(|k| k.ends_with(SECRET_FILE_SUFFIX))
+        .cloned()
+        .collect();
+
+    for file_key in file_keys {
+        let key = file_key.trim_end_matches(SECRET_FILE_SUFFIX).to_string();
+        if table.contains_key(&key) {
+            bail!("Only one of {key} and {file_key} can be set");
+        }
+        let Some(Value::String(path)) = table.remove(&file_key) else {
+            bail!("{file_key} must be a path");
+        };
+
+        let secret = fs::read_to_string(&path)
+            .await
+            .with_context(|| format!("Failed to read {file_key} ({path})"))?;
+        // files usually end with a newline, which is not part of the secret
+        table.insert(
+            key,
+            Value::String(secret.trim_end_matches(['\r', '\n']).to_string()),
+        );
+    }
+
+    Ok(())
+}
+
+// `monitors_dir` and `backup.dir` are relative to the directory of the config file
+pub fn relative_to_config(config_path: &str, path: &str) -> PathBuf {
+    Path::new(config_path)
+        .parent()
+        .unwrap_or(Path::new("."))
+        .join(path)
+}
+
+// Reads the *.toml files of `monitors_dir` in alphabetical order, a missing directory is fine
+async fn load_monitors_dir(config_path: &str, dir: &str) -> anyhow::Result<Vec<ConfigMonitor>> {
+    let dir = relative_to_config(config_path, dir);
+    let mut entries = match fs::read_dir(&dir).await {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(e) => return Err(e).with_context(|| format!("Failed to read {}", dir.display())),
+    };
+
+    let mut paths = vec![];
+    while let Some(entry) = entries.next_entry().await? {
+        let path = entry.path();
+        if path.extension().is_some_and(|e| e == "toml") {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+
+    let mut monitors = vec![];
+    for path in paths {
+        let file = fs::read_to_string(&path).await?;
+        let file = toml::from_str::<ConfigMonitorFile>(&file)
+            .with_context(|| format!("Failed to parse {}", path.display()))?;
+        monitors.extend(file.monitors);
+    }
+
+    Ok(monitors)
+}
+
+// A public status page, viewable without logging in even if guests are not allowed
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ConfigStatusPage {
+    // The page is served at /status/{slug}
+    pub slug: String,
+    pub title: String,
+    // URL of the logo, the stbmon logo is used if not set
+    pub logo: Option<String>,
+    pub groups: Vec<ConfigStatusPageGroup>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ConfigStatusPageGroup {
+    pub name: String,
+    pub monitors: Vec<ConfigStatusPageMonitor>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ConfigStatusPageMonitor {
+    pub id: u64,
+    // Shown instead of the service name and location
+    pub name: String,
+}
+
+impl ConfigStatusPage {
+    pub fn monitor_ids(&self) -> Vec<u64> {
+        self.groups
+            .iter()
+            .flat_map(|g| g.monitors.iter().map(|m| m.id))
+            .collect()
+    }
+}
+
 impl Config {
-    pub fn check_password(&self, inp: &str) -> bool {
-        let mut hasher = Sha256::new();
-        hasher.update(inp);
-        let hash = hasher.finalize();
+    pub fn secret_key(&self) -> anyhow::Result<Option<SecretKey>> {
+        self.secret_key
+            .as_deref()
+            .map(secrets::parse_key)
+            .transpose()
+    }
 
-        hex::encode(hash) == self.password.to_ascii_lowercase()
+    pub fn check_password(&self, inp: &str) -> bool {
+        password::verify(inp, &self.password)
     }
 }
 
@@ -41,18 +575,107 @@ pub async fn init_config(path: String) -> anyhow::Result<()> {
         bail!("Config already initialized");
     }
 
+    let config = load_config(&path).await?;
+    secrets::set_key(config.secret_key()?);
+    CONFIG.set(Arc::new(Mutex::new(config))).unwrap();
+
+    Ok(())
+}
+
+// Reads and checks the config, also used to reload it
+pub async fn load_config(path: &str) -> anyhow::Result<Config> {
     let file = fs::read_to_string(path).await?;
-    let config = toml::from_str::<Config>(&file)?;
+    let mut table = toml::from_str::<Table>(&file)?;
+    let typed_overrides = apply_env_overrides(&mut table, env::vars())?;
+    read_secret_files(&mut table).await?;
+    let mut config = parse_config(table, typed_overrides)?;
 
-    if config.password.len() != 256 / 4 || config.password.chars().any(|c| !c.is_ascii_hexdigit()) {
-        bail!("Password is not a valid SHA256")
+    let dir_monitors = load_monitors_dir(path, &config.monitors_dir).await?;
+    config.monitors.extend(dir_monitors);
+
+    match HashKind::of(&config.password) {
+        None => {
+            bail!("password_hash is not a valid Argon2/bcrypt PHC string or SHA-256 digest")
+        }
+        Some(HashKind::Sha256) => tracing::warn!(
+            "password_hash is an unsalted SHA-256 digest, replace it with the output of `stb-mon hash-password`"
+        ),
+        _ => {}
     }
 
     if config.http.follow_redirects && config.http.max_follow_redirects.is_none() {
         bail!("max_follow_redirects must be set if follow_redirects is enabled");
     }
 
-    CONFIG.set(Arc::new(Mutex::new(config))).unwrap();
+    if !(0. ..=100.).contains(&config.sla_target) {
+        bail!("sla_target must be within 0..100");
+    }
 
-    Ok(())
+    if config.login.trusted_proxies == 0 {
+        bail!("login.trusted_proxies must be at least 1");
+    }
+
+    if let Some(backup) = &config.backup {
+        if backup.interval_hours == 0 || backup.keep == 0 {
+            bail!("backup.interval_hours and backup.keep must be at least 1");
+        }
+    }
+
+    config.secret_key()?;
+
+    if let Some(secret) = &config.login.totp_secret {
+        if !totp::is_valid_secret(secret) {
+            bail!("login.totp_secret must be a base32 encoded secret of at least 128 bits");
+        }
+    } else if config.login.require_totp_for_admins {
+        tracing::warn!("login.require_totp_for_admins is set but login.totp_secret is not, logging in with password_hash only gives viewer rights");
+    }
+
+    if let Some(oidc) = &config.oidc {
+        if Url::parse(&oidc.issuer).is_err() || Url::parse(&oidc.redirect_url).is_err() {
+            bail!("oidc.issuer and oidc.redirect_url must be valid URLs");
+        }
+        // ID tokens are trusted because they are fetched over TLS
+        if !oidc.issuer.starts_with("https://") {
+            tracing::warn!("oidc.issuer does not use HTTPS, only use this for testing");
+        }
+        if !oidc.scopes.iter().any(|s| s == "openid") {
+            bail!("oidc.scopes must contain openid");
+        }
+        for role in oidc.role_mapping.values().chain(oidc.default_role.as_ref()) {
+            if Role::from_str(role).is_none() {
+                bail!("Unknown role `{role}` in oidc, must be one of {{viewer, editor, admin}}");
+            }
+        }
+    }
+
+    for (i, page) in config.status_pages.iter().enumerate() {
+        if page.slug.is_empty()
+            || !page
+                .slug
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        {
+            bail!(
+                "Status page slug `{}` must only contain letters, digits, `-` and `_`",
+                page.slug
+            );
+        }
+
+        if config.status_pages[..i].iter().any(|p| p.slug == page.slug) {
+            bail!("Status page slug `{}` is used more than once", page.slug);
+        }
+    }
+
+    for (i, monitor) in config.monitors.iter().enumerate() {
+        monitor
+            .validate()
+            .with_context(|| format!("Invalid monitor `{}`", monitor.key))?;
+
+        if config.monitors[..i].iter().any(|m| m.key == monitor.key) {
+            bail!("Monitor key `{}` is used more than once", monitor.key);
+        }
+    }
+
+    Ok(config)
 }
@@ -0,0 +1,119 @@
+use std::collections::HashMap;
+
+use crate::{
+    config::CONFIG,
+    database::{
+        self,
+        audit::{self, AuditEntry},
+    },
+    monitor::Monitor,
+};
+
+// Actor of audit log entries for changes made by the config
+static AUDIT_ACTOR: &str = "config";
+
+#[derive(Debug, Default)]
+pub struct ReconcileStats {
+    pub created: usize,
+    pub updated: usize,
+    pub disabled: usize,
+}
+
+// Makes the monitors table match the monitors in the config: monitors with a new key are
+// created, existing ones are updated in place (keeping their records) and monitors whose key was
+// removed from the config are disabled and handed over to the web UI
+pub async fn reconcile() -> anyhow::Result<ReconcileStats> {
+    let wanted: Vec<Monitor> = {
+        let config = CONFIG.get().unwrap().lock().await;
+        config
+            .monitors
+            .iter()
+            .map(|m| {
+                Ok(Monitor {
+                    service_data: m.to_monitor_data()?,
+                    service_name: m.name.clone(),
+                    interval_mins: m.interval_mins.into(),
+                    enabled: m.enabled,
+                    timeout_secs: m.timeout_secs,
+                    config_key: Some(m.key.clone()),
+                    visibility: m.visibility,
+                    hide_location: m.hide_location,
+                })
+            })
+            .collect::<anyhow::Result<_>>()?
+    };
+
+    let mut existing: HashMap<String, (u64, Monitor)> = database::monitor::get_all(false)
+        .await?
+        .into_iter()
+        .filter_map(|(id, mon)| Some((mon.config_key.clone()?, (id, mon))))
+        .collect();
+
+    let mut stats = ReconcileStats::default();
+    for mon in wanted {
+        let key = mon.config_key.clone().unwrap_or_default();
+        let after = serde_json::to_value(&mon).ok();
+
+        match existing.remove(&key) {
+            Some((id, current)) => {
+                // compared directly, secrets are redacted in the JSON snapshots
+                if current == mon {
+                    continue;
+                }
+                let before = serde_json::to_value(&current).ok();
+
+                database::monitor::update(id, &mon).await?;
+                tracing::info!("Updated monitor {id} ({key}) from the config");
+                stats.updated += 1;
+                record(id, "monitor.edit", before, after).await;
+            }
+            None => {
+                let id = database::monitor::add(
+                    &mon.service_data,
+                    mon.interval_mins as u16,
+                    mon.service_name.clone(),
+                    mon.timeout_secs,
+                )
+                .await?;
+                // sets the key, enabled and the visibility
+                database::monitor::update(id, &mon).await?;
+                tracing::info!("Added monitor {id} ({key}) from the config");
+                stats.created += 1;
+                record(id, "monitor.add", None, after).await;
+            }
+        }
+    }
+
+    // removed from the config, disabled instead of deleted to keep the records
+    for (key, (id, current)) in existing {
+        let before = serde_json::to_value(&current).ok();
+        let mon = Monitor {
+            enabled: false,
+            config_key: None,
+            ..current
+        };
+        database::monitor::update(id, &mon).await?;
+        tracing::info!("Disabled monitor {id} ({key}), it was removed from the config");
+        stats.disabled += 1;
+        record(id, "monitor.edit", before, serde_json::to_value(&mon).ok()).await;
+    }
+
+    Ok(stats)
+}
+
+async fn record(
+    id: u64,
+    action: &'static str,
+    before: Option<serde_json::Value>,
+    after: Option<serde_json::Value>,
+) {
+    audit::record(AuditEntry {
+        actor: AUDIT_ACTOR.to_string(),
+        action,
+        target: Some(id.to_string()),
+        before,
+        after,
+        ..Default::default()
+    })
+    .await;
+}
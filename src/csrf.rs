@@ -0,0 +1,70 @@
+use axum::{
+    extract::Request,
+    http::{
+        header::{HOST, ORIGIN, REFERER},
+        Method, StatusCode,
+    },
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use url::Url;
+
+use crate::config::CONFIG;
+
+// Rejects state-changing requests that a browser sent from another site
+// Browsers always send the Origin (or at least the Referer) header with these requests, other
+// clients (scripts, CI) usually send neither and are allowed
+pub async fn origin_check(req: Request, next: Next) -> Response {
+    if [Method::GET, Method::HEAD, Method::OPTIONS].contains(req.method()) {
+        return next.run(req).await;
+    }
+
+    let headers = req.headers();
+    let source = headers
+        .get(ORIGIN)
+        .or_else(|| headers.get(REFERER))
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string);
+
+    let Some(source) = source else {
+        return next.run(req).await;
+    };
+
+    let host = headers
+        .get(HOST)
+        .and_then(|h| h.to_str().ok())
+        .unwrap_or_default();
+
+    let Some(origin) = Url::parse(&source)
+        .ok()
+        .filter(|u| u.has_host())
+        .map(|u| u.origin().ascii_serialization())
+    else {
+        return (StatusCode::FORBIDDEN, "Forbidden (bad Origin header)").into_response();
+    };
+
+    let same_host = origin
+        .split_once("://")
+        .is_some_and(|(_, origin_host)| origin_host.eq_ignore_ascii_case(host));
+    let allowed = same_host
+        || CONFIG
+            .get()
+            .unwrap()
+            .lock()
+            .await
+            .login
+            .allowed_origins
+            .iter()
+            .any(|o| o.trim_end_matches('/').eq_ignore_ascii_case(&origin));
+
+    if !allowed {
+        tracing::warn!(
+            "Rejected cross-site {} request to {} from {origin}",
+            req.method(),
+            req.uri().path()
+        );
+        return (StatusCode::FORBIDDEN, "Forbidden (cross-site request)").into_response();
+    }
+
+    next.run(req).await
+}
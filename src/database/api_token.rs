@@ -0,0 +1,231 @@
+use anyhow::bail;
+use itertools::Itertools;
+use rusqlite::{fallible_iterator::FallibleIterator, params, OptionalExtension};
+
+use crate::config::CONFIG;
+use crate::database::session::{generate_token, hash_token, TOKEN_BYTES};
+use crate::database::user::{effective_role, Role};
+use crate::database::DATABASE;
+use crate::time_util::current_unix_time;
+
+// API tokens start with this, so they can be told apart from session tokens (e.g. by secret
+// scanners)
+static TOKEN_PREFIX: &str = "stb_";
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum Scope {
+    // Read monitors, records and reports
+    Read,
+    // Add, change and delete monitors and their incidents
+    WriteMonitors,
+    // Send heartbeats to push monitors, reserved until push monitors exist
+    Push,
+}
+
+impl Scope {
+    pub const ALL: [Self; 3] = [Self::Read, Self::WriteMonitors, Self::Push];
+
+    pub fn from_str(val: &str) -> Option<Self> {
+        match val.trim().to_lowercase().as_str() {
+            "read" => Some(Self::Read),
+            "write_monitors" => Some(Self::WriteMonitors),
+            "push" => Some(Self::Push),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Read => "read",
+            Self::WriteMonitors => "write_monitors",
+            Self::Push => "push",
+        }
+    }
+}
+
+// Parses comma separated scopes, None if one of them is unknown
+pub fn parse_scopes(val: &str) -> Option<Vec<Scope>> {
+    val.split(',')
+        .filter(|s| !s.trim().is_empty())
+        .map(Scope::from_str)
+        .collect::<Option<Vec<Scope>>>()
+        .map(|scopes| scopes.into_iter().unique().collect())
+}
+
+fn scopes_to_string(scopes: &[Scope]) -> String {
+    scopes.iter().map(Scope::as_str).join(",")
+}
+
+// The user an API token belongs to, the token can do what both the role and the scopes allow
+#[derive(Debug)]
+pub struct TokenUser {
+    pub token_id: u64,
+    pub token_name: String,
+    // None = created with the password from the config
+    pub user_id: Option<u64>,
+    pub username: String,
+    pub role: Role,
+    pub scopes: Vec<Scope>,
+}
+
+#[derive(Debug)]
+pub struct ApiToken {
+    pub id: u64,
+    pub name: String,
+    pub user_id: Option<u64>,
+    pub username: String,
+    pub scopes: Vec<Scope>,
+    pub created_at: u64,
+    // None = never expires
+    pub expires_at: Option<u64>,
+    pub last_used_at: Option<u64>,
+}
+
+// returns the id and the token, the token can not be retrieved later
+pub async fn create(
+    name: &str,
+    user_id: Option<u64>,
+    scopes: &[Scope],
+    expires_at: Option<u64>,
+) -> anyhow::Result<(u64, String)> {
+    tracing::debug!("Adding API token - name: {name} | user: {user_id:?} | scopes: {scopes:?}");
+
+    let token = format!("{TOKEN_PREFIX}{}", generate_token()?);
+    let db = DATABASE.lock().await;
+    db.execute(
+        "INSERT INTO apiTokens (name, tokenHash, userId, scopes, createdAt, expiresAt) VALUES (?, ?, ?, ?, ?, ?)",
+        params![name, hash_token(&token), user_id, scopes_to_string(scopes), current_unix_time(), expires_at],
+    )?;
+
+    Ok((db.last_insert_rowid() as u64, token))
+}
+
+// returns None if the token is invalid or expired, updates the last use of the token otherwise
+pub async fn get_user(token: &str) -> anyhow::Result<Option<TokenUser>> {
+    if token.len() != TOKEN_PREFIX.len() + TOKEN_BYTES * 2 || !token.starts_with(TOKEN_PREFIX) {
+        return Ok(None);
+    }
+
+    let (require_totp, config_has_totp) = {
+        let config = CONFIG.get().unwrap().lock().await;
+        (
+            config.login.require_totp_for_admins,
+            config.login.totp_secret.is_some(),
+        )
+    };
+
+    let now = current_unix_time();
+    let db = DATABASE.lock().await;
+    let row = db
+        .query_row(
+            "SELECT t.id, t.name, t.userId, u.username, u.role, (u.totpEnabled OR u.oidcSubject IS NOT NULL), t.scopes FROM apiTokens t LEFT JOIN users u ON u.id = t.userId WHERE (t.tokenHash = ? AND (t.expiresAt IS NULL OR t.expiresAt > ?))",
+            params![hash_token(token), now],
+            |r| {
+                let token_id: u64 = r.get(0)?;
+                let token_name: String = r.get(1)?;
+                let user_id: Option<u64> = r.get(2)?;
+                let username: Option<String> = r.get(3)?;
+                let role: Option<u8> = r.get(4)?;
+                let has_second_factor: Option<bool> = r.get(5)?;
+                let scopes: String = r.get(6)?;
+
+                Ok((token_id, token_name, user_id, username, role, has_second_factor, scopes))
+            },
+        )
+        .optional()?;
+
+    // tokens can not do more than a session of their user
+    let user = match row {
+        None => None,
+        // tokens created with the password from the config have full access
+        Some((token_id, token_name, None, _, _, _, scopes)) => Some(TokenUser {
+            token_id,
+            token_name,
+            user_id: None,
+            username: "admin (config)".to_string(),
+            role: effective_role(Role::Admin, config_has_totp, require_totp),
+            scopes: parse_scopes(&scopes).unwrap_or_default(),
+        }),
+        Some((
+            token_id,
+            token_name,
+            Some(user_id),
+            Some(username),
+            Some(role),
+            has_second_factor,
+            scopes,
+        )) => Some(TokenUser {
+            token_id,
+            token_name,
+            user_id: Some(user_id),
+            username,
+            role: effective_role(
+                Role::from(role),
+                has_second_factor.unwrap_or_default(),
+                require_totp,
+            ),
+            scopes: parse_scopes(&scopes).unwrap_or_default(),
+        }),
+        // the user was deleted
+        Some(_) => None,
+    };
+
+    if let Some(user) = &user {
+        db.execute(
+            "UPDATE apiTokens SET lastUsedAt = ? WHERE id = ?",
+            params![now, user.token_id],
+        )?;
+    }
+
+    Ok(user)
+}
+
+// all tokens, including expired ones, only the ones of `user_id` if given
+pub async fn get_all(user_id: Option<Option<u64>>) -> anyhow::Result<Vec<ApiToken>> {
+    let lock = DATABASE.lock().await;
+    let mut stmt = lock.prepare(
+        "SELECT t.id, t.name, t.userId, u.username, t.scopes, t.createdAt, t.expiresAt, t.lastUsedAt FROM apiTokens t LEFT JOIN users u ON u.id = t.userId ORDER BY t.id",
+    )?;
+
+    let tokens: Vec<ApiToken> = stmt
+        .query([])?
+        .map(|r| {
+            let user_id: Option<u64> = r.get(2)?;
+            let username: Option<String> = r.get(3)?;
+            let scopes: String = r.get(4)?;
+
+            Ok(ApiToken {
+                id: r.get(0)?,
+                name: r.get(1)?,
+                user_id,
+                username: match user_id {
+                    None => "admin (config)".to_string(),
+                    Some(_) => username.unwrap_or_else(|| "deleted user".to_string()),
+                },
+                scopes: parse_scopes(&scopes).unwrap_or_default(),
+                created_at: r.get(5)?,
+                expires_at: r.get(6)?,
+                last_used_at: r.get(7)?,
+            })
+        })
+        .filter(|t| Ok(user_id.is_none_or(|id| t.user_id == id)))
+        .collect()?;
+
+    Ok(tokens)
+}
+
+pub async fn get_by_id(id: u64) -> Option<ApiToken> {
+    get_all(None).await.ok()?.into_iter().find(|t| t.id == id)
+}
+
+pub async fn util_delete(id: u64) -> anyhow::Result<()> {
+    let affected = DATABASE
+        .lock()
+        .await
+        .execute("DELETE FROM apiTokens WHERE id = ?", [id])?;
+    if affected == 0 {
+        bail!("No such API token")
+    }
+
+    Ok(())
+}
@@ -0,0 +1,144 @@
+use rusqlite::{fallible_iterator::FallibleIterator, params};
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::auth::Auth;
+use crate::database::DATABASE;
+use crate::time_util::current_unix_time;
+
+pub static DEFAULT_PER_PAGE: u64 = 50;
+pub static MAX_PER_PAGE: u64 = 500;
+
+#[derive(Debug, Default)]
+pub struct AuditEntry {
+    // Username (or attempted username for failed logins)
+    pub actor: String,
+    pub user_id: Option<u64>,
+    pub ip: Option<String>,
+    // e.g. monitor.add, login.failed
+    pub action: &'static str,
+    // ID of the changed monitor, user, ...
+    pub target: Option<String>,
+    pub details: Option<String>,
+    // State of the target before and after the action
+    pub before: Option<Value>,
+    pub after: Option<Value>,
+}
+
+impl AuditEntry {
+    pub fn by(auth: &Auth, action: &'static str) -> Self {
+        Self {
+            actor: auth.username.clone(),
+            user_id: auth.user_id,
+            ip: auth.ip.map(|ip| ip.to_string()),
+            action,
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct AuditLogEntry {
+    pub id: u64,
+    pub time: u64,
+    pub actor: String,
+    pub user_id: Option<u64>,
+    pub ip: Option<String>,
+    pub action: String,
+    pub target: Option<String>,
+    pub details: Option<String>,
+    pub before: Option<Value>,
+    pub after: Option<Value>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AuditLogPage {
+    // Starts at 1
+    pub page: u64,
+    pub per_page: u64,
+    // Number of entries on all pages
+    pub total: u64,
+    pub entries: Vec<AuditLogEntry>,
+}
+
+// Failing to write the audit log does not fail the action, it is only logged
+pub async fn record(entry: AuditEntry) {
+    let res = DATABASE.lock().await.execute(
+        "INSERT INTO audit_log (createdAt, actor, userId, ip, action, target, details, beforeJson, afterJson) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)",
+        params![
+            current_unix_time(),
+            entry.actor,
+            entry.user_id,
+            entry.ip,
+            entry.action,
+            entry.target,
+            entry.details,
+            entry.before.map(|v| v.to_string()),
+            entry.after.map(|v| v.to_string()),
+        ],
+    );
+
+    if let Err(e) = res {
+        tracing::error!("Failed to write {} to the audit log: {e}", entry.action);
+    }
+}
+
+// Newest entries first, `page` starts at 1
+pub async fn get_page(page: u64, per_page: u64) -> anyhow::Result<AuditLogPage> {
+    let lock = DATABASE.lock().await;
+    let total: u64 = lock.query_row("SELECT COUNT(*) FROM audit_log", [], |r| r.get(0))?;
+
+    let mut stmt = lock.prepare(
+        "SELECT id, createdAt, actor, userId, ip, action, target, details, beforeJson, afterJson FROM audit_log ORDER BY id DESC LIMIT ? OFFSET ?",
+    )?;
+    let entries: Vec<AuditLogEntry> = stmt
+        .query(params![per_page, page.saturating_sub(1) * per_page])?
+        .map(|r| {
+            let before: Option<String> = r.get(8)?;
+            let after: Option<String> = r.get(9)?;
+
+            Ok(AuditLogEntry {
+                id: r.get(0)?,
+                time: r.get(1)?,
+                actor: r.get(2)?,
+                user_id: r.get(3)?,
+                ip: r.get(4)?,
+                action: r.get(5)?,
+                target: r.get(6)?,
+                details: r.get(7)?,
+                before: before.and_then(|b| serde_json::from_str(&b).ok()),
+                after: after.and_then(|a| serde_json::from_str(&a).ok()),
+            })
+        })
+        .collect()?;
+
+    Ok(AuditLogPage {
+        page,
+        per_page,
+        total,
+        entries,
+    })
+}
+
+// Parses the `page` and `per_page` query fields, falling back to the first page / the default size
+pub fn page_params(page: Option<&String>, per_page: Option<&String>) -> (u64, u64) {
+    let page = page
+        .and_then(|p| p.parse().ok())
+        .filter(|p| *p > 0)
+        .unwrap_or(1)
+        // the offset of the page must fit into an SQLite integer
+        .min(i64::MAX as u64 / MAX_PER_PAGE);
+    let per_page = per_page
+        .and_then(|p| p.parse().ok())
+        .filter(|p| *p > 0)
+        .unwrap_or(DEFAULT_PER_PAGE)
+        .min(MAX_PER_PAGE);
+
+    (page, per_page)
+}
+
+// The monitor as stored, to be used as before/after snapshot
+pub async fn monitor_snapshot(id: u64) -> Option<Value> {
+    let mon = crate::database::monitor::get_by_id(id).await?;
+    serde_json::to_value(mon).ok()
+}
@@ -0,0 +1,181 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use anyhow::{bail, Context};
+use chrono::Utc;
+use rusqlite::{backup::Backup, Connection, OpenFlags};
+
+use super::{DATABASE_PATH, SCHEMA_VERSION};
+
+// Backups are named stbmon-<UTC time>.sqlite, so they sort by age
+static FILE_PREFIX: &str = "stbmon-";
+static FILE_SUFFIX: &str = ".sqlite";
+// Every stbmon database has them, a file without them is not a backup
+static REQUIRED_TABLES: [&str; 2] = ["monitors", "records"];
+// The current database is kept here when a backup is restored
+static BEFORE_RESTORE_SUFFIX: &str = ".before-restore";
+// Pages copied per step of a backup, the server can write to the database between the steps
+static BACKUP_STEP_PAGES: i32 = 1024;
+static BACKUP_STEP_PAUSE: Duration = Duration::from_millis(10);
+
+pub fn file_name() -> String {
+    format!(
+        "{FILE_PREFIX}{}{FILE_SUFFIX}",
+        Utc::now().format("%Y%m%d-%H%M%S")
+    )
+}
+
+// Creates an empty file only the owner can read, backups contain the secrets of the monitors
+fn create_private(path: &Path) -> std::io::Result<File> {
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+    options.open(path)
+}
+
+// Copies the database with the SQLite online backup API from a separate read-only connection, the
+// server keeps using the database while it runs
+// The copy is written to a temporary file first, `path` never contains a partial backup
+pub async fn backup_to(path: &Path) -> anyhow::Result<()> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    let tmp = PathBuf::from(tmp);
+    let _ = fs::remove_file(&tmp);
+
+    let dst = tmp.clone();
+    let res = tokio::task::spawn_blocking(move || -> anyhow::Result<()> {
+        let src = Connection::open_with_flags(DATABASE_PATH, OpenFlags::SQLITE_OPEN_READ_ONLY)
+            .context("Failed to open the database")?;
+        create_private(&dst)?;
+        let mut dst = Connection::open(dst)?;
+        // restarts if the server writes to the database during a step
+        Backup::new(&src, &mut dst)?.run_to_completion(
+            BACKUP_STEP_PAGES,
+            BACKUP_STEP_PAUSE,
+            None,
+        )?;
+
+        Ok(())
+    })
+    .await
+    .map_err(anyhow::Error::from)
+    .and_then(|res| res);
+
+    if let Err(e) = res.and_then(|_| Ok(fs::rename(&tmp, path)?)) {
+        let _ = fs::remove_file(&tmp);
+        return Err(e);
+    }
+
+    Ok(())
+}
+
+// Checks that the file is an intact stbmon database this version can read, returns its schema
+// version (0 for databases from before it was stored, they are migrated when opened)
+pub fn validate(path: &Path) -> anyhow::Result<i32> {
+    let db = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    let integrity: String = db
+        .query_row("PRAGMA integrity_check", [], |r| r.get(0))
+        .context("not a SQLite database")?;
+    if integrity != "ok" {
+        bail!("the database is corrupt: {integrity}");
+    }
+
+    for table in REQUIRED_TABLES {
+        let exists = db
+            .prepare("SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = ?")?
+            .exists([table])?;
+        if !exists {
+            bail!("not a stbmon database, table `{table}` is missing");
+        }
+    }
+
+    let version: i32 = db.pragma_query_value(None, "user_version", |r| r.get(0))?;
+    if version > SCHEMA_VERSION {
+        bail!("the database has schema version {version}, this version of stbmon supports up to {SCHEMA_VERSION}");
+    }
+
+    Ok(version)
+}
+
+// Replaces the database with a backup, fails while the server is running
+// The current database is kept as stbmon.sqlite.before-restore, returns the schema version
+pub fn restore(backup: &Path) -> anyhow::Result<i32> {
+    let version = validate(backup).with_context(|| format!("{} is invalid", backup.display()))?;
+
+    // held until the backup replaced the database, the server can not start in between
+    let _lock = super::lock().context("Stop the server before restoring")?;
+    // left behind by a server that crashed, they belong to the current database
+    for suffix in ["-journal", "-wal"] {
+        let path = format!("{DATABASE_PATH}{suffix}");
+        if Path::new(&path).exists() {
+            bail!("{path} exists, stop the server before restoring");
+        }
+    }
+
+    // copied next to the database first, renaming is atomic on the same file system
+    let tmp = format!("{DATABASE_PATH}.restoring");
+    fs::copy(backup, &tmp).context("Failed to copy the backup")?;
+    if Path::new(DATABASE_PATH).exists() {
+        fs::rename(
+            DATABASE_PATH,
+            format!("{DATABASE_PATH}{BEFORE_RESTORE_SUFFIX}"),
+        )?;
+    }
+    fs::rename(&tmp, DATABASE_PATH)?;
+
+    Ok(version)
+}
+
+// Backups in `dir` made by scheduled_backup, oldest first
+fn list_backups(dir: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let mut backups = vec![];
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let is_backup = path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .is_some_and(|n| n.starts_with(FILE_PREFIX) && n.ends_with(FILE_SUFFIX));
+        if is_backup {
+            backups.push(path);
+        }
+    }
+    backups.sort();
+
+    Ok(backups)
+}
+
+// Backs up into `dir` if the newest backup there is older than `interval`, then deletes all but
+// the newest `keep` backups, returns the path of the new backup
+pub async fn scheduled_backup(
+    dir: &Path,
+    interval: Duration,
+    keep: usize,
+) -> anyhow::Result<Option<PathBuf>> {
+    fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+
+    let newest = list_backups(dir)?.pop();
+    let is_due = newest.map_or(Ok::<_, anyhow::Error>(true), |path| {
+        let modified = fs::metadata(path)?.modified()?;
+        Ok(SystemTime::now()
+            .duration_since(modified)
+            .is_ok_and(|age| age >= interval))
+    })?;
+    if !is_due {
+        return Ok(None);
+    }
+
+    let path = dir.join(file_name());
+    backup_to(&path).await?;
+
+    let backups = list_backups(dir)?;
+    for old in &backups[..backups.len().saturating_sub(keep)] {
+        fs::remove_file(old).with_context(|| format!("Failed to delete {}", old.display()))?;
+    }
+
+    Ok(Some(path))
+}
@@ -0,0 +1,220 @@
+use crate::database::record::RecordResult;
+use crate::time_util::current_unix_time;
+use rusqlite::{fallible_iterator::FallibleIterator, params, Connection, OptionalExtension};
+use serde::Serialize;
+
+use super::DATABASE;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Incident {
+    pub id: u64,
+    pub monitor_id: u64,
+    // The result that opened the incident
+    pub result: RecordResult,
+    pub started_at: u64,
+    // None = incident is still ongoing
+    pub ended_at: Option<u64>,
+    pub first_info: String,
+    pub last_info: String,
+    // Written by an operator, e.g. a postmortem
+    pub comment: Option<String>,
+    // Published incidents are shown on the public status pages
+    pub published: bool,
+}
+
+impl Incident {
+    pub fn duration_secs(&self) -> u64 {
+        // the clock may have been set back since the incident started
+        self.ended_at
+            .unwrap_or_else(current_unix_time)
+            .saturating_sub(self.started_at)
+    }
+}
+
+static INCIDENT_COLUMNS: &str =
+    "id, monitorId, result, startedAt, endedAt, firstInfo, lastInfo, comment, published";
+
+fn incident_from_row(r: &rusqlite::Row) -> rusqlite::Result<Incident> {
+    let id: u64 = r.get(0)?;
+    let monitor_id: u64 = r.get(1)?;
+    let result: u8 = r.get(2)?;
+    let result = RecordResult::from(result);
+    let started_at: u64 = r.get(3)?;
+    let ended_at: Option<u64> = r.get(4)?;
+    let first_info: String = r.get(5)?;
+    let last_info: String = r.get(6)?;
+    let comment: Option<String> = r.get(7)?;
+    let published: bool = r.get(8)?;
+
+    Ok(Incident {
+        id,
+        monitor_id,
+        result,
+        started_at,
+        ended_at,
+        first_info,
+        last_info,
+        comment,
+        published,
+    })
+}
+
+fn get_open(db: &Connection, mon_id: u64) -> anyhow::Result<Option<Incident>> {
+    let incident = db
+        .query_row(
+            &format!("SELECT {INCIDENT_COLUMNS} FROM incidents WHERE monitorId = ? AND endedAt IS NULL ORDER BY startedAt DESC LIMIT 1"),
+            [mon_id],
+            incident_from_row,
+        )
+        .optional()?;
+
+    Ok(incident)
+}
+
+pub async fn incidents_from_mon(mon_id: u64) -> anyhow::Result<Vec<Incident>> {
+    let lock = DATABASE.lock().await;
+    let mut stmt = lock.prepare(&format!(
+        "SELECT {INCIDENT_COLUMNS} FROM incidents WHERE monitorId = ? ORDER BY startedAt DESC"
+    ))?;
+
+    let incidents: Vec<Incident> = stmt.query([mon_id])?.map(incident_from_row).collect()?;
+
+    Ok(incidents)
+}
+
+pub async fn recent(limit: u64) -> anyhow::Result<Vec<Incident>> {
+    let lock = DATABASE.lock().await;
+    let mut stmt = lock.prepare(&format!(
+        "SELECT {INCIDENT_COLUMNS} FROM incidents ORDER BY startedAt DESC LIMIT ?"
+    ))?;
+
+    let incidents: Vec<Incident> = stmt.query([limit])?.map(incident_from_row).collect()?;
+
+    Ok(incidents)
+}
+
+pub async fn published_from_mons(mon_ids: &[u64], since: u64) -> anyhow::Result<Vec<Incident>> {
+    let lock = DATABASE.lock().await;
+    let mut stmt = lock.prepare(&format!(
+        "SELECT {INCIDENT_COLUMNS} FROM incidents WHERE published = 1 AND startedAt >= ? ORDER BY startedAt DESC"
+    ))?;
+
+    let incidents: Vec<Incident> = stmt
+        .query([since])?
+        .map(incident_from_row)
+        .filter(|i| Ok(mon_ids.contains(&i.monitor_id)))
+        .collect()?;
+
+    Ok(incidents)
+}
+
+// returns the new published state, None if there is no such incident
+pub async fn toggle_published(id: u64) -> anyhow::Result<Option<bool>> {
+    let db = DATABASE.lock().await;
+    let Some(published) = db
+        .query_row("SELECT published FROM incidents WHERE id = ?", [id], |r| {
+            r.get::<_, bool>(0)
+        })
+        .optional()?
+    else {
+        return Ok(None);
+    };
+
+    db.execute(
+        "UPDATE incidents SET published = ? WHERE id = ?",
+        params![!published, id],
+    )?;
+
+    Ok(Some(!published))
+}
+
+// returns false if there is no such incident
+pub async fn set_comment(id: u64, comment: Option<String>) -> anyhow::Result<bool> {
+    let affected = DATABASE.lock().await.execute(
+        "UPDATE incidents SET comment = ? WHERE id = ?",
+        params![comment, id],
+    )?;
+
+    Ok(affected != 0)
+}
+
+// Opens, extends or closes the incident of a monitor depending on the result of its latest check,
+// returns the incident as it is afterwards
+// `db` is the transaction the result is stored in, so concurrent checks of the monitor can not
+// open an incident twice
+pub fn util_track_result(
+    db: &Connection,
+    result: &RecordResult,
+    mon_id: u64,
+    info: &str,
+) -> anyhow::Result<Option<Incident>> {
+    let open = get_open(db, mon_id)?;
+    let now = current_unix_time();
+
+    let incident = match (open, result) {
+        (None, RecordResult::Ok) => None,
+        (None, _) => {
+            tracing::info!("Opening incident for monitor {mon_id}: {info}");
+            db.execute(
+                "INSERT INTO incidents (monitorId, result, startedAt, firstInfo, lastInfo) VALUES (?, ?, ?, ?, ?)",
+                params![mon_id, *result as u8, now, info, info],
+            )?;
+
+            Some(Incident {
+                id: db.last_insert_rowid() as u64,
+                monitor_id: mon_id,
+                result: *result,
+                started_at: now,
+                ended_at: None,
+                first_info: info.to_string(),
+                last_info: info.to_string(),
+                comment: None,
+                published: false,
+            })
+        }
+        (Some(mut incident), RecordResult::Ok) => {
+            tracing::info!("Closing incident {} of monitor {mon_id}", incident.id);
+            db.execute(
+                "UPDATE incidents SET endedAt = ?, durationSecs = ? WHERE id = ?",
+                params![now, now.saturating_sub(incident.started_at), incident.id],
+            )?;
+
+            incident.ended_at = Some(now);
+            Some(incident)
+        }
+        (Some(mut incident), _) => {
+            db.execute(
+                "UPDATE incidents SET lastInfo = ? WHERE id = ?",
+                params![info, incident.id],
+            )?;
+
+            incident.last_info = info.to_string();
+            Some(incident)
+        }
+    };
+
+    Ok(incident)
+}
+
+// Closes the open incident of a monitor that is no longer checked, e.g. because it was disabled
+pub async fn util_close_open(mon_id: u64) -> anyhow::Result<()> {
+    let now = current_unix_time();
+    let affected = DATABASE.lock().await.execute(
+        "UPDATE incidents SET endedAt = ?1, durationSecs = MAX(?1 - startedAt, 0) WHERE monitorId = ?2 AND endedAt IS NULL",
+        params![now, mon_id],
+    )?;
+    if affected != 0 {
+        tracing::info!("Closed the open incident of monitor {mon_id}, it is no longer checked");
+    }
+
+    Ok(())
+}
+
+pub async fn util_delete_from_mon(mon_id: u64) -> anyhow::Result<()> {
+    DATABASE
+        .lock()
+        .await
+        .execute("DELETE FROM incidents WHERE monitorId = ?", [mon_id])?;
+
+    Ok(())
+}
@@ -1,17 +1,34 @@
+use anyhow::bail;
 use lazy_static::lazy_static;
 use rusqlite::Connection;
+use std::fs::{File, OpenOptions, TryLockError};
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::sync::Mutex;
 
+pub mod api_token;
+pub mod audit;
+pub mod backup;
+pub mod incident;
 pub mod monitor;
 pub mod record;
 pub mod session;
+pub mod user;
 
 static DATABASE_PATH: &str = "./stbmon.sqlite";
+// Locked by the server while it runs, so backups are not restored under it
+static LOCK_PATH: &str = "./stbmon.sqlite.lock";
+// Stored as `user_version`, increased when the tables change so older versions of stbmon do not
+// restore backups they can not read
+pub static SCHEMA_VERSION: i32 = 1;
 
 lazy_static! {
     pub static ref DATABASE: Arc<Mutex<Connection>> = {
         let database = Connection::open(DATABASE_PATH).expect("Failed to open database");
+        // the CLI can use the database while the server is running
+        database
+            .busy_timeout(Duration::from_secs(5))
+            .expect("Failed to set busy timeout");
         database
             .execute(
                 r"
@@ -47,14 +64,204 @@ lazy_static! {
             .execute(
                 r"
         CREATE TABLE IF NOT EXISTS sessions (
-            token VARCHAR PRIMARY KEY,
-            expiresAt INTEGER
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            token VARCHAR NOT NULL UNIQUE,
+            expiresAt INTEGER,
+            userId INTEGER,
+            createdAt INTEGER,
+            ip VARCHAR,
+            userAgent VARCHAR
+        );
+        ",
+                [],
+            )
+            .expect("Failed to run query");
+
+        database
+            .execute(
+                r"
+        CREATE TABLE IF NOT EXISTS incidents (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            monitorId INTEGER NOT NULL,
+            result INTEGER NOT NULL,
+            startedAt INTEGER NOT NULL,
+            endedAt INTEGER,
+            durationSecs INTEGER,
+            firstInfo VARCHAR NOT NULL,
+            lastInfo VARCHAR NOT NULL,
+            comment VARCHAR,
+            published BOOLEAN DEFAULT 0
+        );
+        ",
+                [],
+            )
+            .expect("Failed to run query");
+
+        database
+            .execute(
+                r"
+        CREATE TABLE IF NOT EXISTS users (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            username VARCHAR NOT NULL UNIQUE,
+            passwordHash VARCHAR NOT NULL,
+            role INTEGER NOT NULL,
+            createdAt INTEGER NOT NULL
+        );
+        ",
+                [],
+            )
+            .expect("Failed to run query");
+
+        database
+            .execute(
+                r"
+        CREATE TABLE IF NOT EXISTS apiTokens (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            name VARCHAR NOT NULL,
+            tokenHash VARCHAR NOT NULL UNIQUE,
+            userId INTEGER,
+            scopes VARCHAR NOT NULL,
+            createdAt INTEGER NOT NULL,
+            expiresAt INTEGER,
+            lastUsedAt INTEGER
+        );
+        ",
+                [],
+            )
+            .expect("Failed to run query");
+
+        database
+            .execute(
+                r"
+        CREATE TABLE IF NOT EXISTS audit_log (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            createdAt INTEGER NOT NULL,
+            actor VARCHAR NOT NULL,
+            userId INTEGER,
+            ip VARCHAR,
+            action VARCHAR NOT NULL,
+            target VARCHAR,
+            details VARCHAR,
+            beforeJson VARCHAR,
+            afterJson VARCHAR
         );
         ",
                 [],
             )
             .expect("Failed to run query");
 
+        // databases created before monitors could be defined in the config
+        add_column_if_missing(&database, "monitors", "configKey", "VARCHAR")
+            .expect("Failed to run query");
+
+        // databases created before monitors had a visibility
+        add_column_if_missing(&database, "monitors", "visibility", "INTEGER DEFAULT 0")
+            .expect("Failed to run query");
+        add_column_if_missing(&database, "monitors", "hideLocation", "BOOLEAN DEFAULT 0")
+            .expect("Failed to run query");
+
+        // databases created before incidents could be published
+        add_column_if_missing(&database, "incidents", "published", "BOOLEAN DEFAULT 0")
+            .expect("Failed to run query");
+
+        // databases created before sessions belonged to users and stored where they came from
+        add_column_if_missing(&database, "sessions", "userId", "INTEGER")
+            .expect("Failed to run query");
+        add_column_if_missing(&database, "sessions", "createdAt", "INTEGER")
+            .expect("Failed to run query");
+        add_column_if_missing(&database, "sessions", "ip", "VARCHAR")
+            .expect("Failed to run query");
+        add_column_if_missing(&database, "sessions", "userAgent", "VARCHAR")
+            .expect("Failed to run query");
+
+        // databases created before sessions had an ID, the implicit rowid was used which VACUUM
+        // can change, the table is rebuilt as a primary key can not be added
+        if !column_exists(&database, "sessions", "id").expect("Failed to run query") {
+            database
+                .execute_batch(
+                    r"
+        BEGIN;
+        ALTER TABLE sessions RENAME TO sessions_old;
+        CREATE TABLE sessions (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            token VARCHAR NOT NULL UNIQUE,
+            expiresAt INTEGER,
+            userId INTEGER,
+            createdAt INTEGER,
+            ip VARCHAR,
+            userAgent VARCHAR
+        );
+        INSERT INTO sessions (token, expiresAt, userId, createdAt, ip, userAgent)
+            SELECT token, expiresAt, userId, createdAt, ip, userAgent FROM sessions_old;
+        DROP TABLE sessions_old;
+        COMMIT;
+        ",
+                )
+                .expect("Failed to run query");
+        }
+
+        // databases created before two-factor authentication
+        add_column_if_missing(&database, "users", "totpSecret", "VARCHAR")
+            .expect("Failed to run query");
+        add_column_if_missing(&database, "users", "totpEnabled", "BOOLEAN DEFAULT 0")
+            .expect("Failed to run query");
+        add_column_if_missing(&database, "users", "totpLastStep", "INTEGER")
+            .expect("Failed to run query");
+        add_column_if_missing(&database, "users", "totpRecoveryCodes", "VARCHAR")
+            .expect("Failed to run query");
+
+        // databases created before single sign-on
+        add_column_if_missing(&database, "users", "oidcSubject", "VARCHAR")
+            .expect("Failed to run query");
+
+        // databases created by newer versions keep their version
+        let version: i32 = database
+            .pragma_query_value(None, "user_version", |r| r.get(0))
+            .expect("Failed to run query");
+        if version < SCHEMA_VERSION {
+            database
+                .pragma_update(None, "user_version", SCHEMA_VERSION)
+                .expect("Failed to run query");
+        }
+
         Arc::new(Mutex::new(database))
     };
 }
+
+// Locks the database until the returned file is dropped, fails if it is already locked
+pub fn lock() -> anyhow::Result<File> {
+    let file = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(LOCK_PATH)?;
+    match file.try_lock() {
+        Ok(()) => Ok(file),
+        Err(TryLockError::WouldBlock) => bail!("{LOCK_PATH} is locked by a running server"),
+        Err(TryLockError::Error(e)) => Err(e.into()),
+    }
+}
+
+fn column_exists(database: &Connection, table: &str, column: &str) -> rusqlite::Result<bool> {
+    database
+        .prepare(&format!(
+            "SELECT 1 FROM pragma_table_info('{table}') WHERE name = ?"
+        ))?
+        .exists([column])
+}
+
+fn add_column_if_missing(
+    database: &Connection,
+    table: &str,
+    column: &str,
+    definition: &str,
+) -> rusqlite::Result<()> {
+    if !column_exists(database, table, column)? {
+        database.execute(
+            &format!("ALTER TABLE {table} ADD COLUMN {column} {definition}"),
+            [],
+        )?;
+    }
+
+    Ok(())
+}
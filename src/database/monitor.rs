@@ -3,22 +3,27 @@ use rusqlite::fallible_iterator::FallibleIterator;
 use rusqlite::params;
 use std::collections::HashMap;
 
-use crate::monitor::{Monitor, MonitorData};
+use crate::{
+    monitor::{Monitor, MonitorData, Visibility},
+    secrets::{self, SecretKey},
+};
 
 use super::DATABASE;
 
 // returns the id of the added monitor
 pub async fn add(
-    service_data: MonitorData,
+    service_data: &MonitorData,
     interval_mins: u16,
     service_name: String,
     timeout_s: u16,
 ) -> anyhow::Result<u64> {
+    // the service data is not logged, it contains secrets
     tracing::debug!(
-        "Adding monitor - service_data: {service_data:?} | interval_mins: {interval_mins}"
+        "Adding monitor - location: {} | interval_mins: {interval_mins}",
+        service_data.service_location_str()
     );
 
-    let service_data = rmp_serde::to_vec(&service_data)?;
+    let service_data = rmp_serde::to_vec(&service_data.encrypted(secrets::key().as_ref())?)?;
     let db = DATABASE.lock().await;
 
     db.execute(
@@ -35,20 +40,96 @@ pub async fn add(
     Ok(id)
 }
 
+// A monitor added by util_import
+pub struct NewMonitor {
+    pub service_data: MonitorData,
+    pub interval_mins: u16,
+    pub service_name: String,
+    pub timeout_secs: u16,
+    pub visibility: Visibility,
+    pub hide_location: bool,
+    pub enabled: bool,
+}
+
+// Adds the monitors in one transaction, if `replace` is set all monitors that are not managed by
+// the config are deleted in it first. Returns the number of deleted monitors and the new ids
+pub async fn util_import(
+    monitors: &[NewMonitor],
+    replace: bool,
+) -> anyhow::Result<(usize, Vec<u64>)> {
+    let key = secrets::key();
+    let service_data = monitors
+        .iter()
+        .map(|m| Ok(rmp_serde::to_vec(&m.service_data.encrypted(key.as_ref())?)?))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    let mut db = DATABASE.lock().await;
+    let tx = db.transaction()?;
+
+    let mut deleted = 0;
+    if replace {
+        tx.execute(
+            "DELETE FROM records WHERE monitorId IN (SELECT id FROM monitors WHERE configKey IS NULL)",
+            [],
+        )?;
+        tx.execute(
+            "DELETE FROM incidents WHERE monitorId IN (SELECT id FROM monitors WHERE configKey IS NULL)",
+            [],
+        )?;
+        deleted = tx.execute("DELETE FROM monitors WHERE configKey IS NULL", [])?;
+    }
+
+    let mut ids = vec![];
+    for (mon, service_data) in monitors.iter().zip(service_data) {
+        tx.execute(
+            "INSERT INTO monitors (serviceDataMp, intervalMins, serviceName, timeoutSecs, visibility, hideLocation, enabled) VALUES (?, ?, ?, ?, ?, ?, ?)",
+            params![
+                service_data,
+                mon.interval_mins,
+                mon.service_name,
+                mon.timeout_secs,
+                mon.visibility as u8,
+                mon.hide_location,
+                mon.enabled
+            ],
+        )?;
+        ids.push(tx.last_insert_rowid() as u64);
+    }
+    tx.commit()?;
+
+    Ok((deleted, ids))
+}
+
+// Secrets that can not be decrypted stay encrypted, checks using them fail instead of sending them
+fn decrypt(id: u64, service_data: MonitorData, key: Option<&SecretKey>) -> MonitorData {
+    match service_data.decrypted(key) {
+        Ok(data) => data,
+        Err(e) => {
+            tracing::error!("Failed to decrypt the secrets of monitor {id}: {e}");
+            service_data
+        }
+    }
+}
+
 pub async fn get_by_id(id: u64) -> Option<Monitor> {
+    let key = secrets::key();
     let mon: Monitor = DATABASE
         .lock()
         .await
         .query_row(
-            "SELECT serviceDataMp, intervalMins, enabled, serviceName, timeoutSecs FROM monitors WHERE id = ?",
+            "SELECT serviceDataMp, intervalMins, enabled, serviceName, timeoutSecs, configKey, visibility, hideLocation FROM monitors WHERE id = ?",
             [id],
             |r| {
                 let service_data: Vec<u8> = r.get(0).unwrap();
                 let service_data: MonitorData = rmp_serde::from_slice(&service_data).unwrap();
+                let service_data = decrypt(id, service_data, key.as_ref());
                 let interval_mins: u64 = r.get(1).unwrap();
                 let enabled: bool = r.get(2).unwrap();
                 let service_name: String = r.get(3).unwrap();
                 let timeout_secs: u16 = r.get(4).unwrap();
+                let config_key: Option<String> = r.get(5).unwrap();
+                let visibility: u8 = r.get(6).unwrap();
+                let hide_location: bool = r.get(7).unwrap();
 
                 let mon = Monitor {
                     service_data,
@@ -56,6 +137,9 @@ pub async fn get_by_id(id: u64) -> Option<Monitor> {
                     interval_mins,
                     enabled,
                     timeout_secs,
+                    config_key,
+                    visibility: visibility.into(),
+                    hide_location,
                 };
                 Ok(mon)
             },
@@ -66,9 +150,10 @@ pub async fn get_by_id(id: u64) -> Option<Monitor> {
 }
 
 pub async fn get_all(enabled_only: bool) -> anyhow::Result<HashMap<u64, Monitor>> {
+    let key = secrets::key();
     let lock = DATABASE.lock().await;
     let mut stmt = lock.prepare(&format!(
-  "SELECT id, serviceDataMp, intervalMins, enabled, serviceName, timeoutSecs FROM monitors {}",
+  "SELECT id, serviceDataMp, intervalMins, enabled, serviceName, timeoutSecs, configKey, visibility, hideLocation FROM monitors {}",
   if enabled_only {
    "WHERE enabled = 1"
   } else {
@@ -81,10 +166,14 @@ pub async fn get_all(enabled_only: bool) -> anyhow::Result<HashMap<u64, Monitor>
             let id: u64 = r.get(0).unwrap();
             let service_data: Vec<u8> = r.get(1).unwrap();
             let service_data: MonitorData = rmp_serde::from_slice(&service_data).unwrap();
+            let service_data = decrypt(id, service_data, key.as_ref());
             let interval_mins: u64 = r.get(2).unwrap();
             let enabled: bool = r.get(3).unwrap();
             let service_name: String = r.get(4).unwrap();
             let timeout_secs: u16 = r.get(5).unwrap();
+            let config_key: Option<String> = r.get(6).unwrap();
+            let visibility: u8 = r.get(7).unwrap();
+            let hide_location: bool = r.get(8).unwrap();
 
             let mon = Monitor {
                 service_data,
@@ -92,6 +181,9 @@ pub async fn get_all(enabled_only: bool) -> anyhow::Result<HashMap<u64, Monitor>
                 interval_mins,
                 enabled,
                 timeout_secs,
+                config_key,
+                visibility: visibility.into(),
+                hide_location,
             };
 
             Ok((id, mon))
@@ -102,6 +194,96 @@ pub async fn get_all(enabled_only: bool) -> anyhow::Result<HashMap<u64, Monitor>
     Ok(res)
 }
 
+// Replaces everything about a monitor, its records are kept
+pub async fn update(id: u64, mon: &Monitor) -> anyhow::Result<()> {
+    let service_data = rmp_serde::to_vec(&mon.service_data.encrypted(secrets::key().as_ref())?)?;
+    DATABASE.lock().await.execute(
+        "UPDATE monitors SET serviceDataMp = ?, intervalMins = ?, enabled = ?, serviceName = ?, timeoutSecs = ?, configKey = ?, visibility = ?, hideLocation = ? WHERE id = ?",
+        params![
+            service_data,
+            mon.interval_mins,
+            mon.enabled,
+            mon.service_name,
+            mon.timeout_secs,
+            mon.config_key,
+            mon.visibility as u8,
+            mon.hide_location,
+            id
+        ],
+    )?;
+    if !mon.enabled {
+        super::incident::util_close_open(id).await?;
+    }
+
+    Ok(())
+}
+
+// Encrypts the secrets of monitors stored while `secret_key` was not set, returns how many
+// monitors were changed
+pub async fn encrypt_plain_secrets() -> anyhow::Result<usize> {
+    let lock = DATABASE.lock().await;
+    let mut stmt = lock.prepare("SELECT id, serviceDataMp FROM monitors")?;
+    let plain: Vec<(u64, MonitorData)> = stmt
+        .query([])?
+        .map(|r| {
+            let id: u64 = r.get(0).unwrap();
+            let service_data: Vec<u8> = r.get(1).unwrap();
+            let service_data: MonitorData = rmp_serde::from_slice(&service_data).unwrap();
+
+            Ok((id, service_data))
+        })
+        .filter(|(_, service_data)| Ok(service_data.has_plain_secrets()))
+        .collect()?;
+
+    let Some(key) = secrets::key() else {
+        if !plain.is_empty() {
+            tracing::warn!(
+                "{} monitors have secrets stored in plain text, set secret_key to encrypt them",
+                plain.len()
+            );
+        }
+        return Ok(0);
+    };
+
+    for (id, service_data) in &plain {
+        let service_data = rmp_serde::to_vec(&service_data.encrypted(Some(&key))?)?;
+        lock.execute(
+            "UPDATE monitors SET serviceDataMp = ? WHERE id = ?",
+            params![service_data, id],
+        )?;
+    }
+
+    Ok(plain.len())
+}
+
+// Fails if secrets of monitors can not be decrypted with `key`, e.g. because secret_key was changed
+// or removed, they would be lost
+pub async fn check_secret_key(key: Option<&SecretKey>) -> anyhow::Result<()> {
+    let lock = DATABASE.lock().await;
+    let mut stmt = lock.prepare("SELECT id, serviceDataMp FROM monitors")?;
+    let undecryptable: Vec<String> = stmt
+        .query([])?
+        .map(|r| {
+            let id: u64 = r.get(0)?;
+            let service_data: Vec<u8> = r.get(1)?;
+            let service_data: MonitorData = rmp_serde::from_slice(&service_data).unwrap();
+
+            Ok((id, service_data))
+        })
+        .filter(|(_, service_data)| Ok(service_data.decrypted(key).is_err()))
+        .map(|(id, _)| Ok(id.to_string()))
+        .collect()?;
+
+    if !undecryptable.is_empty() {
+        bail!(
+            "The secrets of monitors {} can not be decrypted with this secret_key, set the key they were encrypted with",
+            undecryptable.join(", ")
+        );
+    }
+
+    Ok(())
+}
+
 pub async fn util_delete(id: u64) -> anyhow::Result<()> {
     let affected = DATABASE
         .lock()
@@ -116,6 +298,24 @@ pub async fn util_delete(id: u64) -> anyhow::Result<()> {
         .await
         .execute("DELETE FROM records WHERE monitorId = ?", [id])?;
 
+    super::incident::util_delete_from_mon(id).await?;
+
+    Ok(())
+}
+
+pub async fn set_visibility(
+    id: u64,
+    visibility: Visibility,
+    hide_location: bool,
+) -> anyhow::Result<()> {
+    let affected = DATABASE.lock().await.execute(
+        "UPDATE monitors SET visibility = ?, hideLocation = ? WHERE id = ?",
+        params![visibility as u8, hide_location, id],
+    )?;
+    if affected == 0 {
+        bail!("No such monitor")
+    }
+
     Ok(())
 }
 
@@ -130,6 +330,9 @@ pub async fn toggle(id: u64) -> anyhow::Result<bool> {
         "UPDATE monitors SET enabled = ? WHERE id = ?",
         params![!enabled, id],
     )?;
+    if enabled {
+        super::incident::util_close_open(id).await?;
+    }
 
     Ok(!enabled)
 }
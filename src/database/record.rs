@@ -1,10 +1,19 @@
-use crate::{monitor::MonitorResult, time_util::current_unix_time};
-use rusqlite::{fallible_iterator::FallibleIterator, params};
+use crate::{
+    live::{self, LiveEvent},
+    monitor::{MonitorResult, Visibility},
+    time_util::current_unix_time,
+};
+use anyhow::bail;
+use rusqlite::{fallible_iterator::FallibleIterator, params, Connection, OptionalExtension};
+use serde::Serialize;
 
-use super::DATABASE;
+use super::{incident, DATABASE};
 
-#[derive(Debug)]
-#[allow(dead_code)]
+// Shown instead of the info of checks to users a monitor hides its location from, the info can
+// contain the location
+pub static HIDDEN_INFO: &str = "Hidden";
+
+#[derive(Debug, Clone, Serialize)]
 pub struct MonitorRecord {
     pub time_checked: u64,
     pub result: RecordResult,
@@ -15,7 +24,7 @@ pub struct MonitorRecord {
     pub info: String,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone, Copy, Serialize)]
 #[repr(u8)]
 pub enum RecordResult {
     Ok,
@@ -36,25 +45,30 @@ impl From<u8> for RecordResult {
     }
 }
 
-pub async fn add(
+fn add(
+    db: &Connection,
     result: RecordResult, // None results are the ones created when a monitor is added
     response_time: Option<u64>,
     monitor_id: u64,
     info: String,
-) -> anyhow::Result<()> {
+) -> anyhow::Result<MonitorRecord> {
     tracing::debug!(
         "Adding record - result: {result:?} | response_time: {response_time:?} | monitor_id: {monitor_id} | info: {info}"
     );
 
-    DATABASE
-        .lock()
-        .await
-        .execute(
-            "INSERT INTO records (monitorId, result, responseDeltaMs, checkedAt, info) VALUES (?, ?, ?, ?, ?)",
-            params![monitor_id, result as u8, response_time, current_unix_time(), info],
-        )?;
+    let time_checked = current_unix_time();
+    db.execute(
+        "INSERT INTO records (monitorId, result, responseDeltaMs, checkedAt, info) VALUES (?, ?, ?, ?, ?)",
+        params![monitor_id, result as u8, response_time, time_checked, info],
+    )?;
 
-    Ok(())
+    Ok(MonitorRecord {
+        time_checked,
+        result,
+        response_time_ms: response_time,
+        monitor_id,
+        info,
+    })
 }
 
 pub async fn util_last_record(mon_id: u64) -> anyhow::Result<MonitorRecord> {
@@ -118,21 +132,150 @@ pub async fn records_from_mon(mon_id: u64) -> anyhow::Result<Vec<MonitorRecord>>
     Ok(records)
 }
 
-pub async fn util_add_result(res: MonitorResult, mon_id: u64) -> anyhow::Result<()> {
-    match res {
-        MonitorResult::Ok(response_time_ms, info) => {
-            add(RecordResult::Ok, Some(response_time_ms as _), mon_id, info).await
-        }
-        MonitorResult::UnexpectedResponse(response_time_ms, info) => {
-            add(
-                RecordResult::Unexpected,
-                Some(response_time_ms as _),
-                mon_id,
+pub async fn records_from_mon_since(mon_id: u64, since: u64) -> anyhow::Result<Vec<MonitorRecord>> {
+    let lock = DATABASE.lock().await;
+    let mut stmt = lock
+        .prepare("SELECT monitorId, result, responseDeltaMs, checkedAt, info FROM records WHERE monitorId = ? AND checkedAt >= ? ORDER BY checkedAt DESC")?;
+
+    let records: Vec<MonitorRecord> = stmt
+        .query(params![mon_id, since])?
+        .map(|r| {
+            let monitor_id: u64 = r.get(0).unwrap();
+            let result: u8 = r.get(1).unwrap();
+            let result = RecordResult::from(result);
+            let response_time_ms: Option<u64> = r.get(2).unwrap();
+            let time_checked: u64 = r.get(3).unwrap();
+            let info: String = r.get(4).unwrap();
+
+            let rec = MonitorRecord {
+                time_checked,
+                result,
+                response_time_ms,
+                monitor_id,
                 info,
-            )
-            .await
-        }
-        MonitorResult::Down(info) => add(RecordResult::Down, None, mon_id, info).await,
-        MonitorResult::IoError(err) => add(RecordResult::Err, None, mon_id, err).await,
+            };
+
+            Ok(rec)
+        })
+        .collect()
+        .unwrap();
+
+    Ok(records)
+}
+
+// records checked within since..=until, newest first
+pub async fn records_from_mon_between(
+    mon_id: u64,
+    since: u64,
+    until: u64,
+) -> anyhow::Result<Vec<MonitorRecord>> {
+    let lock = DATABASE.lock().await;
+    let mut stmt = lock
+        .prepare("SELECT monitorId, result, responseDeltaMs, checkedAt, info FROM records WHERE monitorId = ? AND checkedAt >= ? AND checkedAt <= ? ORDER BY checkedAt DESC")?;
+
+    let records: Vec<MonitorRecord> = stmt
+        .query(params![mon_id, since, until])?
+        .map(|r| {
+            let monitor_id: u64 = r.get(0).unwrap();
+            let result: u8 = r.get(1).unwrap();
+            let result = RecordResult::from(result);
+            let response_time_ms: Option<u64> = r.get(2).unwrap();
+            let time_checked: u64 = r.get(3).unwrap();
+            let info: String = r.get(4).unwrap();
+
+            let rec = MonitorRecord {
+                time_checked,
+                result,
+                response_time_ms,
+                monitor_id,
+                info,
+            };
+
+            Ok(rec)
+        })
+        .collect()
+        .unwrap();
+
+    Ok(records)
+}
+
+// the last record that was checked before `time`
+pub async fn util_record_before(mon_id: u64, time: u64) -> anyhow::Result<Option<MonitorRecord>> {
+    let record = DATABASE
+        .lock()
+        .await
+        .query_row(
+            "SELECT monitorId, result, responseDeltaMs, checkedAt, info FROM records WHERE monitorId = ? AND checkedAt < ? ORDER BY checkedAt DESC LIMIT 1",
+            params![mon_id, time],
+            |r| {
+                let monitor_id: u64 = r.get(0).unwrap();
+                let result: u8 = r.get(1).unwrap();
+                let result = RecordResult::from(result);
+                let response_time_ms: Option<u64> = r.get(2).unwrap();
+                let time_checked: u64 = r.get(3).unwrap();
+                let info: String = r.get(4).unwrap();
+
+                let rec = MonitorRecord {
+                    time_checked,
+                    result,
+                    response_time_ms,
+                    monitor_id,
+                    info,
+                };
+
+                Ok(rec)
+            },
+        )
+        .optional()?;
+
+    Ok(record)
+}
+
+// Stores the result of a check and tracks the incident of the monitor in one transaction, fails if
+// the monitor was deleted or disabled while it was checked
+pub async fn util_add_result(res: MonitorResult, mon_id: u64) -> anyhow::Result<MonitorRecord> {
+    let (result, response_time, info) = res.into_parts();
+
+    let mut db = DATABASE.lock().await;
+    let tx = db.transaction()?;
+    let monitor: Option<(bool, u8, bool)> = tx
+        .query_row(
+            "SELECT enabled, visibility, hideLocation FROM monitors WHERE id = ?",
+            [mon_id],
+            |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?)),
+        )
+        .optional()?;
+    let Some((true, visibility, hide_location)) = monitor else {
+        bail!("Monitor {mon_id} was deleted or disabled while it was checked");
+    };
+    let visibility = Visibility::from(visibility);
+
+    let previous = tx
+        .query_row(
+            "SELECT result FROM records WHERE monitorId = ? ORDER BY checkedAt DESC LIMIT 1",
+            [mon_id],
+            |r| r.get::<_, u8>(0),
+        )
+        .optional()?
+        .map(RecordResult::from);
+    let record = add(&tx, result, response_time, mon_id, info.clone())?;
+    let incident = incident::util_track_result(&tx, &result, mon_id, &info)?;
+    tx.commit()?;
+    drop(db);
+
+    live::publish(visibility, hide_location, LiveEvent::Record(record.clone()));
+    if previous != Some(result) {
+        live::publish(
+            visibility,
+            hide_location,
+            LiveEvent::StateChange {
+                monitor_id: mon_id,
+                from: previous,
+                to: result,
+                incident,
+            },
+        );
     }
+
+    Ok(record)
 }
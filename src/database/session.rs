@@ -1,46 +1,197 @@
+use crate::config::CONFIG;
+use crate::database::user::{effective_role, Role};
 use crate::database::DATABASE;
 use crate::time_util::current_unix_time;
 use anyhow::bail;
-use rusqlite::params;
+use rusqlite::{fallible_iterator::FallibleIterator, params, OptionalExtension};
 use sha2::{Digest, Sha256};
-use std::iter::repeat_with;
 
-pub async fn create() -> anyhow::Result<String> {
-    let token: String = repeat_with(fastrand::alphanumeric).take(12).collect();
+// sessions are valid for 7 days
+pub static SESSION_LIFETIME_SECS: u64 = 60 * 60 * 24 * 7;
+// 256 bit, hex encoded
+pub static TOKEN_BYTES: usize = 32;
+
+// The user a session belongs to
+#[derive(Debug)]
+pub struct SessionUser {
+    pub session_id: u64,
+    // None = logged in with the password from the config
+    pub user_id: Option<u64>,
+    pub username: String,
+    // Already lowered to viewer if the user is an admin without required two-factor authentication
+    pub role: Role,
+    // The role was lowered, the user has to set up two-factor authentication
+    pub needs_totp: bool,
+}
+
+#[derive(Debug)]
+pub struct Session {
+    pub id: u64,
+    pub user_id: Option<u64>,
+    pub username: String,
+    pub created_at: Option<u64>,
+    pub expires_at: u64,
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+}
+
+// Only the hash of a token is stored, a leaked database does not allow logging in
+pub fn hash_token(token: &str) -> String {
     let mut hasher = Sha256::new();
+    hasher.update(token);
+
+    hex::encode(hasher.finalize())
+}
 
-    hasher.update(&token);
+// Random token with TOKEN_BYTES bytes, hex encoded
+pub fn generate_token() -> anyhow::Result<String> {
+    let mut bytes = [0u8; TOKEN_BYTES];
+    if let Err(e) = getrandom::getrandom(&mut bytes) {
+        bail!("Failed to generate token: {e}");
+    }
+
+    Ok(hex::encode(bytes))
+}
 
-    let hash = hex::encode(hasher.finalize());
-    let expiry = current_unix_time() + 60 * 60 * 24 * 7; // token is valid for 7 days
+// user_id: None if the password from the config was used
+pub async fn create(
+    user_id: Option<u64>,
+    ip: &str,
+    user_agent: Option<&str>,
+) -> anyhow::Result<String> {
+    let token = generate_token()?;
 
+    let now = current_unix_time();
     DATABASE.lock().await.execute(
-        "INSERT INTO sessions (token, expiresAt) VALUES (?, ?)",
-        params![hash, expiry],
+        "INSERT INTO sessions (token, expiresAt, userId, createdAt, ip, userAgent) VALUES (?, ?, ?, ?, ?, ?)",
+        params![hash_token(&token), now + SESSION_LIFETIME_SECS, user_id, now, ip, user_agent],
     )?;
 
     Ok(token)
 }
 
-pub async fn is_valid(token: &str) -> anyhow::Result<bool> {
-    if token.len() != 12 {
-        return Ok(false);
+// returns None if the session is invalid or expired
+pub async fn get_user(token: &str) -> anyhow::Result<Option<SessionUser>> {
+    if token.len() != TOKEN_BYTES * 2 {
+        return Ok(None);
     }
 
-    let mut hasher = Sha256::new();
-    hasher.update(token);
-    let hash = hex::encode(hasher.finalize());
-
-    let sessions = DATABASE.lock().await.query_row(
-        "SELECT token FROM sessions WHERE (token = ? AND expiresAt > ?)",
-        params![hash, current_unix_time()],
-        |_| Ok(()),
-    );
-    match sessions {
-        Err(rusqlite::Error::QueryReturnedNoRows) => return Ok(false),
-        Err(e) => bail!(e),
-        _ => {}
+    let (require_totp, config_has_totp) = {
+        let config = CONFIG.get().unwrap().lock().await;
+        (
+            config.login.require_totp_for_admins,
+            config.login.totp_secret.is_some(),
+        )
+    };
+
+    let row = DATABASE
+        .lock()
+        .await
+        .query_row(
+            "SELECT s.id, s.userId, u.username, u.role, (u.totpEnabled OR u.oidcSubject IS NOT NULL) FROM sessions s LEFT JOIN users u ON u.id = s.userId WHERE (s.token = ? AND s.expiresAt > ?)",
+            params![hash_token(token), current_unix_time()],
+            |r| {
+                let session_id: u64 = r.get(0)?;
+                let user_id: Option<u64> = r.get(1)?;
+                let username: Option<String> = r.get(2)?;
+                let role: Option<u8> = r.get(3)?;
+                let has_second_factor: Option<bool> = r.get(4)?;
+
+                Ok((session_id, user_id, username, role, has_second_factor))
+            },
+        )
+        .optional()?;
+
+    let user = match row {
+        None => None,
+        // sessions created with the password from the config have full access
+        Some((session_id, None, _, _, _)) => {
+            let effective = effective_role(Role::Admin, config_has_totp, require_totp);
+            Some(SessionUser {
+                session_id,
+                user_id: None,
+                username: "admin (config)".to_string(),
+                role: effective,
+                needs_totp: effective != Role::Admin,
+            })
+        }
+        Some((session_id, Some(user_id), Some(username), Some(role), has_second_factor)) => {
+            let role = Role::from(role);
+            let effective =
+                effective_role(role, has_second_factor.unwrap_or_default(), require_totp);
+            Some(SessionUser {
+                session_id,
+                user_id: Some(user_id),
+                username,
+                role: effective,
+                needs_totp: effective != role,
+            })
+        }
+        // the user was deleted
+        Some(_) => None,
+    };
+
+    Ok(user)
+}
+
+// all sessions that did not expire yet, only the ones of `user_id` if given
+pub async fn get_active(user_id: Option<Option<u64>>) -> anyhow::Result<Vec<Session>> {
+    let lock = DATABASE.lock().await;
+    let mut stmt = lock.prepare(
+        "SELECT s.id, s.userId, u.username, s.createdAt, s.expiresAt, s.ip, s.userAgent FROM sessions s LEFT JOIN users u ON u.id = s.userId WHERE s.expiresAt > ? ORDER BY s.createdAt DESC",
+    )?;
+
+    let sessions: Vec<Session> = stmt
+        .query([current_unix_time()])?
+        .map(|r| {
+            let user_id: Option<u64> = r.get(1)?;
+            let username: Option<String> = r.get(2)?;
+
+            Ok(Session {
+                id: r.get(0)?,
+                user_id,
+                username: match user_id {
+                    None => "admin (config)".to_string(),
+                    Some(_) => username.unwrap_or_else(|| "deleted user".to_string()),
+                },
+                created_at: r.get(3)?,
+                expires_at: r.get(4)?,
+                ip: r.get(5)?,
+                user_agent: r.get(6)?,
+            })
+        })
+        .filter(|s| Ok(user_id.is_none_or(|id| s.user_id == id)))
+        .collect()?;
+
+    Ok(sessions)
+}
+
+pub async fn get_by_id(id: u64) -> Option<Session> {
+    get_active(None)
+        .await
+        .ok()?
+        .into_iter()
+        .find(|s| s.id == id)
+}
+
+pub async fn util_delete(id: u64) -> anyhow::Result<()> {
+    let affected = DATABASE
+        .lock()
+        .await
+        .execute("DELETE FROM sessions WHERE id = ?", [id])?;
+    if affected == 0 {
+        bail!("No such session")
     }
 
-    Ok(true)
+    Ok(())
+}
+
+// returns the number of deleted sessions
+pub async fn purge_expired() -> anyhow::Result<usize> {
+    let deleted = DATABASE.lock().await.execute(
+        "DELETE FROM sessions WHERE expiresAt <= ?",
+        [current_unix_time()],
+    )?;
+
+    Ok(deleted)
 }
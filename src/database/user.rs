@@ -0,0 +1,380 @@
+use anyhow::bail;
+use itertools::Itertools;
+use lazy_static::lazy_static;
+use rusqlite::{fallible_iterator::FallibleIterator, params, OptionalExtension};
+use serde::Serialize;
+use subtle::ConstantTimeEq;
+
+use crate::database::session::hash_token;
+use crate::password;
+use crate::time_util::current_unix_time;
+use crate::totp;
+
+use super::DATABASE;
+
+lazy_static! {
+    static ref DUMMY_HASH: String = password::hash("dummy").unwrap();
+}
+
+#[derive(Debug, PartialEq, PartialOrd, Clone, Copy, Serialize)]
+#[repr(u8)]
+pub enum Role {
+    // Can see all pages, even if guests are not allowed
+    Viewer,
+    // Can also add, delete and change monitors and incidents
+    Editor,
+    // Can also manage users
+    Admin,
+}
+
+impl From<u8> for Role {
+    fn from(value: u8) -> Self {
+        match value {
+            0 => Self::Viewer,
+            1 => Self::Editor,
+            2 => Self::Admin,
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl Role {
+    pub fn from_str(val: &str) -> Option<Self> {
+        match val.to_lowercase().as_str() {
+            "viewer" => Some(Self::Viewer),
+            "editor" => Some(Self::Editor),
+            "admin" => Some(Self::Admin),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Viewer => "viewer",
+            Self::Editor => "editor",
+            Self::Admin => "admin",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct User {
+    pub id: u64,
+    pub username: String,
+    pub role: Role,
+    pub created_at: u64,
+    pub totp_enabled: bool,
+    // Logs in with single sign-on, two-factor authentication is up to the identity provider
+    pub sso: bool,
+}
+
+// Admins without two-factor authentication only get the rights of viewers if
+// `login.require_totp_for_admins` is set
+// SSO users count as having it, it is up to the identity provider
+pub fn effective_role(role: Role, has_totp: bool, require_totp_for_admins: bool) -> Role {
+    if role == Role::Admin && !has_totp && require_totp_for_admins {
+        Role::Viewer
+    } else {
+        role
+    }
+}
+
+pub fn is_valid_username(username: &str) -> bool {
+    (1..=32).contains(&username.len())
+        && username
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_.".contains(c))
+}
+
+// Turns a username from an identity provider (e.g. an email address) into a valid one by
+// replacing the characters local usernames can not contain with `_`
+pub fn normalize_username(username: &str) -> Option<String> {
+    let normalized: String = username
+        .trim()
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || "-_.".contains(c) {
+                c
+            } else {
+                '_'
+            }
+        })
+        .take(32)
+        .collect();
+
+    is_valid_username(&normalized).then_some(normalized)
+}
+
+// returns the id of the added user
+pub async fn add(username: &str, password: &str, role: Role) -> anyhow::Result<u64> {
+    tracing::debug!("Adding user - username: {username} | role: {role:?}");
+
+    let hash = password::hash(password)?;
+    let db = DATABASE.lock().await;
+
+    let exists = db
+        .prepare("SELECT 1 FROM users WHERE username = ?")?
+        .exists([username])?;
+    if exists {
+        bail!("A user with this name already exists");
+    }
+
+    db.execute(
+        "INSERT INTO users (username, passwordHash, role, createdAt) VALUES (?, ?, ?, ?)",
+        params![username, hash, role as u8, current_unix_time()],
+    )?;
+
+    Ok(db.last_insert_rowid() as u64)
+}
+
+// returns the user if the username and password are correct
+pub async fn check_password(username: &str, password: &str) -> anyhow::Result<Option<User>> {
+    let row = DATABASE
+        .lock()
+        .await
+        .query_row(
+            "SELECT id, username, role, createdAt, totpEnabled, oidcSubject IS NOT NULL, passwordHash FROM users WHERE username = ?",
+            [username],
+            |r| {
+                let user = User {
+                    id: r.get(0)?,
+                    username: r.get(1)?,
+                    role: Role::from(r.get::<_, u8>(2)?),
+                    created_at: r.get(3)?,
+                    totp_enabled: r.get(4)?,
+                    sso: r.get(5)?,
+                };
+                let hash: String = r.get(6)?;
+
+                Ok((user, hash))
+            },
+        )
+        .optional()?;
+
+    let Some((user, hash)) = row else {
+        // takes as long as a wrong password so usernames can't be guessed by timing
+        password::verify(password, &DUMMY_HASH);
+        return Ok(None);
+    };
+    if !password::verify(password, &hash) {
+        return Ok(None);
+    }
+
+    Ok(Some(user))
+}
+
+pub async fn get_all() -> anyhow::Result<Vec<User>> {
+    let lock = DATABASE.lock().await;
+    let mut stmt =
+        lock.prepare("SELECT id, username, role, createdAt, totpEnabled, oidcSubject IS NOT NULL FROM users ORDER BY id")?;
+
+    let users: Vec<User> = stmt
+        .query([])?
+        .map(|r| {
+            Ok(User {
+                id: r.get(0)?,
+                username: r.get(1)?,
+                role: Role::from(r.get::<_, u8>(2)?),
+                created_at: r.get(3)?,
+                totp_enabled: r.get(4)?,
+                sso: r.get(5)?,
+            })
+        })
+        .collect()?;
+
+    Ok(users)
+}
+
+// Finds or creates the user of an OpenID Connect subject (`issuer#sub`), the role is updated on
+// every login so changes at the identity provider are applied
+// SSO users have no password, so they can only log in with single sign-on
+pub async fn upsert_sso(subject: &str, username: &str, role: Role) -> anyhow::Result<u64> {
+    if !is_valid_username(username) {
+        bail!("{username} is not a valid username");
+    }
+
+    let db = DATABASE.lock().await;
+    let existing = db
+        .query_row(
+            "SELECT id FROM users WHERE oidcSubject = ?",
+            [subject],
+            |r| r.get::<_, u64>(0),
+        )
+        .optional()?;
+
+    if let Some(id) = existing {
+        db.execute(
+            "UPDATE users SET role = ? WHERE id = ?",
+            params![role as u8, id],
+        )?;
+        return Ok(id);
+    }
+
+    let taken = db
+        .prepare("SELECT 1 FROM users WHERE username = ?")?
+        .exists([username])?;
+    if taken {
+        bail!("The username {username} is already used by another account");
+    }
+
+    tracing::debug!("Adding SSO user - username: {username} | role: {role:?}");
+    db.execute(
+        "INSERT INTO users (username, passwordHash, role, createdAt, oidcSubject) VALUES (?, '', ?, ?, ?)",
+        params![username, role as u8, current_unix_time(), subject],
+    )?;
+
+    Ok(db.last_insert_rowid() as u64)
+}
+
+// Starts setting up two-factor authentication, returns the new TOTP secret
+// It is only enabled after a code was confirmed with `enable_totp`
+pub async fn begin_totp(id: u64) -> anyhow::Result<String> {
+    let secret = totp::generate_secret()?;
+    let affected = DATABASE.lock().await.execute(
+        "UPDATE users SET totpSecret = ? WHERE id = ? AND totpEnabled = 0",
+        params![secret, id],
+    )?;
+    if affected == 0 {
+        bail!("No such user or two-factor authentication is already enabled")
+    }
+
+    Ok(secret)
+}
+
+// The TOTP secret of a setup that was started but not confirmed yet
+pub async fn get_pending_totp_secret(id: u64) -> anyhow::Result<Option<String>> {
+    let secret = DATABASE
+        .lock()
+        .await
+        .query_row(
+            "SELECT totpSecret FROM users WHERE id = ? AND totpEnabled = 0",
+            [id],
+            |r| r.get::<_, Option<String>>(0),
+        )
+        .optional()?;
+
+    Ok(secret.flatten())
+}
+
+// Confirms the setup with a code from the authenticator app
+// returns the recovery codes, None if the code is wrong
+pub async fn enable_totp(id: u64, code: &str) -> anyhow::Result<Option<Vec<String>>> {
+    let Some(secret) = get_pending_totp_secret(id).await? else {
+        bail!("Two-factor authentication was not set up or is already enabled")
+    };
+    let Some(step) = totp::verify(&secret, code, None) else {
+        return Ok(None);
+    };
+
+    let recovery_codes = totp::generate_recovery_codes()?;
+    let hashes = recovery_codes
+        .iter()
+        .map(|c| hash_token(&totp::normalize_code(c)))
+        .join(",");
+    DATABASE.lock().await.execute(
+        "UPDATE users SET totpEnabled = 1, totpLastStep = ?, totpRecoveryCodes = ? WHERE id = ?",
+        params![step, hashes, id],
+    )?;
+
+    Ok(Some(recovery_codes))
+}
+
+// Checks a TOTP code or a recovery code, each code can only be used once
+pub async fn check_second_factor(id: u64, code: &str) -> anyhow::Result<bool> {
+    let db = DATABASE.lock().await;
+    let Some((secret, last_step, recovery_codes)) = db
+        .query_row(
+            "SELECT totpSecret, totpLastStep, totpRecoveryCodes FROM users WHERE id = ? AND totpEnabled = 1",
+            [id],
+            |r| {
+                Ok((
+                    r.get::<_, String>(0)?,
+                    r.get::<_, Option<u64>>(1)?,
+                    r.get::<_, Option<String>>(2)?.unwrap_or_default(),
+                ))
+            },
+        )
+        .optional()?
+    else {
+        return Ok(false);
+    };
+
+    if let Some(step) = totp::verify(&secret, code, last_step) {
+        db.execute(
+            "UPDATE users SET totpLastStep = ? WHERE id = ?",
+            params![step, id],
+        )?;
+        return Ok(true);
+    }
+
+    let hash = hash_token(&totp::normalize_code(code));
+    let mut hashes = recovery_codes
+        .split(',')
+        .filter(|h| !h.is_empty())
+        .collect::<Vec<&str>>();
+    let Some(pos) = hashes
+        .iter()
+        .position(|h| bool::from(h.as_bytes().ct_eq(hash.as_bytes())))
+    else {
+        return Ok(false);
+    };
+
+    hashes.remove(pos);
+    db.execute(
+        "UPDATE users SET totpRecoveryCodes = ? WHERE id = ?",
+        params![hashes.join(","), id],
+    )?;
+    tracing::info!("User {id} used a recovery code, {} left", hashes.len());
+
+    Ok(true)
+}
+
+pub async fn disable_totp(id: u64) -> anyhow::Result<()> {
+    let affected = DATABASE.lock().await.execute(
+        "UPDATE users SET totpEnabled = 0, totpSecret = NULL, totpLastStep = NULL, totpRecoveryCodes = NULL WHERE id = ?",
+        [id],
+    )?;
+    if affected == 0 {
+        bail!("No such user")
+    }
+
+    Ok(())
+}
+
+pub async fn get_by_id(id: u64) -> anyhow::Result<Option<User>> {
+    Ok(get_all().await?.into_iter().find(|u| u.id == id))
+}
+
+pub async fn set_role(id: u64, role: Role) -> anyhow::Result<()> {
+    let affected = DATABASE.lock().await.execute(
+        "UPDATE users SET role = ? WHERE id = ?",
+        params![role as u8, id],
+    )?;
+    if affected == 0 {
+        bail!("No such user")
+    }
+
+    Ok(())
+}
+
+// also deletes all sessions of the user
+pub async fn util_delete(id: u64) -> anyhow::Result<()> {
+    let affected = DATABASE
+        .lock()
+        .await
+        .execute("DELETE FROM users WHERE id = ?", [id])?;
+    if affected == 0 {
+        bail!("No such user")
+    }
+
+    DATABASE
+        .lock()
+        .await
+        .execute("DELETE FROM sessions WHERE userId = ?", [id])?;
+    DATABASE
+        .lock()
+        .await
+        .execute("DELETE FROM apiTokens WHERE userId = ?", [id])?;
+
+    Ok(())
+}
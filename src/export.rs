@@ -0,0 +1,224 @@
+use anyhow::{bail, Context};
+use serde::{Deserialize, Serialize};
+use tokio::task::JoinSet;
+
+use crate::{
+    config::{ConfigMonitorService, ConfigStatusPage, CONFIG},
+    database::{self, monitor::NewMonitor, record::MonitorRecord},
+    monitor::{MonitorData, Visibility},
+    time_util::current_unix_time,
+};
+
+// Version of the export format, increased on incompatible changes
+static FORMAT_VERSION: u32 = 1;
+
+// All monitors of an instance, header values and request bodies are included in plain text
+#[derive(Serialize, Deserialize, Debug)]
+pub struct MonitorExport {
+    pub version: u32,
+    pub exported_at: u64,
+    pub monitors: Vec<ExportedMonitor>,
+    // Status pages of the config for reference, they are not imported
+    #[serde(default)]
+    pub status_pages: Vec<ConfigStatusPage>,
+}
+
+// Uses the same fields as `[[monitor]]` in the config
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ExportedMonitor {
+    // ID on the exporting instance, imported monitors get a new one
+    pub id: u64,
+    #[serde(default)]
+    pub name: String,
+    pub interval_mins: u16,
+    pub timeout_secs: u16,
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    #[serde(default)]
+    pub visibility: Visibility,
+    #[serde(default)]
+    pub hide_location: bool,
+    // Monitors managed by the config are exported but not imported, they belong in the config
+    pub config_key: Option<String>,
+    #[serde(flatten)]
+    pub service: ConfigMonitorService,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum ImportMode {
+    // Adds the monitors that do not exist yet (same name and service)
+    Merge,
+    // Deletes all monitors that were added in the web UI (including their records) first
+    Replace,
+}
+
+impl ImportMode {
+    pub fn from_str(val: &str) -> Option<Self> {
+        match val.to_lowercase().as_str() {
+            "merge" => Some(Self::Merge),
+            "replace" => Some(Self::Replace),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Default)]
+pub struct ImportSummary {
+    // IDs of the new monitors
+    pub added: Vec<u64>,
+    // Monitors that already existed or are managed by the config
+    pub skipped: usize,
+    pub deleted: usize,
+}
+
+pub async fn export_monitors() -> anyhow::Result<MonitorExport> {
+    let mut monitors = vec![];
+    for (id, mon) in database::monitor::get_all(false).await? {
+        let service = ConfigMonitorService::from_monitor_data(&mon.service_data)
+            .with_context(|| format!("Failed to export monitor {id}"))?;
+
+        monitors.push(ExportedMonitor {
+            id,
+            name: mon.service_name,
+            interval_mins: mon.interval_mins as _,
+            timeout_secs: mon.timeout_secs,
+            enabled: mon.enabled,
+            visibility: mon.visibility,
+            hide_location: mon.hide_location,
+            config_key: mon.config_key,
+            service,
+        });
+    }
+    monitors.sort_by_key(|m| m.id);
+
+    let status_pages = CONFIG.get().unwrap().lock().await.status_pages.clone();
+
+    Ok(MonitorExport {
+        version: FORMAT_VERSION,
+        exported_at: current_unix_time(),
+        monitors,
+        status_pages,
+    })
+}
+
+// Parses a JSON or TOML export
+pub fn parse_export(text: &str, toml: bool) -> anyhow::Result<MonitorExport> {
+    let export: MonitorExport = if toml {
+        toml::from_str(text)?
+    } else {
+        serde_json::from_str(text)?
+    };
+    if export.version > FORMAT_VERSION {
+        bail!(
+            "Export format version {} is not supported, at most {FORMAT_VERSION}",
+            export.version
+        );
+    }
+
+    Ok(export)
+}
+
+// Nothing is changed if a monitor of the export is invalid
+pub async fn import_monitors(
+    export: MonitorExport,
+    mode: ImportMode,
+) -> anyhow::Result<ImportSummary> {
+    let mut summary = ImportSummary::default();
+
+    let mut to_add: Vec<(ExportedMonitor, MonitorData)> = vec![];
+    for mon in export.monitors {
+        if mon.config_key.is_some() {
+            summary.skipped += 1;
+            continue;
+        }
+        if !(1..=60 * 24 * 7).contains(&mon.interval_mins) {
+            bail!("Monitor {}: interval_mins must be within 1..10080", mon.id);
+        }
+        if !(1..=60).contains(&mon.timeout_secs) {
+            bail!("Monitor {}: timeout_secs must be within 1..60", mon.id);
+        }
+        let data = mon
+            .service
+            .to_monitor_data()
+            .with_context(|| format!("Monitor {} is invalid", mon.id))?;
+
+        to_add.push((mon, data));
+    }
+
+    let replace = matches!(mode, ImportMode::Replace);
+    // the monitors that are left after deleting in replace mode
+    let existing = database::monitor::get_all(false).await?;
+    let existing: Vec<_> = existing
+        .values()
+        .filter(|m| !replace || m.config_key.is_some())
+        .collect();
+
+    let mut new = vec![];
+    for (mon, data) in to_add {
+        let exists = existing
+            .iter()
+            .any(|m| m.service_name == mon.name && m.service_data == data);
+        if exists {
+            summary.skipped += 1;
+            continue;
+        }
+
+        new.push(NewMonitor {
+            service_data: data,
+            interval_mins: mon.interval_mins,
+            service_name: mon.name,
+            timeout_secs: mon.timeout_secs,
+            visibility: mon.visibility,
+            hide_location: mon.hide_location,
+            enabled: mon.enabled,
+        });
+    }
+
+    let (deleted, added) = database::monitor::util_import(&new, replace).await?;
+    summary.deleted = deleted;
+    summary.added = added.clone();
+
+    let mut checks = JoinSet::new();
+    for (mon, id) in new.into_iter().zip(added) {
+        // disabled monitors are not checked, the checker picks them up once they are enabled
+        if !mon.enabled {
+            continue;
+        }
+
+        // checked right away instead of when the checker gets to it
+        checks.spawn(async move {
+            let res = mon.service_data.run(mon.timeout_secs).await;
+            database::record::util_add_result(res, id).await
+        });
+    }
+
+    while let Some(res) = checks.join_next().await {
+        if let Ok(Err(e)) = res {
+            tracing::error!("Failed to store the first check of an imported monitor: {e}");
+        }
+    }
+
+    Ok(summary)
+}
+
+pub fn records_csv(records: &[MonitorRecord]) -> String {
+    let mut csv = "monitor_id,checked_at,result,response_time_ms,info\n".to_string();
+    for r in records {
+        csv.push_str(&format!(
+            "{},{},{:?},{},\"{}\"\n",
+            r.monitor_id,
+            r.time_checked,
+            r.result,
+            r.response_time_ms
+                .map(|ms| ms.to_string())
+                .unwrap_or_default(),
+            r.info.replace('"', "\"\"")
+        ));
+    }
+
+    csv
+}
@@ -0,0 +1,71 @@
+use serde::Serialize;
+use tokio::sync::broadcast;
+
+use crate::{
+    database::{
+        incident::Incident,
+        record::{MonitorRecord, RecordResult, HIDDEN_INFO},
+    },
+    monitor::Visibility,
+};
+
+// Events a slow subscriber can fall behind before it misses some
+static CHANNEL_CAPACITY: usize = 256;
+
+lazy_static::lazy_static! {
+    // Events are sent with the visibility and hide_location of their monitor so subscribers can
+    // filter them without querying the database
+    static ref EVENTS: broadcast::Sender<(Visibility, bool, LiveEvent)> = broadcast::channel(CHANNEL_CAPACITY).0;
+}
+
+// Updates for the live dashboard, produced by the checker
+#[derive(Debug, Clone, Serialize)]
+#[serde(untagged)]
+pub enum LiveEvent {
+    // A check finished
+    Record(MonitorRecord),
+    // The result differs from the previous check, `from` is None for the first check
+    StateChange {
+        monitor_id: u64,
+        from: Option<RecordResult>,
+        to: RecordResult,
+        // The incident the check opened, extended or closed
+        incident: Option<Incident>,
+    },
+}
+
+impl LiveEvent {
+    // Name of the server-sent event
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Record(_) => "record",
+            Self::StateChange { .. } => "state",
+        }
+    }
+
+    // For users the monitor hides its location from
+    pub fn without_info(mut self) -> Self {
+        match &mut self {
+            Self::Record(record) => record.info = HIDDEN_INFO.to_string(),
+            Self::StateChange {
+                incident: Some(incident),
+                ..
+            } => {
+                incident.first_info = HIDDEN_INFO.to_string();
+                incident.last_info = HIDDEN_INFO.to_string();
+            }
+            Self::StateChange { incident: None, .. } => {}
+        }
+
+        self
+    }
+}
+
+pub fn publish(visibility: Visibility, hide_location: bool, event: LiveEvent) {
+    // only fails if nobody is subscribed
+    let _ = EVENTS.send((visibility, hide_location, event));
+}
+
+pub fn subscribe() -> broadcast::Receiver<(Visibility, bool, LiveEvent)> {
+    EVENTS.subscribe()
+}
@@ -0,0 +1,73 @@
+use lazy_static::lazy_static;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use tokio::sync::Mutex;
+
+use crate::{config::CONFIG, time_util::current_unix_time};
+
+#[derive(Default)]
+struct Attempts {
+    // times of the failed attempts within the lockout window
+    failures: Vec<u64>,
+    locked_until: u64,
+}
+
+lazy_static! {
+    static ref PER_IP: Mutex<HashMap<IpAddr, Attempts>> = Mutex::new(HashMap::new());
+    static ref GLOBAL: Mutex<Attempts> = Mutex::new(Attempts::default());
+}
+
+// Returns the seconds until the IP may try again if it is locked out
+pub async fn check(ip: IpAddr) -> Option<u64> {
+    let now = current_unix_time();
+    let locked_until = PER_IP
+        .lock()
+        .await
+        .get(&ip)
+        .map(|a| a.locked_until)
+        .unwrap_or_default()
+        .max(GLOBAL.lock().await.locked_until);
+
+    (locked_until > now).then(|| locked_until - now)
+}
+
+pub async fn record_failure(ip: IpAddr) {
+    let (max_per_ip, lockout_secs, max_global) = {
+        let config = CONFIG.get().unwrap().lock().await;
+        (
+            config.login.max_attempts_per_ip as usize,
+            config.login.lockout_secs,
+            config.login.max_attempts_global_per_min as usize,
+        )
+    };
+    let now = current_unix_time();
+
+    let mut per_ip = PER_IP.lock().await;
+    // forget IPs without recent failures so the map doesn't grow forever
+    per_ip.retain(|_, a| {
+        a.failures.retain(|t| t + lockout_secs > now);
+        !a.failures.is_empty() || a.locked_until > now
+    });
+
+    let attempts = per_ip.entry(ip).or_default();
+    attempts.failures.push(now);
+    if attempts.failures.len() >= max_per_ip {
+        tracing::warn!("Locking out {ip} for {lockout_secs}s after too many failed logins");
+        attempts.locked_until = now + lockout_secs;
+        attempts.failures.clear();
+    }
+    drop(per_ip);
+
+    let mut global = GLOBAL.lock().await;
+    global.failures.retain(|t| t + 60 > now);
+    global.failures.push(now);
+    if global.failures.len() >= max_global {
+        tracing::warn!("Rejecting all logins for 60s after too many failed logins");
+        global.locked_until = now + 60;
+        global.failures.clear();
+    }
+}
+
+pub async fn record_success(ip: IpAddr) {
+    PER_IP.lock().await.remove(&ip);
+}
@@ -2,35 +2,108 @@ use crate::config::CONFIG;
 
 use anyhow::Context;
 use axum::{
+    middleware,
     routing::{delete, get, patch, post, put},
     Router,
 };
 use checker::checker_thread;
+use clap::Parser;
+use cli::{Cli, Command, ConfigCommand};
 use database::DATABASE;
 use rusqlite::fallible_iterator::FallibleIterator;
-use std::env;
+use std::io::{self, BufRead, Write};
+use std::net::SocketAddr;
+use std::time::Duration;
 
 mod api;
+mod auth;
 mod checker;
+mod cli;
 mod config;
+mod config_monitors;
+mod csrf;
 mod database;
+mod export;
+mod live;
+mod login_throttle;
 mod monitor;
+mod oidc;
+mod password;
+mod reload;
 mod routes;
+mod secrets;
+mod sla;
 mod templates;
 mod time_util;
+mod totp;
+mod uptime;
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    tracing_subscriber::fmt::init();
+    let cli = Cli::parse();
+    let config_path = cli.legacy_config.unwrap_or(cli.config);
+    let command = cli.command.unwrap_or(Command::Serve);
 
-    let config_path = env::args()
-        .nth(1)
-        .unwrap_or_else(|| "./stbmon.toml".to_string());
+    // stdout is kept clean for the output of the other commands
+    if matches!(command, Command::Serve) {
+        tracing_subscriber::fmt::init();
+    } else {
+        tracing_subscriber::fmt().with_writer(io::stderr).init();
+    }
+
+    match command {
+        Command::Serve => serve(config_path).await,
+        Command::Check { id, all } => {
+            // HTTP checks use the settings from the config
+            init_config(config_path).await?;
+            cli::check(id, all).await
+        }
+        Command::Monitors(command) => {
+            init_config(config_path).await?;
+            cli::monitors(command).await
+        }
+        Command::Records(command) => cli::records(command).await,
+        Command::Config(ConfigCommand::Validate) => cli::validate_config(&config_path).await,
+        Command::Db(command) => cli::db(command).await,
+        Command::HashPassword => hash_password_command(),
+        Command::TotpSecret => totp_secret_command(),
+        Command::SecretKey => {
+            println!("{}", secrets::generate_key()?);
+            Ok(())
+        }
+    }
+}
 
+async fn init_config(config_path: String) -> anyhow::Result<()> {
     tracing::info!("Loading config");
     config::init_config(config_path)
         .await
-        .context("Failed to initialize config")?;
+        .context("Failed to initialize config")
+}
+
+async fn serve(config_path: String) -> anyhow::Result<()> {
+    // held while the server runs, `db restore` refuses to replace the database under it
+    let _lock = database::lock()?;
+    init_config(config_path.clone()).await?;
+    database::monitor::check_secret_key(secrets::key().as_ref()).await?;
+
+    match config_monitors::reconcile().await {
+        Ok(stats) => tracing::info!(
+            "Synced monitors from the config ({} added, {} updated, {} disabled)",
+            stats.created,
+            stats.updated,
+            stats.disabled
+        ),
+        Err(e) => tracing::error!("Failed to sync monitors from the config: {e}"),
+    }
+
+    match database::monitor::encrypt_plain_secrets().await {
+        Ok(encrypted) if encrypted > 0 => {
+            tracing::info!("Encrypted the secrets of {encrypted} monitors");
+        }
+        Err(e) => tracing::error!("Failed to encrypt monitor secrets: {e}"),
+        _ => {}
+    }
 
     tracing::debug!("Fixing monitors");
     match fix_no_records().await {
@@ -44,12 +117,77 @@ async fn main() -> anyhow::Result<()> {
     let app = Router::new()
         .route("/", get(templates::index_template))
         .route("/admin", get(templates::admin_template))
+        .route("/admin/audit", get(templates::audit_log_template))
+        .route("/account", get(templates::account_template))
         .route("/monitor/{id}", get(templates::monitor_template))
+        .route("/status/{slug}", get(templates::status_page_template))
+        .route("/sla", get(templates::sla_template))
         .route("/static/{*path}", get(routes::static_route))
+        .route(
+            "/badge/{id}/status.svg",
+            get(templates::status_badge_template),
+        )
+        .route(
+            "/badge/{id}/uptime.svg",
+            get(templates::uptime_badge_template),
+        )
+        .route(
+            "/badge/{id}/response.svg",
+            get(templates::response_badge_template),
+        )
         .route("/api/monitors/{id}", delete(api::delete_monitor_route))
         .route("/api/monitors/{id}/toggle", patch(api::toggle_monitor))
+        .route("/api/monitors/{id}/check", post(api::check_monitor_route))
+        .route(
+            "/api/monitors/check_down",
+            post(api::check_down_monitors_route),
+        )
+        .route(
+            "/api/monitors/{id}/visibility",
+            patch(api::set_monitor_visibility_route),
+        )
+        .route(
+            "/api/monitors/{id}/request",
+            patch(api::edit_monitor_request_route),
+        )
         .route("/api/monitors", put(api::add_monitor_route))
-        .route("/api/create_session", post(api::create_session_route));
+        .route("/api/monitors/test", post(api::test_monitor_route))
+        .route(
+            "/api/monitors/{id}/records",
+            get(api::monitor_records_route),
+        )
+        .route("/api/export/monitors", get(api::export_monitors_route))
+        .route("/api/import/monitors", post(api::import_monitors_route))
+        .route(
+            "/api/incidents/{id}/comment",
+            patch(api::comment_incident_route),
+        )
+        .route(
+            "/api/incidents/{id}/publish",
+            patch(api::toggle_incident_published),
+        )
+        .route("/api/sla", get(api::sla_route))
+        .route("/api/events", get(api::events_route))
+        .route("/api/audit", get(api::audit_log_route))
+        .route("/api/admin/backup", get(api::backup_route))
+        .route("/api/users", put(api::add_user_route))
+        .route("/api/users/{id}", delete(api::delete_user_route))
+        .route("/api/users/{id}/role", patch(api::set_user_role_route))
+        .route("/api/users/{id}/totp", delete(api::reset_user_totp_route))
+        .route(
+            "/api/totp",
+            put(api::begin_totp_route)
+                .patch(api::enable_totp_route)
+                .delete(api::disable_totp_route),
+        )
+        .route("/api/create_session", post(api::create_session_route))
+        .route("/api/logout", post(api::logout_route))
+        .route("/api/oidc/login", get(api::oidc_login_route))
+        .route("/api/oidc/callback", get(api::oidc_callback_route))
+        .route("/api/sessions/{id}", delete(api::revoke_session_route))
+        .route("/api/tokens", put(api::add_api_token_route))
+        .route("/api/tokens/{id}", delete(api::revoke_api_token_route))
+        .layer(middleware::from_fn(csrf::origin_check));
 
     let bind_addr = CONFIG.get().unwrap().lock().await.bind_addr;
 
@@ -60,27 +198,35 @@ async fn main() -> anyhow::Result<()> {
         .context("Failed to start web server")?;
 
     tokio::task::spawn(checker_thread());
+    tokio::task::spawn(session_purge_thread());
+    tokio::task::spawn(backup_thread(config_path.clone()));
+    tokio::task::spawn(reload::reload_thread(config_path));
 
-    axum::serve(listener, app.into_make_service())
-        .await
-        .unwrap();
+    axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .await
+    .unwrap();
 
     Ok(())
 }
 
-// create records for monitors with no records
+// create records for enabled monitors with no records
 async fn fix_no_records() -> anyhow::Result<usize> {
-    let lock = DATABASE.lock().await;
-    let mut stmt =
-        lock.prepare("SELECT id FROM monitors WHERE id NOT IN (SELECT monitorId FROM records)")?;
-
-    let ids: Vec<u64> = stmt
-        .query([])?
-        .map(|r| Ok(r.get::<_, u64>(0).unwrap()))
-        .collect()
-        .unwrap();
-    drop(stmt);
-    drop(lock);
+    // in a block, the statement must not be held across awaits
+    let ids: Vec<u64> = {
+        let lock = DATABASE.lock().await;
+        let mut stmt = lock
+            .prepare("SELECT id FROM monitors WHERE enabled = 1 AND id NOT IN (SELECT monitorId FROM records)")?;
+
+        let ids = stmt
+            .query([])?
+            .map(|r| Ok(r.get::<_, u64>(0).unwrap()))
+            .collect()
+            .unwrap();
+        ids
+    };
 
     let mut fixed = 0;
     for id in ids {
@@ -93,3 +239,63 @@ async fn fix_no_records() -> anyhow::Result<usize> {
 
     Ok(fixed)
 }
+
+// deletes expired sessions once an hour
+async fn session_purge_thread() {
+    loop {
+        match database::session::purge_expired().await {
+            Ok(purged) if purged > 0 => tracing::debug!("Purged {purged} expired sessions"),
+            Err(e) => tracing::error!("Failed to purge expired sessions: {e}"),
+            _ => {}
+        }
+
+        tokio::time::sleep(Duration::from_secs(60 * 60)).await;
+    }
+}
+
+// makes the backups configured in [backup], checks every 10 minutes whether one is due
+async fn backup_thread(config_path: String) {
+    loop {
+        let backup = CONFIG.get().unwrap().lock().await.backup.clone();
+        if let Some(backup) = backup {
+            let dir = config::relative_to_config(&config_path, &backup.dir);
+            let interval = Duration::from_secs(backup.interval_hours * 60 * 60);
+            match database::backup::scheduled_backup(&dir, interval, backup.keep).await {
+                Ok(Some(path)) => tracing::info!("Backed up the database to {}", path.display()),
+                Err(e) => tracing::error!("Failed to back up the database: {e:#}"),
+                _ => {}
+            }
+        }
+
+        tokio::time::sleep(Duration::from_secs(10 * 60)).await;
+    }
+}
+
+// reads a password from stdin and prints its Argon2 hash for `password_hash` in the config
+fn hash_password_command() -> anyhow::Result<()> {
+    eprint!("Password: ");
+    io::stderr().flush()?;
+
+    let mut password = String::new();
+    io::stdin().lock().read_line(&mut password)?;
+    let password = password.trim_end_matches(['\r', '\n']);
+    if password.is_empty() {
+        anyhow::bail!("Password must not be empty");
+    }
+
+    println!("{}", password::hash(password)?);
+
+    Ok(())
+}
+
+// Prints a new secret for `login.totp_secret` and the URI to add it to an authenticator app
+fn totp_secret_command() -> anyhow::Result<()> {
+    let secret = totp::generate_secret()?;
+    println!("{secret}");
+    eprintln!(
+        "Provisioning URI: {}",
+        totp::provisioning_uri(&secret, "stb-mon", "admin")?
+    );
+
+    Ok(())
+}
@@ -9,11 +9,17 @@ use std::{
 use adler32::adler32;
 use axum::http::{HeaderName, HeaderValue};
 use reqwest::{redirect::Policy, StatusCode};
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Serialize, Serializer};
 
-use crate::config::CONFIG;
+use crate::{
+    config::{CONFIG, MONITOR_ENV_PREFIX},
+    secrets::{self, SecretKey},
+};
+
+use super::{MonitorResult, ResponseDetails};
 
-use super::MonitorResult;
+// Bytes of the response body shown when testing a monitor
+pub static MAX_DETAILS_BODY: usize = 4096;
 
 pub fn parse_codes(val: &str) -> Option<Vec<StatusCode>> {
     let val = val.replace(' ', "");
@@ -59,7 +65,7 @@ pub fn parse_codes(val: &str) -> Option<Vec<StatusCode>> {
     Some(codes)
 }
 
-#[derive(Serialize, Deserialize, Debug, Default)]
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
 pub enum HttpMethod {
     #[default]
     Get,
@@ -105,9 +111,20 @@ impl HttpMethod {
     }
 }
 
-#[derive(Serialize, Deserialize, Debug, Default)]
+// Header values are secret, see HttpRequest
+#[derive(Deserialize, Debug, Default, Clone, PartialEq)]
 pub struct HeaderHashMap(HashMap<String, String>);
 
+impl Serialize for HeaderHashMap {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            serializer.collect_map(self.0.keys().map(|k| (k, secrets::REDACTED)))
+        } else {
+            self.0.serialize(serializer)
+        }
+    }
+}
+
 impl HeaderHashMap {
     pub fn try_parse_str(val: &str) -> Option<Self> {
         let mut headers = HashMap::new();
@@ -124,6 +141,13 @@ impl HeaderHashMap {
         Some(hhm)
     }
 
+    pub fn try_from_map(headers: HashMap<String, String>) -> Option<Self> {
+        let hhm = Self(headers);
+        Self::to_reqwest(&hhm)?;
+
+        Some(hhm)
+    }
+
     pub fn to_reqwest(&self) -> Option<reqwest::header::HeaderMap> {
         let mut hm = reqwest::header::HeaderMap::new();
 
@@ -141,16 +165,133 @@ impl HeaderHashMap {
 
         Some(hm)
     }
+
+    pub fn names(&self) -> impl Iterator<Item = &String> {
+        self.0.keys()
+    }
+
+    // Header values in plain text, only for exports
+    pub fn to_map(&self) -> HashMap<String, String> {
+        self.0.clone()
+    }
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+// Header values and the body are secret: they are encrypted in the database if `secret_key` is
+// set and only serialized as is for the database (MessagePack), JSON output is redacted
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct HttpRequest {
     pub method: HttpMethod,
     pub headers: HeaderHashMap,
+    #[serde(serialize_with = "serialize_body")]
     pub body: Vec<u8>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+fn serialize_body<S: Serializer>(body: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+    if !serializer.is_human_readable() {
+        body.serialize(serializer)
+    } else if body.is_empty() {
+        serializer.serialize_str("")
+    } else {
+        serializer.serialize_str(secrets::REDACTED)
+    }
+}
+
+impl HttpRequest {
+    fn map_secrets(&self, f: impl Fn(&[u8]) -> anyhow::Result<Vec<u8>>) -> anyhow::Result<Self> {
+        let mut headers = HashMap::new();
+        for (k, v) in &self.headers.0 {
+            headers.insert(k.clone(), String::from_utf8(f(v.as_bytes())?)?);
+        }
+
+        Ok(Self {
+            method: self.method.clone(),
+            headers: HeaderHashMap(headers),
+            body: f(&self.body)?,
+        })
+    }
+
+    // Empty and already encrypted values are kept as they are
+    pub fn encrypted(&self, key: &SecretKey) -> anyhow::Result<Self> {
+        self.map_secrets(|v| {
+            if v.is_empty() || secrets::is_encrypted(v) {
+                Ok(v.to_vec())
+            } else {
+                secrets::encrypt(key, v)
+            }
+        })
+    }
+
+    pub fn decrypted(&self, key: Option<&SecretKey>) -> anyhow::Result<Self> {
+        self.map_secrets(|v| secrets::decrypt(key, v))
+    }
+
+    fn secret_values(&self) -> impl Iterator<Item = &[u8]> {
+        self.headers
+            .0
+            .values()
+            .map(String::as_bytes)
+            .chain([self.body.as_slice()])
+    }
+
+    pub fn has_plain_secrets(&self) -> bool {
+        self.secret_values()
+            .any(|v| !v.is_empty() && !secrets::is_encrypted(v))
+    }
+
+    // Headers and body with ${VAR} replaced, only done right before sending the request so
+    // secrets are never stored in the database
+    pub fn interpolated(&self) -> Result<(reqwest::header::HeaderMap, Vec<u8>), String> {
+        // still encrypted if decrypting them failed
+        if self.secret_values().any(secrets::is_encrypted) {
+            return Err("Failed to decrypt the secrets of the monitor".to_string());
+        }
+
+        let mut headers = HashMap::new();
+        for (k, v) in &self.headers.0 {
+            headers.insert(k.clone(), interpolate_env(v)?);
+        }
+        let headers = HeaderHashMap(headers)
+            .to_reqwest()
+            .ok_or("Invalid header value after replacing environment variables")?;
+
+        let body = match std::str::from_utf8(&self.body) {
+            Ok(body) => interpolate_env(body)?.into_bytes(),
+            Err(_) => self.body.clone(),
+        };
+
+        Ok((headers, body))
+    }
+}
+
+// Replaces every ${NAME} with the value of the environment variable NAME, which has to start with
+// config::MONITOR_ENV_PREFIX
+pub fn interpolate_env(val: &str) -> Result<String, String> {
+    let mut res = String::new();
+    let mut rest = val;
+    while let Some(start) = rest.find("${") {
+        let Some(len) = rest[start + 2..].find('}') else {
+            break;
+        };
+        let name = &rest[start + 2..start + 2 + len];
+        if !name.starts_with(MONITOR_ENV_PREFIX) {
+            return Err(format!(
+                "Environment variable {name} can not be used, only ones starting with {MONITOR_ENV_PREFIX}"
+            ));
+        }
+        let Ok(value) = std::env::var(name) else {
+            return Err(format!("Environment variable {name} is not set"));
+        };
+
+        res.push_str(&rest[..start]);
+        res.push_str(&value);
+        rest = &rest[start + 3 + len..];
+    }
+    res.push_str(rest);
+
+    Ok(res)
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub enum HttpExpectedResponse {
     // The service sent any response
     Any,
@@ -168,12 +309,14 @@ pub async fn http_service(
     expected: &HttpExpectedResponse,
     timeout: Duration,
     request_data: &HttpRequest,
+    details: Option<&mut ResponseDetails>,
 ) -> MonitorResult {
     let start_time = Instant::now();
-    let config = CONFIG.get().unwrap().lock().await;
+    // copied out, the config must not stay locked while the request runs
+    let http_config = CONFIG.get().unwrap().lock().await.http.clone();
     let client = reqwest::ClientBuilder::new()
-        .redirect(if config.http.follow_redirects {
-            let limit = config.http.max_follow_redirects;
+        .redirect(if http_config.follow_redirects {
+            let limit = http_config.max_follow_redirects;
             Policy::limited(limit.unwrap().into())
         } else {
             Policy::none()
@@ -181,10 +324,15 @@ pub async fn http_service(
         .build()
         .unwrap();
 
+    let (headers, body) = match request_data.interpolated() {
+        Ok(r) => r,
+        Err(e) => return MonitorResult::IoError(e),
+    };
+
     let res = client
         .request(request_data.method.to_reqwest(), url)
-        .headers(request_data.headers.to_reqwest().unwrap())
-        .body(request_data.body.clone())
+        .headers(headers)
+        .body(body)
         .timeout(timeout)
         .send()
         .await;
@@ -193,7 +341,8 @@ pub async fn http_service(
         Ok(res) => res,
         Err(e) => {
             if e.is_timeout() {
-                return MonitorResult::Down(format!("Connection timed out: {:?}", e));
+                // the error contains the URL, it can include credentials
+                return MonitorResult::Down("Connection timed out".to_string());
             }
 
             if let Some(Some(kind)) = e
@@ -215,24 +364,29 @@ pub async fn http_service(
         }
     };
 
-    if config.http.fivexx_status_code_down && (500..599).contains(&res.status().as_u16()) {
-        return MonitorResult::Down(format!("Server replied with status {}", res.status()));
+    let status = res.status();
+    let delta = Instant::now().duration_since(start_time).as_millis();
+    let headers = details.is_some().then(|| res.headers().clone());
+    let bytes = res.bytes().await;
+    if let (Some(details), Some(headers), Ok(bytes)) = (details, headers, &bytes) {
+        *details = response_details(status, &headers, bytes);
+    }
+
+    if http_config.fivexx_status_code_down && (500..599).contains(&status.as_u16()) {
+        return MonitorResult::Down(format!("Server replied with status {status}"));
     }
 
-    let delta = Instant::now().duration_since(start_time).as_millis();
     match expected {
         HttpExpectedResponse::Any => MonitorResult::Ok(
             delta,
             format!(
-                "Server replied with status {} and {} bytes",
-                res.status(),
-                res.bytes().await.map(|b| b.len()).unwrap_or_default()
+                "Server replied with status {status} and {} bytes",
+                bytes.map(|b| b.len()).unwrap_or_default()
             ),
         ),
         HttpExpectedResponse::StatusCode(codes) => {
             let codes = parse_codes(codes).unwrap();
-            let status = res.status();
-            let Ok(bytes) = res.bytes().await else {
+            let Ok(bytes) = bytes else {
                 return MonitorResult::IoError("Failed to parse response bytes".to_string());
             };
             let info = format!(
@@ -247,8 +401,7 @@ pub async fn http_service(
             }
         }
         HttpExpectedResponse::Response(code, body_checksum) => {
-            let status = res.status();
-            let Ok(res_bytes) = res.bytes().await else {
+            let Ok(res_bytes) = bytes else {
                 return MonitorResult::IoError("Failed to parse response bytes".to_string());
             };
 
@@ -283,3 +436,63 @@ pub async fn http_service(
         }
     }
 }
+
+fn response_details(
+    status: StatusCode,
+    headers: &reqwest::header::HeaderMap,
+    body: &[u8],
+) -> ResponseDetails {
+    ResponseDetails::Http {
+        status: status.as_u16(),
+        headers: headers
+            .iter()
+            .map(|(k, v)| {
+                (
+                    k.to_string(),
+                    String::from_utf8_lossy(v.as_bytes()).to_string(),
+                )
+            })
+            .collect(),
+        body: String::from_utf8_lossy(&body[..body.len().min(MAX_DETAILS_BODY)]).to_string(),
+        body_bytes: body.len(),
+        body_adler32: adler32(body).unwrap(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn interpolates_monitor_variables() {
+        std::env::set_var("STBMON_MONITOR_TEST_TOKEN", "s3cr3t");
+
+        assert_eq!(interpolate_env("no variables").unwrap(), "no variables");
+        assert_eq!(
+            interpolate_env("Bearer ${STBMON_MONITOR_TEST_TOKEN}").unwrap(),
+            "Bearer s3cr3t"
+        );
+        assert_eq!(
+            interpolate_env("é${STBMON_MONITOR_TEST_TOKEN}:${STBMON_MONITOR_TEST_TOKEN}é").unwrap(),
+            "és3cr3t:s3cr3té"
+        );
+    }
+
+    #[test]
+    fn keeps_unterminated_braces() {
+        assert_eq!(
+            interpolate_env("a ${STBMON_MONITOR_TEST_TOKEN").unwrap(),
+            "a ${STBMON_MONITOR_TEST_TOKEN"
+        );
+        assert_eq!(interpolate_env("${").unwrap(), "${");
+        assert_eq!(interpolate_env("$").unwrap(), "$");
+    }
+
+    #[test]
+    fn rejects_other_variables() {
+        assert!(interpolate_env("${HOME}").is_err());
+        assert!(interpolate_env("${STBMON_SECRET_KEY}").is_err());
+        assert!(interpolate_env("${}").is_err());
+        assert!(interpolate_env("${STBMON_MONITOR_TEST_UNSET}").is_err());
+    }
+}
@@ -1,21 +1,95 @@
 use std::{collections::HashMap, net::SocketAddr, time::Duration};
 
 use http::HttpRequest;
+use itertools::Itertools;
 use serde::{Deserialize, Serialize};
 
+use crate::{
+    database::{record::RecordResult, user::Role},
+    secrets::{self, SecretKey},
+};
+
 pub mod http;
 pub mod tcp;
 
-#[derive(Debug)]
+#[derive(Serialize, Debug, PartialEq)]
 pub struct Monitor {
     pub service_data: MonitorData,
     pub service_name: String,
     pub interval_mins: u64,
     pub enabled: bool,
     pub timeout_secs: u16,
+    // Key of the monitor in the config, None if it was added in the web UI
+    pub config_key: Option<String>,
+    pub visibility: Visibility,
+    // Hides the service location and monitor-specific details from guests, the status is shown
+    pub hide_location: bool,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+impl Monitor {
+    // role = None: guest
+    pub fn is_visible_to(&self, role: Option<Role>) -> bool {
+        self.visibility.allows(role)
+    }
+
+    pub fn shows_location_to(&self, role: Option<Role>) -> bool {
+        !self.hide_location || role.is_some()
+    }
+}
+
+// Who can see a monitor including its records and incidents
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+#[repr(u8)]
+pub enum Visibility {
+    // Everyone who can see the monitor list, guests too if allow_guest is set
+    #[default]
+    Public,
+    // Only logged in users
+    Private,
+    // Only admins
+    Admin,
+}
+
+impl From<u8> for Visibility {
+    fn from(value: u8) -> Self {
+        match value {
+            1 => Self::Private,
+            2 => Self::Admin,
+            _ => Self::Public,
+        }
+    }
+}
+
+impl Visibility {
+    pub fn from_str(val: &str) -> Option<Self> {
+        match val.to_lowercase().as_str() {
+            "public" => Some(Self::Public),
+            "private" => Some(Self::Private),
+            "admin" => Some(Self::Admin),
+            _ => None,
+        }
+    }
+
+    // Whether a user with `role` (None for guests) can see monitors with this visibility
+    pub fn allows(&self, role: Option<Role>) -> bool {
+        match self {
+            Self::Public => true,
+            Self::Private => role.is_some(),
+            Self::Admin => role.is_some_and(|r| r >= Role::Admin),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Public => "public",
+            Self::Private => "private",
+            Self::Admin => "admin",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub enum MonitorData {
     Tcp {
         addr: SocketAddr,
@@ -46,12 +120,91 @@ pub enum MonitorResult {
     IoError(String),
 }
 
+impl MonitorResult {
+    // (result, response_time_ms, info) as stored in records
+    pub fn into_parts(self) -> (RecordResult, Option<u64>, String) {
+        match self {
+            Self::Ok(response_time_ms, info) => {
+                (RecordResult::Ok, Some(response_time_ms as _), info)
+            }
+            Self::UnexpectedResponse(response_time_ms, info) => {
+                (RecordResult::Unexpected, Some(response_time_ms as _), info)
+            }
+            Self::Down(info) => (RecordResult::Down, None, info),
+            Self::IoError(err) => (RecordResult::Err, None, err),
+        }
+    }
+}
+
+// What the service responded, only collected to show it when testing a monitor
+#[derive(Serialize, Debug, Default)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum ResponseDetails {
+    // The service did not respond
+    #[default]
+    None,
+    Http {
+        status: u16,
+        headers: Vec<(String, String)>,
+        // The body as text, cut off after http::MAX_DETAILS_BODY bytes
+        body: String,
+        body_bytes: usize,
+        // For the `res` expected response
+        body_adler32: u32,
+    },
+    Tcp {
+        // Like `hexdump -C`
+        hex_dump: String,
+        bytes: usize,
+    },
+}
+
+// Result of a check that is not stored, for trying out a monitor before adding it
+#[derive(Serialize, Debug)]
+pub struct TestResult {
+    pub result: RecordResult,
+    pub response_time_ms: Option<u64>,
+    pub info: String,
+    pub response: ResponseDetails,
+}
+
 impl MonitorData {
     // Running a service will execute the logic of the service and put its results into the database
     pub async fn run(&self, timeout_s: u16) -> MonitorResult {
+        self.run_inner(timeout_s, None).await
+    }
+
+    // Runs the check without storing it and also returns what the service responded
+    pub async fn test(&self, timeout_s: u16) -> TestResult {
+        let mut response = ResponseDetails::None;
+        let (result, response_time_ms, info) = self
+            .run_inner(timeout_s, Some(&mut response))
+            .await
+            .into_parts();
+
+        TestResult {
+            result,
+            response_time_ms,
+            info,
+            response,
+        }
+    }
+
+    // details are only collected when testing a monitor
+    async fn run_inner(
+        &self,
+        timeout_s: u16,
+        details: Option<&mut ResponseDetails>,
+    ) -> MonitorResult {
         match self {
             Self::Tcp { addr, expected } => {
-                tcp::tcp_service(addr, expected, Duration::from_secs(timeout_s.into())).await
+                tcp::tcp_service(
+                    addr,
+                    expected,
+                    Duration::from_secs(timeout_s.into()),
+                    details,
+                )
+                .await
             }
             Self::Http {
                 url,
@@ -63,6 +216,7 @@ impl MonitorData {
                     expected,
                     Duration::from_secs(timeout_s.into()),
                     request,
+                    details,
                 )
                 .await
             }
@@ -92,17 +246,64 @@ impl MonitorData {
             } => {
                 hm.insert("URL".to_string(), url.to_string());
                 hm.insert("Method".to_string(), format!("{:?}", request.method));
-                hm.insert(
-                    "Headers".to_string(),
-                    format!("{:?}", request.headers.to_reqwest().unwrap()),
-                );
-                let body = String::from_utf8(request.body.clone())
-                    .unwrap_or_else(|_| "binary".to_string());
-                hm.insert("Body".to_string(), body);
+                // header values and the body are secret, only the header names are shown
+                let headers = request
+                    .headers
+                    .names()
+                    .sorted()
+                    .map(|name| format!("{name}: {}", secrets::REDACTED))
+                    .join(", ");
+                hm.insert("Headers".to_string(), headers);
+                let body = if request.body.is_empty() {
+                    ""
+                } else {
+                    secrets::REDACTED
+                };
+                hm.insert("Body".to_string(), body.to_string());
                 hm.insert("Expected response".to_string(), format!("{expected:?}"));
             }
         };
 
         hm
     }
+    // Copy with the secret fields encrypted for storing it, unchanged if no key is set
+    pub fn encrypted(&self, key: Option<&SecretKey>) -> anyhow::Result<Self> {
+        match (self, key) {
+            (
+                Self::Http {
+                    url,
+                    request,
+                    expected,
+                },
+                Some(key),
+            ) => Ok(Self::Http {
+                url: url.clone(),
+                request: request.encrypted(key)?,
+                expected: expected.clone(),
+            }),
+            _ => Ok(self.clone()),
+        }
+    }
+
+    pub fn decrypted(&self, key: Option<&SecretKey>) -> anyhow::Result<Self> {
+        match self {
+            Self::Http {
+                url,
+                request,
+                expected,
+            } => Ok(Self::Http {
+                url: url.clone(),
+                request: request.decrypted(key)?,
+                expected: expected.clone(),
+            }),
+            Self::Tcp { .. } => Ok(self.clone()),
+        }
+    }
+
+    pub fn has_plain_secrets(&self) -> bool {
+        match self {
+            Self::Http { request, .. } => request.has_plain_secrets(),
+            Self::Tcp { .. } => false,
+        }
+    }
 }
@@ -1,5 +1,6 @@
 use std::{io::ErrorKind, net::SocketAddr, time::Duration};
 
+use itertools::Itertools;
 use serde::{Deserialize, Serialize};
 use tokio::{
     io::{AsyncReadExt, AsyncWriteExt},
@@ -7,9 +8,12 @@ use tokio::{
     time::Instant,
 };
 
-use super::MonitorResult;
+use super::{MonitorResult, ResponseDetails};
 
-#[derive(Serialize, Deserialize, Debug)]
+// How long to wait for a banner when testing a monitor that only expects an open port
+static BANNER_TIMEOUT: Duration = Duration::from_secs(1);
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub enum TcpExpectedResponse {
     // The connection does not get closed if we try to connect to the service
     OpenPort,
@@ -22,6 +26,7 @@ pub async fn tcp_service(
     addr: &SocketAddr,
     expected: &TcpExpectedResponse,
     timeout: Duration,
+    mut details: Option<&mut ResponseDetails>,
 ) -> MonitorResult {
     let start_time = Instant::now();
 
@@ -39,8 +44,20 @@ pub async fn tcp_service(
 
     let (sent, expected) = match expected {
         TcpExpectedResponse::OpenPort => {
+            let delta = Instant::now().duration_since(start_time).as_millis();
+
+            // show the banner that some services (SSH, SMTP, ...) send right after connecting
+            if let Some(details) = details {
+                let mut buf = [0u8; 2048];
+                if let Ok(Ok(read)) =
+                    tokio::time::timeout(BANNER_TIMEOUT, conn.read(&mut buf)).await
+                {
+                    *details = response_details(&buf[..read]);
+                }
+            }
+
             return MonitorResult::Ok(
-                Instant::now().duration_since(start_time).as_millis(),
+                delta,
                 "The service successfully established the connection".to_string(),
             );
         }
@@ -59,6 +76,9 @@ pub async fn tcp_service(
     };
 
     let bytes = buf[..read].to_vec();
+    if let Some(details) = details.as_mut() {
+        **details = response_details(&bytes);
+    }
 
     if expected.is_empty() {
         return MonitorResult::Ok(
@@ -71,5 +91,35 @@ pub async fn tcp_service(
         );
     }
 
-    todo!("Response hex pattern checking");
+    MonitorResult::IoError("Checking the response bytes is not supported yet".to_string())
+}
+
+fn response_details(bytes: &[u8]) -> ResponseDetails {
+    ResponseDetails::Tcp {
+        hex_dump: hex_dump(bytes),
+        bytes: bytes.len(),
+    }
+}
+
+// 16 bytes per line with their offset and printable characters, like `hexdump -C`
+fn hex_dump(bytes: &[u8]) -> String {
+    bytes
+        .chunks(16)
+        .enumerate()
+        .map(|(i, chunk)| {
+            let hex = chunk.iter().map(|b| format!("{b:02x}")).join(" ");
+            let text: String = chunk
+                .iter()
+                .map(|b| {
+                    if b.is_ascii_graphic() || *b == b' ' {
+                        *b as char
+                    } else {
+                        '.'
+                    }
+                })
+                .collect();
+
+            format!("{:08x}  {hex:<47}  |{text}|", i * 16)
+        })
+        .join("\n")
 }
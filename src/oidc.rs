@@ -0,0 +1,293 @@
+use std::collections::HashMap;
+
+use anyhow::{anyhow, bail, Context};
+use base64::{prelude::BASE64_URL_SAFE_NO_PAD, Engine};
+use lazy_static::lazy_static;
+use serde::Deserialize;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use tokio::sync::Mutex;
+use url::Url;
+
+use crate::{
+    config::{ConfigOidc, CONFIG},
+    database::user::{self, Role},
+    time_util::current_unix_time,
+};
+
+// A login has to be finished within this time
+static PENDING_LOGIN_LIFETIME_SECS: u64 = 60 * 10;
+// Allowed clock difference to the provider when checking the expiry of ID tokens
+static CLOCK_SKEW_SECS: u64 = 60;
+
+// The parts of the discovery document that are needed
+#[derive(Deserialize, Debug, Clone)]
+struct ProviderMetadata {
+    issuer: String,
+    authorization_endpoint: String,
+    token_endpoint: String,
+    userinfo_endpoint: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct TokenResponse {
+    id_token: String,
+    access_token: String,
+}
+
+// A login that was started but not finished yet
+struct PendingLogin {
+    pkce_verifier: String,
+    nonce: String,
+    started_at: u64,
+}
+
+// The user logging in, as reported by the identity provider
+pub struct OidcUser {
+    // `issuer#sub`, stays the same even if the username changes
+    pub subject: String,
+    pub username: String,
+    pub role: Role,
+}
+
+lazy_static! {
+    static ref METADATA: Mutex<Option<ProviderMetadata>> = Mutex::new(None);
+    // state -> pending login
+    static ref PENDING: Mutex<HashMap<String, PendingLogin>> = Mutex::new(HashMap::new());
+}
+
+fn random_string() -> anyhow::Result<String> {
+    let mut bytes = [0u8; 32];
+    if let Err(e) = getrandom::getrandom(&mut bytes) {
+        bail!("Failed to generate random value: {e}");
+    }
+
+    Ok(BASE64_URL_SAFE_NO_PAD.encode(bytes))
+}
+
+async fn oidc_config() -> anyhow::Result<ConfigOidc> {
+    CONFIG
+        .get()
+        .unwrap()
+        .lock()
+        .await
+        .oidc
+        .clone()
+        .context("Single sign-on is not configured")
+}
+
+// Fetched once and cached, the provider is only contacted when someone logs in
+async fn metadata(config: &ConfigOidc) -> anyhow::Result<ProviderMetadata> {
+    let mut cached = METADATA.lock().await;
+    if let Some(metadata) = cached.as_ref() {
+        return Ok(metadata.clone());
+    }
+
+    let url = format!(
+        "{}/.well-known/openid-configuration",
+        config.issuer.trim_end_matches('/')
+    );
+    let body = reqwest::get(&url)
+        .await
+        .and_then(|r| r.error_for_status())
+        .with_context(|| format!("Failed to fetch {url}"))?
+        .text()
+        .await?;
+    let metadata: ProviderMetadata =
+        serde_json::from_str(&body).context("Invalid OpenID Connect discovery document")?;
+
+    if metadata.issuer.trim_end_matches('/') != config.issuer.trim_end_matches('/') {
+        bail!(
+            "Issuer of the discovery document ({}) does not match oidc.issuer",
+            metadata.issuer
+        );
+    }
+
+    *cached = Some(metadata.clone());
+    Ok(metadata)
+}
+
+// Forgets the discovery document, e.g. because the issuer was changed in the config
+pub async fn clear_metadata_cache() {
+    *METADATA.lock().await = None;
+}
+
+// Returns the URL of the identity provider to redirect to and the state, which has to be stored
+// in the browser to make sure the callback belongs to a login started there
+pub async fn begin_login() -> anyhow::Result<(String, String)> {
+    let config = oidc_config().await?;
+    let metadata = metadata(&config).await?;
+
+    let state = random_string()?;
+    let nonce = random_string()?;
+    let pkce_verifier = random_string()?;
+    let pkce_challenge = BASE64_URL_SAFE_NO_PAD.encode(Sha256::digest(pkce_verifier.as_bytes()));
+
+    let mut url = Url::parse(&metadata.authorization_endpoint)?;
+    url.query_pairs_mut()
+        .append_pair("response_type", "code")
+        .append_pair("client_id", &config.client_id)
+        .append_pair("redirect_uri", &config.redirect_url)
+        .append_pair("scope", &config.scopes.join(" "))
+        .append_pair("state", &state)
+        .append_pair("nonce", &nonce)
+        .append_pair("code_challenge", &pkce_challenge)
+        .append_pair("code_challenge_method", "S256");
+
+    let now = current_unix_time();
+    let mut pending = PENDING.lock().await;
+    pending.retain(|_, p| p.started_at + PENDING_LOGIN_LIFETIME_SECS > now);
+    pending.insert(
+        state.clone(),
+        PendingLogin {
+            pkce_verifier,
+            nonce,
+            started_at: now,
+        },
+    );
+
+    Ok((url.to_string(), state))
+}
+
+// Payload of a JWT, the signature is not checked (see finish_login)
+fn jwt_claims(jwt: &str) -> anyhow::Result<HashMap<String, Value>> {
+    let payload = jwt.split('.').nth(1).context("Malformed ID token")?;
+    let payload = BASE64_URL_SAFE_NO_PAD
+        .decode(payload.trim_end_matches('='))
+        .context("Malformed ID token")?;
+
+    serde_json::from_slice(&payload).context("Malformed ID token")
+}
+
+// Looks up a claim, nested claims are separated by dots
+fn claim<'a>(claims: &'a HashMap<String, Value>, path: &str) -> Option<&'a Value> {
+    let mut parts = path.split('.');
+    let mut value = claims.get(parts.next()?)?;
+    for part in parts {
+        value = value.get(part)?;
+    }
+
+    Some(value)
+}
+
+// The highest role any value of the roles claim is mapped to, otherwise the default role
+fn map_role(config: &ConfigOidc, claims: &HashMap<String, Value>) -> Option<Role> {
+    let values = match config.roles_claim.as_ref().and_then(|c| claim(claims, c)) {
+        Some(Value::Array(values)) => values.iter().filter_map(Value::as_str).collect(),
+        Some(Value::String(value)) => vec![value.as_str()],
+        _ => vec![],
+    };
+
+    values
+        .iter()
+        .filter_map(|v| config.role_mapping.get(*v))
+        .filter_map(|r| Role::from_str(r))
+        .reduce(|a, b| if b > a { b } else { a })
+        .or_else(|| config.default_role.as_deref().and_then(Role::from_str))
+}
+
+// Exchanges the code from the callback for tokens and returns the user
+pub async fn finish_login(code: &str, state: &str) -> anyhow::Result<OidcUser> {
+    let config = oidc_config().await?;
+    let metadata = metadata(&config).await?;
+
+    let Some(pending) = PENDING.lock().await.remove(state) else {
+        bail!("Unknown or expired login, please try again");
+    };
+    if pending.started_at + PENDING_LOGIN_LIFETIME_SECS <= current_unix_time() {
+        bail!("Login took too long, please try again");
+    }
+
+    let mut params = vec![
+        ("grant_type", "authorization_code"),
+        ("code", code),
+        ("redirect_uri", &config.redirect_url),
+        ("client_id", &config.client_id),
+        ("code_verifier", &pending.pkce_verifier),
+    ];
+    if let Some(secret) = &config.client_secret {
+        params.push(("client_secret", secret));
+    }
+
+    let client = reqwest::Client::new();
+    let res = client
+        .post(&metadata.token_endpoint)
+        .form(&params)
+        .send()
+        .await
+        .context("Failed to reach the token endpoint")?;
+    if !res.status().is_success() {
+        bail!(
+            "Token endpoint responded with {}: {}",
+            res.status(),
+            res.text().await.unwrap_or_default()
+        );
+    }
+    let tokens: TokenResponse =
+        serde_json::from_str(&res.text().await?).context("Invalid token response")?;
+
+    // The ID token comes directly from the token endpoint over TLS, which OpenID Connect Core
+    // 3.1.3.7 allows to use instead of checking the signature, the claims are checked though
+    let mut claims = jwt_claims(&tokens.id_token)?;
+    if claims.get("iss").and_then(Value::as_str) != Some(metadata.issuer.as_str()) {
+        bail!("ID token was issued by someone else");
+    }
+    let audience_ok = match claims.get("aud") {
+        Some(Value::String(aud)) => *aud == config.client_id,
+        Some(Value::Array(auds)) => auds.iter().any(|a| a.as_str() == Some(&config.client_id)),
+        _ => false,
+    };
+    if !audience_ok {
+        bail!("ID token is meant for another client");
+    }
+    let expires_at = claims
+        .get("exp")
+        .and_then(Value::as_u64)
+        .unwrap_or_default();
+    if expires_at + CLOCK_SKEW_SECS < current_unix_time() {
+        bail!("ID token is expired");
+    }
+    if claims.get("nonce").and_then(Value::as_str) != Some(pending.nonce.as_str()) {
+        bail!("ID token belongs to another login");
+    }
+    let sub = claims
+        .get("sub")
+        .and_then(Value::as_str)
+        .context("ID token has no subject")?
+        .to_string();
+
+    // providers often only put some claims into the ID token, the rest is in the userinfo
+    if let Some(userinfo_endpoint) = &metadata.userinfo_endpoint {
+        let userinfo = client
+            .get(userinfo_endpoint)
+            .bearer_auth(&tokens.access_token)
+            .send()
+            .await
+            .and_then(|r| r.error_for_status());
+        if let Ok(res) = userinfo {
+            let userinfo: HashMap<String, Value> =
+                serde_json::from_str(&res.text().await?).unwrap_or_default();
+            // the userinfo must be about the same user
+            if userinfo.get("sub").and_then(Value::as_str) == Some(sub.as_str()) {
+                for (k, v) in userinfo {
+                    claims.entry(k).or_insert(v);
+                }
+            }
+        }
+    }
+
+    // shown in the admin UI and the audit log like the names of local users
+    let username = claim(&claims, &config.username_claim)
+        .and_then(Value::as_str)
+        .and_then(user::normalize_username)
+        .ok_or_else(|| anyhow!("Claim {} is missing or empty", config.username_claim))?;
+    let Some(role) = map_role(&config, &claims) else {
+        bail!("{username} has no role that allows logging in");
+    };
+
+    Ok(OidcUser {
+        subject: format!("{}#{sub}", metadata.issuer),
+        username,
+        role,
+    })
+}
@@ -0,0 +1,63 @@
+use anyhow::anyhow;
+use argon2::{
+    password_hash::{rand_core::OsRng, PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
+    Argon2,
+};
+use sha2::{Digest, Sha256};
+use subtle::ConstantTimeEq;
+
+// Stored password hashes can look like this:
+// $argon2id$...: Argon2 PHC string (created by `hash`)
+// $2b$...: bcrypt hash ($2a$ and $2y$ work as well)
+// 64 hex characters: unsalted SHA-256 digest (legacy, config only)
+pub enum HashKind {
+    Argon2,
+    Bcrypt,
+    Sha256,
+}
+
+impl HashKind {
+    pub fn of(hash: &str) -> Option<Self> {
+        if hash.starts_with("$argon2") {
+            return PasswordHash::new(hash).is_ok().then_some(Self::Argon2);
+        }
+
+        if ["$2a$", "$2b$", "$2y$"].iter().any(|p| hash.starts_with(p)) {
+            return Some(Self::Bcrypt);
+        }
+
+        let is_sha256_hex = hash.len() == 256 / 4 && hash.chars().all(|c| c.is_ascii_hexdigit());
+        is_sha256_hex.then_some(Self::Sha256)
+    }
+}
+
+fn ct_eq(a: &str, b: &str) -> bool {
+    a.as_bytes().ct_eq(b.as_bytes()).into()
+}
+
+// Hashes a password with Argon2id and a random salt, returns a PHC string
+pub fn hash(password: &str) -> anyhow::Result<String> {
+    let salt = SaltString::generate(&mut OsRng);
+    let hash = Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map_err(|e| anyhow!("Failed to hash password: {e}"))?;
+
+    Ok(hash.to_string())
+}
+
+// All comparisons are done in constant time
+pub fn verify(password: &str, hash: &str) -> bool {
+    match HashKind::of(hash) {
+        Some(HashKind::Argon2) => PasswordHash::new(hash).is_ok_and(|h| {
+            Argon2::default()
+                .verify_password(password.as_bytes(), &h)
+                .is_ok()
+        }),
+        Some(HashKind::Bcrypt) => bcrypt::verify(password, hash).unwrap_or_default(),
+        Some(HashKind::Sha256) => ct_eq(
+            &hex::encode(Sha256::digest(password)),
+            &hash.to_ascii_lowercase(),
+        ),
+        None => false,
+    }
+}
@@ -0,0 +1,194 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use notify::{Event, RecommendedWatcher, RecursiveMode, Watcher};
+use tokio::sync::mpsc::{self, UnboundedSender};
+
+use crate::{
+    config::{self, CONFIG},
+    config_monitors,
+    database::{
+        self,
+        audit::{self, AuditEntry},
+    },
+    oidc, secrets,
+};
+
+// Editors often write a file in several steps, the config is reloaded once it was quiet this long
+static DEBOUNCE: Duration = Duration::from_millis(500);
+
+enum Trigger {
+    // The config file or a file in `monitors_dir` changed
+    Files,
+    Hangup,
+}
+
+// Reloads the config when the config file or a file in `monitors_dir` changes and on SIGHUP
+// An invalid config is logged and the old one is kept
+pub async fn reload_thread(config_path: String) {
+    let (tx, mut rx) = mpsc::unbounded_channel();
+
+    // paths whose changes cause a reload, other files in the directory of the config (e.g. the
+    // database) are ignored so they do not wake up the loop or delay a reload
+    let watched: Arc<Mutex<Vec<PathBuf>>> = Arc::default();
+
+    let files_tx = tx.clone();
+    let files_watched = watched.clone();
+    let watcher = notify::recommended_watcher(move |res: notify::Result<Event>| match res {
+        Ok(event) if !event.kind.is_access() => {
+            let watched = files_watched.lock().unwrap();
+            if event
+                .paths
+                .iter()
+                .any(|p| watched.iter().any(|w| p.starts_with(w)))
+            {
+                let _ = files_tx.send(Trigger::Files);
+            }
+        }
+        Ok(_) => {}
+        Err(e) => tracing::warn!("Error while watching the config: {e}"),
+    });
+    let mut watcher = match watcher {
+        Ok(w) => Some(w),
+        Err(e) => {
+            tracing::warn!("Failed to watch the config, only reloading it on SIGHUP: {e}");
+            None
+        }
+    };
+
+    tokio::task::spawn(hangup_thread(tx));
+
+    loop {
+        if let Some(w) = &mut watcher {
+            let paths = watch(w, &config_path).await;
+            *watched.lock().unwrap() = paths;
+        }
+
+        match rx.recv().await {
+            Some(Trigger::Files) => {
+                while let Ok(Some(_)) = tokio::time::timeout(DEBOUNCE, rx.recv()).await {}
+                tracing::info!("Config changed, reloading it");
+            }
+            Some(Trigger::Hangup) => tracing::info!("Received SIGHUP, reloading the config"),
+            None => return,
+        }
+
+        if let Err(e) = reload(&config_path).await {
+            tracing::error!("Failed to reload the config, keeping the old one: {e:#}");
+        }
+    }
+}
+
+#[cfg(unix)]
+async fn hangup_thread(tx: UnboundedSender<Trigger>) {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut hangup = match signal(SignalKind::hangup()) {
+        Ok(s) => s,
+        Err(e) => {
+            tracing::warn!("Failed to listen for SIGHUP: {e}");
+            return;
+        }
+    };
+    while hangup.recv().await.is_some() {
+        if tx.send(Trigger::Hangup).is_err() {
+            return;
+        }
+    }
+}
+
+#[cfg(not(unix))]
+async fn hangup_thread(_tx: UnboundedSender<Trigger>) {}
+
+// Watches the directory of the config file (editors replace files instead of writing to them)
+// and `monitors_dir`, returns the paths whose changes cause a reload
+async fn watch(watcher: &mut RecommendedWatcher, config_path: &str) -> Vec<PathBuf> {
+    let config_path = absolute(Path::new(config_path));
+    let monitors_dir = CONFIG.get().unwrap().lock().await.monitors_dir.clone();
+    let monitors_dir = absolute(&config::relative_to_config(
+        &config_path.to_string_lossy(),
+        &monitors_dir,
+    ));
+
+    if let Some(parent) = config_path.parent() {
+        if let Err(e) = watcher.watch(parent, RecursiveMode::NonRecursive) {
+            tracing::warn!("Failed to watch {}: {e}", parent.display());
+        }
+    }
+    // does not exist until the first monitor file is added, then it is created in the watched
+    // directory of the config file
+    if monitors_dir.is_dir() {
+        let _ = watcher.watch(&monitors_dir, RecursiveMode::NonRecursive);
+    }
+
+    vec![config_path, monitors_dir]
+}
+
+fn absolute(path: &Path) -> PathBuf {
+    match (path.parent(), path.file_name()) {
+        (Some(parent), Some(name)) => {
+            let parent = if parent.as_os_str().is_empty() {
+                Path::new(".")
+            } else {
+                parent
+            };
+            parent
+                .canonicalize()
+                .map(|p| p.join(name))
+                .unwrap_or_else(|_| path.to_path_buf())
+        }
+        _ => path.to_path_buf(),
+    }
+}
+
+// Swaps in the new config and applies the changes that are not read from it on every use
+pub async fn reload(config_path: &str) -> anyhow::Result<()> {
+    let new = config::load_config(config_path).await?;
+    let new_key = new.secret_key()?;
+    if new_key != secrets::key() {
+        database::monitor::check_secret_key(new_key.as_ref()).await?;
+    }
+
+    let mut config = CONFIG.get().unwrap().lock().await;
+    if new.bind_addr != config.bind_addr {
+        tracing::warn!("bind_addr was changed, restart stbmon to apply it");
+    }
+    let issuer_changed =
+        config.oidc.as_ref().map(|o| &o.issuer) != new.oidc.as_ref().map(|o| &o.issuer);
+    if secrets::set_key(new_key) {
+        tracing::info!("secret_key was changed");
+    }
+
+    *config = new;
+    drop(config);
+
+    if issuer_changed {
+        oidc::clear_metadata_cache().await;
+    }
+
+    let stats = config_monitors::reconcile().await?;
+    tracing::info!(
+        "Reloaded the config ({} monitors added, {} updated, {} disabled)",
+        stats.created,
+        stats.updated,
+        stats.disabled
+    );
+    let encrypted = database::monitor::encrypt_plain_secrets().await?;
+    if encrypted > 0 {
+        tracing::info!("Encrypted the secrets of {encrypted} monitors");
+    }
+    audit::record(AuditEntry {
+        actor: "config".to_string(),
+        action: "config.reload",
+        ..Default::default()
+    })
+    .await;
+
+    // new monitors are only checked by the checker once they have a record
+    crate::fix_no_records().await?;
+
+    Ok(())
+}
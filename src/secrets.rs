@@ -0,0 +1,99 @@
+use std::sync::RwLock;
+
+use anyhow::{anyhow, bail, Context};
+use base64::{prelude::BASE64_STANDARD, Engine};
+use chacha20poly1305::{
+    aead::{Aead, KeyInit},
+    ChaCha20Poly1305, Key, Nonce,
+};
+
+// Shown instead of secret monitor fields (HTTP header values and request bodies)
+pub static REDACTED: &str = "••••";
+// Prefix of encrypted values, followed by the base64 encoded nonce and ciphertext
+static PREFIX: &[u8] = b"enc:v1:";
+static KEY_BYTES: usize = 32;
+static NONCE_BYTES: usize = 12;
+
+pub type SecretKey = [u8; KEY_BYTES];
+
+lazy_static::lazy_static! {
+    // Parsed `secret_key` of the current config, not behind the config lock because monitors are
+    // read while it is held
+    static ref KEY: RwLock<Option<SecretKey>> = RwLock::new(None);
+}
+
+pub fn parse_key(key: &str) -> anyhow::Result<SecretKey> {
+    let bytes = BASE64_STANDARD
+        .decode(key.trim())
+        .context("secret_key is not valid base64")?;
+
+    bytes
+        .try_into()
+        .map_err(|_| anyhow!("secret_key must be {KEY_BYTES} bytes long"))
+}
+
+pub fn generate_key() -> anyhow::Result<String> {
+    let mut key = [0u8; KEY_BYTES];
+    if let Err(e) = getrandom::getrandom(&mut key) {
+        bail!("Failed to generate secret key: {e}");
+    }
+
+    Ok(BASE64_STANDARD.encode(key))
+}
+
+pub fn key() -> Option<SecretKey> {
+    *KEY.read().unwrap()
+}
+
+// Returns whether the key was changed
+pub fn set_key(key: Option<SecretKey>) -> bool {
+    let mut current = KEY.write().unwrap();
+    let changed = *current != key;
+    *current = key;
+
+    changed
+}
+
+pub fn is_encrypted(val: &[u8]) -> bool {
+    val.starts_with(PREFIX)
+}
+
+pub fn encrypt(key: &SecretKey, plain: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let mut nonce = [0u8; NONCE_BYTES];
+    if let Err(e) = getrandom::getrandom(&mut nonce) {
+        bail!("Failed to generate nonce: {e}");
+    }
+
+    let ciphertext = ChaCha20Poly1305::new(Key::from_slice(key))
+        .encrypt(Nonce::from_slice(&nonce), plain)
+        .map_err(|_| anyhow!("Failed to encrypt secret"))?;
+
+    let mut res = PREFIX.to_vec();
+    res.extend(
+        BASE64_STANDARD
+            .encode([nonce.as_slice(), &ciphertext].concat())
+            .into_bytes(),
+    );
+
+    Ok(res)
+}
+
+// Values without the prefix were stored before `secret_key` was set and are returned as is
+pub fn decrypt(key: Option<&SecretKey>, val: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let Some(encoded) = val.strip_prefix(PREFIX) else {
+        return Ok(val.to_vec());
+    };
+    let Some(key) = key else {
+        bail!("secret_key is not set");
+    };
+
+    let bytes = BASE64_STANDARD.decode(encoded)?;
+    if bytes.len() < NONCE_BYTES {
+        bail!("Encrypted secret is too short");
+    }
+    let (nonce, ciphertext) = bytes.split_at(NONCE_BYTES);
+
+    ChaCha20Poly1305::new(Key::from_slice(key))
+        .decrypt(Nonce::from_slice(nonce), ciphertext)
+        .map_err(|_| anyhow!("Failed to decrypt secret, was secret_key changed?"))
+}
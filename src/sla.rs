@@ -0,0 +1,115 @@
+use anyhow::Context;
+use chrono::{Datelike, NaiveDate, Utc};
+use itertools::Itertools;
+use serde::Serialize;
+
+use crate::{
+    database::{self, user::Role},
+    time_util::current_unix_time,
+    uptime,
+};
+
+// Returns the response time below which `perc` percent of the response times are (nearest-rank)
+// `sorted` must be sorted in ascending order and not be empty
+pub fn percentile(sorted: &[u64], perc: f64) -> u64 {
+    let rank = (perc / 100. * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+#[derive(Debug, Serialize)]
+pub struct SlaReport {
+    pub monitor_id: u64,
+    pub service_name: String,
+    // YYYY-MM
+    pub month: String,
+    pub since: u64,
+    pub until: u64,
+    pub target_perc: f64,
+    pub availability_perc: Option<f64>,
+    pub downtime_secs: u64,
+    // Time without records (checker offline), not counted as downtime
+    pub unknown_secs: u64,
+    // Allowed downtime for the whole month at the target availability
+    pub error_budget_secs: u64,
+    // Negative if the error budget was exceeded
+    pub error_budget_remaining_secs: i64,
+    pub target_met: Option<bool>,
+}
+
+// Parses YYYY-MM into the unix times of the start of the month and the start of the next month
+pub fn parse_month(month: &str) -> Option<(u64, u64)> {
+    let (year, month) = month.split_once('-')?;
+    let (year, month) = (year.parse::<i32>().ok()?, month.parse::<u32>().ok()?);
+
+    let start = NaiveDate::from_ymd_opt(year, month, 1)?;
+    let end = if month == 12 {
+        NaiveDate::from_ymd_opt(year + 1, 1, 1)?
+    } else {
+        NaiveDate::from_ymd_opt(year, month + 1, 1)?
+    };
+
+    let to_unix = |d: NaiveDate| d.and_hms_opt(0, 0, 0).unwrap().and_utc().timestamp();
+    Some((
+        u64::try_from(to_unix(start)).ok()?,
+        u64::try_from(to_unix(end)).ok()?,
+    ))
+}
+
+pub fn current_month() -> String {
+    let now = Utc::now();
+    format!("{:04}-{:02}", now.year(), now.month())
+}
+
+// Reports for the current month only cover the time until now
+pub async fn monthly_report(
+    mon_id: u64,
+    service_name: String,
+    interval_mins: u64,
+    month: &str,
+    target_perc: f64,
+) -> anyhow::Result<SlaReport> {
+    let (since, month_end) = parse_month(month).context("Invalid month")?;
+    let until = month_end.min(current_unix_time()).max(since);
+
+    let uptime = uptime::for_monitor(mon_id, interval_mins, since, until).await?;
+    let availability_perc = uptime.availability_perc();
+    let downtime_secs = uptime.not_ok();
+    let error_budget_secs = ((month_end - since) as f64 * (100. - target_perc) / 100.) as u64;
+
+    Ok(SlaReport {
+        monitor_id: mon_id,
+        service_name,
+        month: month.to_string(),
+        since,
+        until,
+        target_perc,
+        availability_perc,
+        downtime_secs,
+        unknown_secs: uptime.unknown,
+        error_budget_secs,
+        error_budget_remaining_secs: error_budget_secs as i64 - downtime_secs as i64,
+        target_met: availability_perc.map(|a| a >= target_perc),
+    })
+}
+
+// Reports for all monitors, sorted by monitor id
+// Reports of the monitors visible to role (None = guest)
+pub async fn monthly_reports(
+    month: &str,
+    target_perc: f64,
+    role: Option<Role>,
+) -> anyhow::Result<Vec<SlaReport>> {
+    let mons = database::monitor::get_all(false).await?;
+    let mut reports = vec![];
+    for (id, mon) in mons
+        .into_iter()
+        .filter(|(_, m)| m.is_visible_to(role))
+        .sorted_by_key(|(id, _)| *id)
+    {
+        reports.push(
+            monthly_report(id, mon.service_name, mon.interval_mins, month, target_perc).await?,
+        );
+    }
+
+    Ok(reports)
+}
@@ -0,0 +1,130 @@
+use axum_extra::extract::CookieJar;
+use maud::{html, Markup, PreEscaped, DOCTYPE};
+use reqwest::StatusCode;
+
+use crate::{
+    config::CONFIG,
+    database::{self, session::SessionUser},
+    templates::{render_session_list, HTML_HEADER_GLOB},
+    totp,
+};
+
+pub async fn account_template(cookies: CookieJar) -> (StatusCode, Markup) {
+    let user = match cookies.get("token") {
+        None => None,
+        Some(c) => database::session::get_user(c.value())
+            .await
+            .unwrap_or_default(),
+    };
+    let Some(user) = user else {
+        let render = html!(
+            (DOCTYPE)
+            head {
+                (HTML_HEADER_GLOB)
+                title { "Unauthorized" }
+            }
+
+            body {
+                header { h1 { "Unauthorized" } }
+                p { "Please log in to see this page" }
+                a href="/" { "Back to main page" }
+            }
+        );
+
+        return (StatusCode::UNAUTHORIZED, render);
+    };
+
+    let instance_name = CONFIG.get().unwrap().lock().await.instance_name.clone();
+    let render = html!(
+        (DOCTYPE);
+        html {
+            head {
+                (HTML_HEADER_GLOB);
+                script src="/static/index.js" {};
+                script src="/static/admin.js" {};
+                title { "Account - " (instance_name) }
+            }
+
+            body {
+                header style="display: flex; align-items: center;" {
+                    a href="/" {
+                        img.logo src="/static/logo.png" alt="Logo";
+                    }
+
+                    h1 style="margin-bottom: 16px; margin-left: 16px; padding: 16px" { (instance_name) " - Account" }
+                }
+
+                p { "Logged in as " (user.username) " (" (user.role.as_str()) ")" }
+
+                h2 { "Two-factor authentication" }
+                (render_totp_section(&user, &instance_name).await)
+
+                h2 { "Sessions" }
+                (render_session_list(&user, true).await)
+            }
+        }
+    );
+
+    (StatusCode::OK, render)
+}
+
+async fn render_totp_section(user: &SessionUser, instance_name: &str) -> Markup {
+    let Some(user_id) = user.user_id else {
+        return html!(
+            p {
+                "The config password uses the TOTP secret from " code { "login.totp_secret" }
+                ", generate one with " code { "stb-mon totp-secret" } "."
+            }
+        );
+    };
+
+    let Some(account) = database::user::get_by_id(user_id).await.unwrap_or_default() else {
+        return html!(p { "Your account does not exist anymore" });
+    };
+    if account.sso {
+        return html!(
+            p { "You log in with single sign-on, two-factor authentication is set up at your identity provider." }
+        );
+    }
+    if account.totp_enabled {
+        return html!(
+            p { "Two-factor authentication is enabled." }
+            form #totpform autocomplete="off" action="javascript:onTotpDisable()" {
+                label for="totp-code" { "TOTP or recovery code" }
+                input #totp-code autocomplete="one-time-code";
+                input type="submit" value="Disable";
+            }
+        );
+    }
+
+    let pending = database::user::get_pending_totp_secret(user_id)
+        .await
+        .unwrap_or_default();
+    let Some(secret) = pending else {
+        return html!(
+            @if user.needs_totp {
+                p style="color: #f59e0b" { "Your role requires two-factor authentication, until it is set up you only have the rights of a viewer." }
+            }
+            p { "Two-factor authentication is disabled." }
+            button onclick="onTotpBegin()" { "Set up" }
+        );
+    };
+
+    let qr = totp::provisioning_uri(&secret, instance_name, &user.username)
+        .and_then(|uri| totp::provisioning_qr_svg(&uri));
+
+    html!(
+        p { "Scan the QR code with your authenticator app or enter the secret manually, then enter a code to finish the setup." }
+        @match qr {
+            Ok(svg) => div style="width: 200px" { (PreEscaped(svg)) },
+            Err(e) => p { "Failed to create QR code: " (e) },
+        }
+        p { "Secret: " code { (secret) } }
+        form #totpform autocomplete="off" action="javascript:onTotpEnable()" {
+            label for="totp-code" { "Code" }
+            input #totp-code autocomplete="one-time-code" inputmode="numeric";
+            input type="submit" value="Enable";
+        }
+        pre #recovery-codes hidden {}
+    )
+}
@@ -4,18 +4,19 @@ use reqwest::StatusCode;
 
 use crate::{
     config::CONFIG,
-    database,
-    templates::{render_monitor_list, HTML_HEADER_GLOB},
+    database::{self, api_token::Scope, session::SessionUser, user::Role},
+    templates::{render_monitor_list, render_session_list, HTML_HEADER_GLOB},
+    time_util,
 };
 
 pub async fn admin_template(cookies: CookieJar) -> (StatusCode, Markup) {
-    let is_logged_in = match cookies.get("token") {
-        None => false,
-        Some(c) => database::session::is_valid(c.value())
+    let user = match cookies.get("token") {
+        None => None,
+        Some(c) => database::session::get_user(c.value())
             .await
             .unwrap_or_default(),
     };
-    if !is_logged_in {
+    let Some(user) = user.filter(|u| u.role >= Role::Editor) else {
         let render = html!(
             (DOCTYPE)
             head {
@@ -25,13 +26,13 @@ pub async fn admin_template(cookies: CookieJar) -> (StatusCode, Markup) {
 
             body {
                 header { h1 { "Unauthorized" } }
-                p { "Please log in to see this page" }
+                p { "Please log in as editor or admin to see this page" }
                 a href="/" { "Back to main page" }
             }
         );
 
         return (StatusCode::UNAUTHORIZED, render);
-    }
+    };
 
     let render = html!(
         (DOCTYPE);
@@ -51,7 +52,8 @@ pub async fn admin_template(cookies: CookieJar) -> (StatusCode, Markup) {
                     h1 style="margin-bottom: 16px; margin-left: 16px; padding: 16px" { (CONFIG.get().unwrap().lock().await.instance_name) " - Admin" }
                 }
                 p {
-                    (render_monitor_list(true).await)
+                    (render_monitor_list(true, Some(user.role)).await)
+                    a href="javascript:onCheckDown()" { "Check all monitors that are not up now" }
                     details {
                         summary { "Add" };
                         form #addform autocomplete="off" action="javascript:onAdd()" {
@@ -70,6 +72,17 @@ pub async fn admin_template(cookies: CookieJar) -> (StatusCode, Markup) {
                             label for="timeout" { "Timeout (in seconds)" }
                             input #timeout type="number" placeholder="seconds" min="1" max="60" value="5";
 
+                            label for="visibility" { "Visibility" }
+                            select #visibility {
+                                option value="public" selected { "Public" }
+                                option value="private" { "Logged in users" }
+                                option value="admin" { "Admins" }
+                            }
+                            label {
+                                input #hide-location type="checkbox";
+                                "Hide the location from guests"
+                            }
+
                             br;
 
                             div #tcp-options {
@@ -109,7 +122,7 @@ pub async fn admin_template(cookies: CookieJar) -> (StatusCode, Markup) {
                                 select #http-expected-response onchange="onHttpExpectedResponseChange()" {
                                     option value="any" { "Any" }
                                     option value="sc" { "Status codes" }
-                                    option value="res" disabled { "Specific response" }
+                                    option value="res" { "Specific response" }
                                 };
 
                                 div #http-sc-options hidden {
@@ -118,7 +131,7 @@ pub async fn admin_template(cookies: CookieJar) -> (StatusCode, Markup) {
                                 };
 
                                 div #http-response-options hidden {
-                                    // TODO: front end for body checksum generation
+                                    // filled in by testing the monitor
                                     label for="body-cs" { "Response body adler32 hash" }
                                     input #body-cs type="number" placeholder="adler32" min="0" max="4294967296";
                                 };
@@ -131,12 +144,164 @@ pub async fn admin_template(cookies: CookieJar) -> (StatusCode, Markup) {
                             br;
 
                             input type="submit" value="Add";
+                            " "
+                            input type="button" value="Test" onclick="onTest()";
                         }
+                        pre #test-result hidden {}
+                    }
+                }
+
+                @if user.role >= Role::Admin {
+                    (render_user_list().await)
+                    p { a href="/admin/audit" { "Audit log" } }
+                    p {
+                        "Export monitors including their secrets: "
+                        a href="/api/export/monitors?format=json" { "JSON" }
+                        " "
+                        a href="/api/export/monitors?format=toml" { "TOML" }
                     }
+                    p { a href="/api/admin/backup" { "Download a backup of the database" } }
                 }
+
+                // admins see all sessions, everyone else only their own ones
+                (render_session_list(&user, user.role < Role::Admin).await)
+                (render_api_token_list(&user).await)
             }
         }
     );
 
     (StatusCode::OK, render)
 }
+
+async fn render_user_list() -> Markup {
+    let users = database::user::get_all().await.unwrap_or_default();
+
+    html!(
+        table {
+            caption { "Users" }
+            thead {
+                tr {
+                    th scope="col" { "ID" }
+                    th scope="col" { "Username" }
+                    th scope="col" { "Role" }
+                    th scope="col" { "2FA" }
+                    th scope="col" { "Created" }
+                    th scope="col" { "Actions" }
+                }
+            }
+            tbody {
+                @for user in users {
+                    tr {
+                        td { (user.id) }
+                        td { (user.username) }
+                        td {
+                            select onchange={ "onUserRoleChange(" (user.id) ", this.value)" } {
+                                @for role in [Role::Viewer, Role::Editor, Role::Admin] {
+                                    option value=(role.as_str()) selected[role == user.role] { (role.as_str()) }
+                                }
+                            }
+                        }
+                        td {
+                            @if user.sso {
+                                "SSO"
+                            } @else if user.totp_enabled {
+                                "on " a href={ "javascript:onUserTotpReset(" (user.id) ")" } { "(Reset)" }
+                            } @else {
+                                "off"
+                            }
+                        }
+                        td { (time_util::format_unix_time(user.created_at)) }
+                        td { a href={ "javascript:onUserDelete(" (user.id) ")" } { "Del" } }
+                    }
+                }
+            }
+        }
+        details {
+            summary { "Add user" };
+            form #adduserform autocomplete="off" action="javascript:onAddUser()" {
+                label for="new-username" { "Username" }
+                input #new-username placeholder="e.g. alice";
+
+                label for="new-password" { "Password" }
+                input #new-password type="password" placeholder="at least 8 characters";
+
+                label for="new-role" { "Role" }
+                select #new-role {
+                    option value="viewer" selected { "Viewer" }
+                    option value="editor" { "Editor" }
+                    option value="admin" { "Admin" }
+                }
+                br;
+
+                input type="submit" value="Add";
+            }
+        }
+    )
+}
+
+// Admins see all sessions, everyone else only their own ones
+// Admins see all API tokens, everyone else only their own ones
+async fn render_api_token_list(user: &SessionUser) -> Markup {
+    let filter = (user.role < Role::Admin).then_some(user.user_id);
+    let tokens = database::api_token::get_all(filter)
+        .await
+        .unwrap_or_default();
+    let now = time_util::current_unix_time();
+
+    html!(
+        table {
+            caption { "API tokens" }
+            thead {
+                tr {
+                    th scope="col" { "Name" }
+                    th scope="col" { "User" }
+                    th scope="col" { "Scopes" }
+                    th scope="col" { "Created" }
+                    th scope="col" { "Expires" }
+                    th scope="col" { "Last used" }
+                    th scope="col" { "Actions" }
+                }
+            }
+            tbody {
+                @for token in tokens {
+                    tr {
+                        td { (token.name) }
+                        td { (token.username) }
+                        td { (token.scopes.iter().map(Scope::as_str).collect::<Vec<_>>().join(", ")) }
+                        td { (time_util::format_unix_time(token.created_at)) }
+                        td {
+                            @match token.expires_at {
+                                None => "never",
+                                Some(exp) if exp <= now => span style="color: #888" { "expired" },
+                                Some(exp) => (time_util::format_unix_time(exp)),
+                            }
+                        }
+                        td { (token.last_used_at.map(time_util::format_unix_time).unwrap_or_else(|| "never".to_string())) }
+                        td { a href={ "javascript:onRevokeApiToken(" (token.id) ")" } { "Revoke" } }
+                    }
+                }
+            }
+        }
+        details {
+            summary { "Add API token" };
+            form #addtokenform autocomplete="off" action="javascript:onAddApiToken()" {
+                label for="token-name" { "Name" }
+                input #token-name placeholder="e.g. terraform";
+
+                label { "Scopes" }
+                @for scope in Scope::ALL {
+                    label {
+                        input.token-scope type="checkbox" value=(scope.as_str()) checked[scope == Scope::Read];
+                        (scope.as_str())
+                    }
+                }
+
+                label for="token-expiry" { "Expires after (in days, empty = never)" }
+                input #token-expiry type="number" min="1" max="3650";
+                br;
+
+                input type="submit" value="Add";
+            }
+        }
+    )
+}
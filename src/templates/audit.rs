@@ -0,0 +1,137 @@
+use std::collections::HashMap;
+
+use axum::extract::Query;
+use axum_extra::extract::CookieJar;
+use maud::{html, Markup, DOCTYPE};
+use reqwest::StatusCode;
+use serde_json::Value;
+
+use crate::{
+    config::CONFIG,
+    database::{self, audit, user::Role},
+    templates::HTML_HEADER_GLOB,
+    time_util,
+};
+
+// Query q fields
+// page: page of the audit log, newest entries first (1 if not given)
+pub async fn audit_log_template(
+    q: Query<HashMap<String, String>>,
+    cookies: CookieJar,
+) -> (StatusCode, Markup) {
+    let user = match cookies.get("token") {
+        None => None,
+        Some(c) => database::session::get_user(c.value())
+            .await
+            .unwrap_or_default(),
+    };
+    if !user.is_some_and(|u| u.role >= Role::Admin) {
+        let render = html!(
+            (DOCTYPE)
+            head {
+                (HTML_HEADER_GLOB)
+                title { "Unauthorized" }
+            }
+
+            body {
+                header { h1 { "Unauthorized" } }
+                p { "Please log in as admin to see this page" }
+                a href="/" { "Back to main page" }
+            }
+        );
+
+        return (StatusCode::UNAUTHORIZED, render);
+    }
+
+    let (page, per_page) = audit::page_params(q.get("page"), None);
+    let log = match audit::get_page(page, per_page).await {
+        Ok(log) => log,
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                html!(p { "Failed to read audit log: " (e) }),
+            );
+        }
+    };
+    let last_page = log.total.div_ceil(per_page).max(1);
+
+    let instance_name = CONFIG.get().unwrap().lock().await.instance_name.clone();
+    let render = html!(
+        (DOCTYPE);
+        html {
+            head {
+                (HTML_HEADER_GLOB);
+                title { "Audit log - " (instance_name) }
+            }
+
+            body {
+                header style="display: flex; align-items: center;" {
+                    a href="/" {
+                        img.logo src="/static/logo.png" alt="Logo";
+                    }
+
+                    h1 style="margin-bottom: 16px; margin-left: 16px; padding: 16px" { (instance_name) " - Audit log" }
+                }
+
+                a href="/admin" { "Back to admin page" }
+
+                table {
+                    thead {
+                        tr {
+                            th scope="col" { "Time" }
+                            th scope="col" { "Actor" }
+                            th scope="col" { "IP" }
+                            th scope="col" { "Action" }
+                            th scope="col" { "Target" }
+                            th scope="col" { "Details" }
+                        }
+                    }
+                    tbody {
+                        @for entry in log.entries {
+                            tr {
+                                td { (time_util::format_unix_time(entry.time)) }
+                                td { (entry.actor) }
+                                td { (entry.ip.unwrap_or_default()) }
+                                td { code { (entry.action) } }
+                                td { (entry.target.unwrap_or_default()) }
+                                td {
+                                    (entry.details.unwrap_or_default())
+                                    @if entry.before.is_some() || entry.after.is_some() {
+                                        details {
+                                            summary { "Changes" }
+                                            (render_snapshot("Before", entry.before))
+                                            (render_snapshot("After", entry.after))
+                                        }
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+
+                p {
+                    @if page > 1 {
+                        a href={ "?page=" (page - 1) } { "Newer" } " "
+                    }
+                    "Page " (page) " of " (last_page)
+                    @if page < last_page {
+                        " " a href={ "?page=" (page + 1) } { "Older" }
+                    }
+                }
+            }
+        }
+    );
+
+    (StatusCode::OK, render)
+}
+
+fn render_snapshot(label: &str, snapshot: Option<Value>) -> Markup {
+    let Some(snapshot) = snapshot else {
+        return html!();
+    };
+
+    html!(
+        p { (label) }
+        pre { (serde_json::to_string_pretty(&snapshot).unwrap_or_default()) }
+    )
+}
@@ -0,0 +1,261 @@
+use crate::{
+    config::CONFIG,
+    database,
+    monitor::Monitor,
+    templates::{availability_color, result_to_text_color},
+    time_util::current_unix_time,
+    uptime,
+};
+
+use axum::{
+    extract::{Path, Query},
+    http::{
+        header::{CACHE_CONTROL, CONTENT_TYPE},
+        HeaderName,
+    },
+};
+use axum_extra::extract::CookieJar;
+use maud::html;
+use reqwest::StatusCode;
+use std::collections::HashMap;
+
+type BadgeResponse = (StatusCode, [(HeaderName, &'static str); 2], String);
+
+static LABEL_COLOR: &str = "#555";
+static NO_DATA_COLOR: &str = "#9f9f9f";
+static DEFAULT_PERIOD: &str = "30d";
+// Longest period of the uptime badge in days
+static MAX_PERIOD_DAYS: u64 = 365;
+// Rough width of a character in Verdana 11px, badges do not need to be exact
+static CHAR_WIDTH: usize = 7;
+static TEXT_PADDING: usize = 10;
+
+// Parses 24h, 7d, ... into seconds
+fn parse_period(period: &str) -> Option<u64> {
+    let (n, unit) = period.split_at_checked(period.len().checked_sub(1)?)?;
+    let n = n.parse::<u64>().ok().filter(|n| *n > 0)?;
+    let secs = match unit {
+        "h" => n.checked_mul(60 * 60)?,
+        "d" => n.checked_mul(60 * 60 * 24)?,
+        _ => return None,
+    };
+
+    (secs <= MAX_PERIOD_DAYS * 60 * 60 * 24).then_some(secs)
+}
+
+// Colors may be given without the # because it has to be escaped in URLs
+fn parse_color(color: &str) -> Option<String> {
+    let hex = color.trim_start_matches('#');
+    ([3, 6].contains(&hex.len()) && hex.chars().all(|c| c.is_ascii_hexdigit()))
+        .then(|| format!("#{hex}"))
+}
+
+// Renders a shields.io style badge
+// Query q fields
+// label: text on the left side (depends on the badge if not given)
+// color: color of the right side as hex, e.g. 4c1 (depends on the result if not given)
+// label_color: color of the left side as hex (#555 if not given)
+fn render_badge(
+    status: StatusCode,
+    q: &HashMap<String, String>,
+    label: &str,
+    message: &str,
+    color: &str,
+) -> BadgeResponse {
+    let label = q.get("label").map_or(label, String::as_str);
+    let color = q
+        .get("color")
+        .and_then(|c| parse_color(c))
+        .unwrap_or(color.to_string());
+    let label_color = q
+        .get("label_color")
+        .and_then(|c| parse_color(c))
+        .unwrap_or(LABEL_COLOR.to_string());
+
+    let label_width = label.chars().count() * CHAR_WIDTH + TEXT_PADDING;
+    let message_width = message.chars().count() * CHAR_WIDTH + TEXT_PADDING;
+    let width = label_width + message_width;
+
+    let svg = html!(
+        svg xmlns="http://www.w3.org/2000/svg" width=(width) height="20" role="img" aria-label={ (label) ": " (message) } {
+            title { (label) ": " (message) }
+            linearGradient id="s" x2="0" y2="100%" {
+                stop offset="0" stop-color="#bbb" stop-opacity=".1" {}
+                stop offset="1" stop-opacity=".1" {}
+            }
+            clipPath id="r" {
+                rect width=(width) height="20" rx="3" fill="#fff" {}
+            }
+            g clip-path="url(#r)" {
+                rect width=(label_width) height="20" fill=(label_color) {}
+                rect x=(label_width) width=(message_width) height="20" fill=(color) {}
+                rect width=(width) height="20" fill="url(#s)" {}
+            }
+            g fill="#fff" text-anchor="middle" font-family="Verdana,Geneva,DejaVu Sans,sans-serif" font-size="11" {
+                text x=(label_width / 2) y="15" fill="#010101" fill-opacity=".3" { (label) }
+                text x=(label_width / 2) y="14" { (label) }
+                text x=(label_width + message_width / 2) y="15" fill="#010101" fill-opacity=".3" { (message) }
+                text x=(label_width + message_width / 2) y="14" { (message) }
+            }
+        }
+    );
+
+    (
+        status,
+        [(CONTENT_TYPE, "image/svg+xml"), (CACHE_CONTROL, "no-cache")],
+        svg.into_string(),
+    )
+}
+
+// The monitor if the requesting user (or guest) may see it
+async fn visible_monitor(id: u64, cookies: &CookieJar) -> Option<Monitor> {
+    let role = match cookies.get("token") {
+        None => None,
+        Some(c) => database::session::get_user(c.value())
+            .await
+            .unwrap_or_default()
+            .map(|u| u.role),
+    };
+
+    if role.is_none() {
+        let config = CONFIG.get().unwrap().lock().await;
+        if !config.allow_guest && !config.guest_badges {
+            return None;
+        }
+    }
+
+    database::monitor::get_by_id(id)
+        .await
+        .filter(|m| m.is_visible_to(role))
+}
+
+fn not_found(q: &HashMap<String, String>) -> BadgeResponse {
+    render_badge(
+        StatusCode::NOT_FOUND,
+        q,
+        "monitor",
+        "not found",
+        NO_DATA_COLOR,
+    )
+}
+
+pub async fn status_badge_template(
+    id: Path<u64>,
+    q: Query<HashMap<String, String>>,
+    cookies: CookieJar,
+) -> BadgeResponse {
+    if visible_monitor(*id, &cookies).await.is_none() {
+        return not_found(&q);
+    }
+
+    match database::record::util_last_record(*id).await {
+        Ok(record) => {
+            let (msg, color) = result_to_text_color(&record.result);
+            render_badge(StatusCode::OK, &q, "status", msg, color)
+        }
+        Err(_) => render_badge(StatusCode::OK, &q, "status", "no data", NO_DATA_COLOR),
+    }
+}
+
+// Query q fields
+// period: time span like 24h or 30d, at most 365d (30d if not given)
+pub async fn uptime_badge_template(
+    id: Path<u64>,
+    q: Query<HashMap<String, String>>,
+    cookies: CookieJar,
+) -> BadgeResponse {
+    let Some(mon) = visible_monitor(*id, &cookies).await else {
+        return not_found(&q);
+    };
+
+    let period = q.get("period").map_or(DEFAULT_PERIOD, String::as_str);
+    let Some(period_secs) = parse_period(period) else {
+        return render_badge(
+            StatusCode::BAD_REQUEST,
+            &q,
+            "uptime",
+            "invalid period",
+            NO_DATA_COLOR,
+        );
+    };
+
+    let label = format!("uptime {period}");
+    let now = current_unix_time();
+    let perc = uptime::for_monitor(*id, mon.interval_mins, now - period_secs, now)
+        .await
+        .ok()
+        .and_then(|u| u.availability_perc());
+
+    match perc {
+        Some(perc) => render_badge(
+            StatusCode::OK,
+            &q,
+            &label,
+            &format!("{perc:.2}%"),
+            availability_color(perc),
+        ),
+        None => render_badge(StatusCode::OK, &q, &label, "no data", NO_DATA_COLOR),
+    }
+}
+
+pub async fn response_badge_template(
+    id: Path<u64>,
+    q: Query<HashMap<String, String>>,
+    cookies: CookieJar,
+) -> BadgeResponse {
+    if visible_monitor(*id, &cookies).await.is_none() {
+        return not_found(&q);
+    }
+
+    let Ok(record) = database::record::util_last_record(*id).await else {
+        return render_badge(StatusCode::OK, &q, "response", "no data", NO_DATA_COLOR);
+    };
+
+    // down and errors have no response time, their status is shown instead
+    let (msg, color) = result_to_text_color(&record.result);
+    match record.response_time_ms {
+        Some(ms) => render_badge(StatusCode::OK, &q, "response", &format!("{ms}ms"), color),
+        None => render_badge(StatusCode::OK, &q, "response", msg, color),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_periods() {
+        assert_eq!(parse_period("1h"), Some(60 * 60));
+        assert_eq!(parse_period("24h"), Some(60 * 60 * 24));
+        assert_eq!(parse_period("30d"), Some(60 * 60 * 24 * 30));
+        assert_eq!(parse_period("365d"), Some(60 * 60 * 24 * 365));
+    }
+
+    #[test]
+    fn rejects_bad_periods() {
+        for period in ["", "d", "0d", "-1d", "1", "1m", "1.5d", "366d", "1é", "é"] {
+            assert_eq!(parse_period(period), None, "{period}");
+        }
+        // must not overflow
+        assert_eq!(parse_period(&format!("{}d", u64::MAX)), None);
+        assert_eq!(parse_period(&format!("{}h", u64::MAX / 60)), None);
+    }
+
+    #[test]
+    fn parses_colors() {
+        assert_eq!(parse_color("4c1").as_deref(), Some("#4c1"));
+        assert_eq!(parse_color("#4C11AA").as_deref(), Some("#4C11AA"));
+        for color in [
+            "",
+            "#",
+            "4c",
+            "4c1a",
+            "ggg",
+            "4c1;x",
+            "##4c1\"",
+            "\"><script>",
+        ] {
+            assert_eq!(parse_color(color), None, "{color}");
+        }
+    }
+}
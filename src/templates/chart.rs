@@ -0,0 +1,116 @@
+use maud::{html, Markup};
+
+use crate::{
+    database::record::MonitorRecord, templates::availability_color, time_util, uptime::Uptime,
+};
+
+// (name, time span, uptime bar bucket size) in seconds
+pub static CHART_RANGES: [(&str, u64, u64); 3] = [
+    ("24h", 60 * 60 * 24, 60 * 60),
+    ("7d", 60 * 60 * 24 * 7, 60 * 60 * 24),
+    ("30d", 60 * 60 * 24 * 30, 60 * 60 * 24),
+];
+
+static CHART_WIDTH: u64 = 600;
+static CHART_HEIGHT: u64 = 150;
+// the response time chart averages the response times of this many points
+static CHART_POINTS: u64 = 200;
+static CHART_LINE_COLOR: &str = "#3b82f6";
+
+// Renders one colored bar per bucket, the last bucket ends at `until`
+pub fn render_uptime_bar(
+    records: &[MonitorRecord],
+    until: u64,
+    buckets: u64,
+    bucket_secs: u64,
+    interval_mins: u64,
+) -> Markup {
+    let since = until.saturating_sub(buckets * bucket_secs);
+    let uptimes = Uptime::buckets_from_records(records, until, buckets, bucket_secs, interval_mins);
+
+    html!(
+        svg.uptime-bar viewBox={ "0 0 " (buckets * 10) " 24" } preserveAspectRatio="none" {
+            @for (i, uptime) in uptimes.iter().enumerate() {
+                @let bucket_start = since + i as u64 * bucket_secs;
+                @let (color, text) = match uptime.availability_perc() {
+                    None => ("#444", "no data".to_string()),
+                    Some(perc) => (availability_color(perc), format!("{perc:.2}% up")),
+                };
+                rect x=(i * 10) y="0" width="8" height="24" rx="1" fill=(color) {
+                    title { (time_util::format_unix_time(bucket_start)) ": " (text) }
+                }
+            }
+        }
+    )
+}
+
+// Renders the response times between `since` and `until` as a line chart
+// Records without a response time (down, error) leave a gap in the line
+pub fn render_response_time_chart(records: &[MonitorRecord], since: u64, until: u64) -> Markup {
+    let span = until.saturating_sub(since).max(1);
+    let point_secs = (span / CHART_POINTS).max(1);
+
+    // (sum, count) per point, index 0 is the oldest one
+    let mut points = vec![(0u64, 0u64); (span / point_secs + 1) as usize];
+    for r in records
+        .iter()
+        .filter(|r| (since..=until).contains(&r.time_checked))
+    {
+        let Some(response_time) = r.response_time_ms else {
+            continue;
+        };
+        let point = &mut points[((r.time_checked - since) / point_secs) as usize];
+        point.0 += response_time;
+        point.1 += 1;
+    }
+
+    let averages = points
+        .iter()
+        .map(|(sum, count)| (*count > 0).then(|| sum / count))
+        .collect::<Vec<Option<u64>>>();
+    let max = averages
+        .iter()
+        .flatten()
+        .max()
+        .copied()
+        .unwrap_or_default()
+        .max(1);
+
+    let mut path = String::new();
+    let mut in_line = false;
+    for (i, avg) in averages.iter().enumerate() {
+        let Some(avg) = avg else {
+            in_line = false;
+            continue;
+        };
+
+        let x = i as f32 / (averages.len() - 1).max(1) as f32 * CHART_WIDTH as f32;
+        let y = CHART_HEIGHT as f32 - *avg as f32 / max as f32 * CHART_HEIGHT as f32;
+        path.push_str(&format!(
+            "{}{x:.1} {y:.1} ",
+            if in_line { "L" } else { "M" }
+        ));
+        in_line = true;
+    }
+
+    html!(
+        svg.response-chart viewBox={ "0 -10 " (CHART_WIDTH) " " (CHART_HEIGHT + 30) } {
+            line x1="0" y1=(CHART_HEIGHT) x2=(CHART_WIDTH) y2=(CHART_HEIGHT) stroke="#888" stroke-width="1" {}
+            line x1="0" y1="0" x2=(CHART_WIDTH) y2="0" stroke="#444" stroke-width="1" stroke-dasharray="4" {}
+            text x="2" y="-2" font-size="10" fill="#888" { (max) "ms" }
+            text x="2" y=(CHART_HEIGHT + 14) font-size="10" fill="#888" {
+                (time_util::format_unix_time(since))
+            }
+            text x=(CHART_WIDTH - 2) y=(CHART_HEIGHT + 14) font-size="10" fill="#888" text-anchor="end" {
+                (time_util::format_unix_time(until))
+            }
+            @if path.is_empty() {
+                text x=(CHART_WIDTH / 2) y=(CHART_HEIGHT / 2) font-size="12" fill="#888" text-anchor="middle" {
+                    "No response times in this time span"
+                }
+            } @else {
+                path d=(path.trim_end()) fill="none" stroke=(CHART_LINE_COLOR) stroke-width="1.5" {}
+            }
+        }
+    )
+}
@@ -4,19 +4,23 @@ use reqwest::StatusCode;
 
 use crate::{
     config::CONFIG,
-    database,
-    templates::{render_monitor_list, HTML_HEADER_GLOB},
+    database::{self, user::Role},
+    templates::{render_incident_list, render_monitor_list, visible_incidents, HTML_HEADER_GLOB},
 };
 
 pub async fn index_template(cookies: CookieJar) -> (StatusCode, Markup) {
-    let is_logged_in = match cookies.get("token") {
-        None => false,
-        Some(c) => database::session::is_valid(c.value())
+    let user = match cookies.get("token") {
+        None => None,
+        Some(c) => database::session::get_user(c.value())
             .await
             .unwrap_or_default(),
     };
+    let is_logged_in = user.is_some();
 
-    let allow_guest = CONFIG.get().unwrap().lock().await.allow_guest;
+    let (allow_guest, oidc) = {
+        let config = CONFIG.get().unwrap().lock().await;
+        (config.allow_guest, config.oidc.clone())
+    };
     let can_view = !(!allow_guest && !is_logged_in);
 
     let render = html! {
@@ -37,12 +41,31 @@ pub async fn index_template(cookies: CookieJar) -> (StatusCode, Markup) {
                     h1 style="margin-bottom: 16px; margin-left: 16px; padding: 16px" { (CONFIG.get().unwrap().lock().await.instance_name) }
 
                     div style="position: absolute; top: 5px; right: 5px" {
-                        @if !is_logged_in {
-                            label for="password" { "Login: " }
-                            input #password placeholder="Password" type="password";
-                            button style="background: #181818" onclick="onLogin()" { "OK" }
+                        @if let Some(user) = &user {
+                            p {
+                                "You are logged in as " (user.username) " (" (user.role.as_str()) ")"
+                                @if user.role >= Role::Editor {
+                                    " - " a href="/admin" { "ADMIN" }
+                                }
+                                " - " a href="/account" { "Account" }
+                                " - " a href="javascript:onLogout()" { "Log out" }
+                            }
+                            @if user.needs_totp {
+                                p style="color: #f59e0b" {
+                                    "Set up two-factor authentication on the " a href="/account" { "account page" } " to get the rights of your role"
+                                }
+                            }
                         } @else {
-                            p { "You are logged in - " a href="/admin" { "ADMIN" } }
+                            @if !oidc.as_ref().is_some_and(|o| o.disable_password_login) {
+                                label for="username" { "Login: " }
+                                input #username placeholder="Username (optional)";
+                                input #password placeholder="Password" type="password";
+                                input #otp placeholder="TOTP code" autocomplete="one-time-code" hidden;
+                                button style="background: #181818" onclick="onLogin()" { "OK" }
+                            }
+                            @if let Some(oidc) = &oidc {
+                                " " a href="/api/oidc/login" { (oidc.button_label) }
+                            }
                         }
                     };
 
@@ -50,7 +73,16 @@ pub async fn index_template(cookies: CookieJar) -> (StatusCode, Markup) {
 
                 @if can_view {
                     body {
-                        (render_monitor_list(false).await)
+                        @let role = user.as_ref().map(|u| u.role);
+                        (render_monitor_list(false, role).await)
+                        p { a href="/sla" { "SLA report" } }
+
+                        @let incidents = database::incident::recent(10).await.unwrap_or_default();
+                        @let incidents = visible_incidents(incidents, role).await;
+                        // shown by index.js when the first incident is opened
+                        div #incidents hidden[incidents.is_empty()] {
+                            (render_incident_list(&incidents, true, false, role).await)
+                        }
                     }
                 }
                 @else {
@@ -2,17 +2,34 @@ use itertools::Itertools;
 use maud::{html, Markup, PreEscaped};
 
 use crate::{
-    database::{self, record::RecordResult},
+    database::{
+        self,
+        incident::Incident,
+        record::{RecordResult, HIDDEN_INFO},
+        session::SessionUser,
+        user::Role,
+    },
     time_util,
 };
 
+mod account;
 mod admin;
+mod audit;
+mod badge;
+mod chart;
 mod index;
 mod monitor;
+mod sla;
+mod status;
 
+pub use account::account_template;
 pub use admin::admin_template;
+pub use audit::audit_log_template;
+pub use badge::{response_badge_template, status_badge_template, uptime_badge_template};
 pub use index::index_template;
 pub use monitor::monitor_template;
+pub use sla::sla_template;
+pub use status::status_page_template;
 
 #[rustfmt::skip]
 static HTML_HEADER_GLOB: PreEscaped<&'static str> = PreEscaped(concat!(
@@ -21,12 +38,14 @@ r#"<link rel="stylesheet" href="https://cdn.jsdelivr.net/npm/@exampledev/new.css
 r#"<link rel="shortcut icon" type="image/png" href="/static/favicon.png">"#,
 "<style>",
     "body { max-width: 65%; }",
-    "#addform {",
+    "#addform, #adduserform, #addtokenform, #totpform {",
         "input { min-width: 30%; display: block; }",
         "label { margin-down: 3px; display: block; }",
     "}",
     ".logo { image-rendering: pixelated; margin-bottom: 0; height: 48px }",
     "header { padding-top: 0.5rem; padding-bottom: 0.2rem; }",
+    ".uptime-bar { display: block; width: 100%; height: 24px; }",
+    ".response-chart { display: block; width: 100%; }",
 "</style>"
 ));
 
@@ -39,10 +58,25 @@ fn result_to_text_color(res: &RecordResult) -> (&'static str, &'static str) {
     }
 }
 
-async fn render_monitor_list(admin: bool) -> Markup {
+// Color of an availability in percent, as used by the uptime bars and badges
+fn availability_color(perc: f64) -> &'static str {
+    let result = if perc >= 99.9 {
+        RecordResult::Ok
+    } else if perc >= 95. {
+        RecordResult::Unexpected
+    } else {
+        RecordResult::Down
+    };
+
+    result_to_text_color(&result).1
+}
+
+// role = None: guest
+async fn render_monitor_list(admin: bool, role: Option<Role>) -> Markup {
     let mons = database::monitor::get_all(false).await.unwrap();
     let mons = mons
         .into_iter()
+        .filter(|(_, m)| m.is_visible_to(role))
         .sorted_by(|(i1, _), (i2, _)| i1.cmp(i2))
         .sorted_by(|(_, m1), (_, m2)| m2.enabled.cmp(&m1.enabled));
 
@@ -58,30 +92,34 @@ async fn render_monitor_list(admin: bool) -> Markup {
                     th scope="col" { "Interval" }
                     @if admin {
                         th scope="col" { "Enabled" }
+                        th scope="col" { "Visibility" }
                         th scope="col" { "Actions" }
                     }
                 }
             }
             tbody {
                 @for (id, mon) in mons {
-                    @let Ok(last_record) = crate::database::record::util_last_record(id).await else {
-                        continue;
-                    };
+                    // None for imported disabled monitors, they are only checked once they are enabled
+                    @let last_record = crate::database::record::util_last_record(id).await.ok();
 
-                    @let background_color = match last_record.result {
-                        RecordResult::Ok => "rgba(0, 0, 0, 0)",
-                        RecordResult::Unexpected => "rgba(245, 204, 0, 0.1)",
-                        RecordResult::Down => "rgba(255, 0, 0, 0.1)",
-                        RecordResult::Err => "rgba(125, 21, 21, 0.1)",
+                    @let background_color = match last_record.as_ref().map(|r| r.result) {
+                        None | Some(RecordResult::Ok) => "rgba(0, 0, 0, 0)",
+                        Some(RecordResult::Unexpected) => "rgba(245, 204, 0, 0.1)",
+                        Some(RecordResult::Down) => "rgba(255, 0, 0, 0.1)",
+                        Some(RecordResult::Err) => "rgba(125, 21, 21, 0.1)",
                     };
 
-                    tr style={ "background-color:" (background_color) } {
+                    // data attributes are used by the live updates in index.js
+                    tr data-monitor-id=(id) data-checked-at=[last_record.as_ref().map(|r| r.time_checked)] style={ "background-color:" (background_color) } {
                         @if admin { td { (id) } }
-                        td { (mon.service_name) }
+                        td .service-name { (mon.service_name) }
                         td {
                             @let loc = mon.service_data.service_location_str();
                             @let tloc = loc.split_at_checked(126).map_or(loc.as_str(), |s| s.0);
-                            @if loc.starts_with("http") {
+                            @if !mon.shows_location_to(role) {
+                                "Hidden"
+                            }
+                            @else if loc.starts_with("http") {
                                 a href=(loc) { (tloc) }
                             }
                             @else {
@@ -89,27 +127,46 @@ async fn render_monitor_list(admin: bool) -> Markup {
                             }
                         }
                         td {
-                            @let (msg, color) = result_to_text_color(&last_record.result);
-                            (time_util::time_diff_now(last_record.time_checked as i64))
-                            " ago ("
-                            span title=(last_record.info) style={ "color: " (color) } {
-                                (msg)
+                            @if let Some(last_record) = &last_record {
+                                @let (msg, color) = result_to_text_color(&last_record.result);
+                                span.checked-ago { (time_util::time_diff_now(last_record.time_checked as i64)) }
+                                " ago ("
+                                @let info = if mon.shows_location_to(role) { &last_record.info } else { HIDDEN_INFO };
+                                span.result title=(info) style={ "color: " (color) } {
+                                    (msg)
+                                }
+                                span.response-time {
+                                    @if let Some(time) = last_record.response_time_ms {
+                                        " " (time) "ms"
+                                    }
+                                }
+                                ")";
                             }
-
-                            @if let Some(time) = last_record.response_time_ms {
-                                " " (time) "ms"
+                            @else {
+                                "Not checked yet"
                             }
-                            ")";
                         };
                         td { (mon.interval_mins) " min" };
                         @if admin {
                             td { (mon.enabled) };
                             td {
-                                a href={ "javascript:onDelete(" (id) ")" } { "Del" };
-                                " "
-                                a href={ "javascript:onToggle(" (id) ")" } {
-                                    @if mon.enabled { "Dis" }
-                                    @else { "En" }
+                                (mon.visibility.as_str())
+                                @if mon.hide_location { " (location hidden)" }
+                            };
+                            td {
+                                @if mon.enabled {
+                                    a href={ "javascript:onCheck(" (id) ")" } { "Check" };
+                                    " "
+                                }
+                                @if let Some(key) = &mon.config_key {
+                                    span title={ "Managed by the config, key " (key) } { "Config" }
+                                } @else {
+                                    a href={ "javascript:onDelete(" (id) ")" } { "Del" };
+                                    " "
+                                    a href={ "javascript:onToggle(" (id) ")" } {
+                                        @if mon.enabled { "Dis" }
+                                        @else { "En" }
+                                    }
                                 }
                             }
                         }
@@ -120,3 +177,138 @@ async fn render_monitor_list(admin: bool) -> Markup {
         }
     )
 }
+
+// Drops incidents of monitors the user can not see
+async fn visible_incidents(incidents: Vec<Incident>, role: Option<Role>) -> Vec<Incident> {
+    let Ok(mons) = database::monitor::get_all(false).await else {
+        return vec![];
+    };
+
+    incidents
+        .into_iter()
+        .filter(|i| {
+            mons.get(&i.monitor_id)
+                .is_some_and(|m| m.is_visible_to(role))
+        })
+        .collect()
+}
+
+// The info of incidents is hidden from users their monitor hides its location from
+async fn render_incident_list(
+    incidents: &[Incident],
+    show_monitor: bool,
+    admin: bool,
+    role: Option<Role>,
+) -> Markup {
+    let mons = database::monitor::get_all(false).await.unwrap_or_default();
+
+    html!(
+        table {
+            caption { "Incidents" }
+            thead {
+                tr {
+                    @if show_monitor { th scope="col" { "Monitor" } }
+                    th scope="col" { "Started" }
+                    th scope="col" { "Duration" }
+                    th scope="col" { "Status" }
+                    th scope="col" { "Info" }
+                    th scope="col" { "Comment" }
+                }
+            }
+            tbody {
+                @for incident in incidents {
+                    // data attributes are used by the live updates in index.js
+                    tr data-incident-id=(incident.id) data-started-at=(incident.started_at) data-ended-at=[incident.ended_at] {
+                        @if show_monitor {
+                            td {
+                                @let mon_name = mons
+                                    .get(&incident.monitor_id)
+                                    .map(|m| m.service_name.as_str())
+                                    .unwrap_or_default();
+                                a href={ "/monitor/" (incident.monitor_id) } {
+                                    @if mon_name.is_empty() { "#" (incident.monitor_id) }
+                                    @else { (mon_name) }
+                                }
+                            }
+                        }
+                        td { (time_util::format_unix_time(incident.started_at)) }
+                        td .duration {
+                            (time_util::format_duration(incident.duration_secs()))
+                            @if incident.ended_at.is_none() { " (ongoing)" }
+                        }
+                        td {
+                            @let (msg, color) = result_to_text_color(&incident.result);
+                            span style={ "color: " (color) } { (msg) }
+                        }
+                        td .info {
+                            @if mons.get(&incident.monitor_id).is_some_and(|m| m.shows_location_to(role)) {
+                                (incident.first_info)
+                                @if incident.last_info != incident.first_info {
+                                    br;
+                                    "Last: " (incident.last_info)
+                                }
+                            }
+                            @else {
+                                (HIDDEN_INFO)
+                            }
+                        }
+                        td {
+                            (incident.comment.clone().unwrap_or_default())
+                            @if admin {
+                                " "
+                                a href={ "javascript:onIncidentComment(" (incident.id) ")" } { "Edit" }
+                                " "
+                                a href={ "javascript:onIncidentPublish(" (incident.id) ")" } {
+                                    @if incident.published { "Unpublish" }
+                                    @else { "Publish" }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    )
+}
+
+// only_own: only the sessions of `user`, all sessions otherwise
+async fn render_session_list(user: &SessionUser, only_own: bool) -> Markup {
+    let filter = only_own.then_some(user.user_id);
+    let sessions = database::session::get_active(filter)
+        .await
+        .unwrap_or_default();
+
+    html!(
+        table {
+            caption { "Active sessions" }
+            thead {
+                tr {
+                    th scope="col" { "User" }
+                    th scope="col" { "Created" }
+                    th scope="col" { "Expires" }
+                    th scope="col" { "IP" }
+                    th scope="col" { "User agent" }
+                    th scope="col" { "Actions" }
+                }
+            }
+            tbody {
+                @for session in sessions {
+                    tr {
+                        td { (session.username) }
+                        td { (session.created_at.map(time_util::format_unix_time).unwrap_or_default()) }
+                        td { (time_util::format_unix_time(session.expires_at)) }
+                        td { (session.ip.unwrap_or_default()) }
+                        td { (session.user_agent.unwrap_or_default()) }
+                        td {
+                            @if session.id == user.session_id {
+                                "current"
+                            } @else {
+                                a href={ "javascript:onRevokeSession(" (session.id) ")" } { "Revoke" }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    )
+}
@@ -3,25 +3,47 @@ use crate::{
     database::{
         self,
         record::{MonitorRecord, RecordResult},
+        user::Role,
+    },
+    monitor::{Monitor, MonitorData, Visibility},
+    sla::percentile,
+    templates::{
+        chart::{render_response_time_chart, render_uptime_bar, CHART_RANGES},
+        render_incident_list, result_to_text_color, HTML_HEADER_GLOB,
     },
-    monitor::Monitor,
-    templates::{result_to_text_color, HTML_HEADER_GLOB},
     time_util::{self, current_unix_time},
+    uptime::Uptime,
 };
 
-use axum::extract::Path;
+use axum::extract::{Path, Query};
 use axum_extra::extract::CookieJar;
 use itertools::Itertools;
 use maud::{html, Markup, PreEscaped, DOCTYPE};
 use reqwest::StatusCode;
+use std::collections::HashMap;
 
 #[allow(clippy::let_unit_value)]
-async fn render_monitor_info(mon: Monitor, mon_id: u64) -> Markup {
+async fn render_monitor_info(
+    mon: Monitor,
+    mon_id: u64,
+    role: Option<Role>,
+    chart_range: &str,
+) -> Markup {
     let time = current_unix_time();
+    let can_edit = role.is_some_and(|r| r >= Role::Editor);
+    let shows_location = mon.shows_location_to(role);
 
     let Ok(records) = database::record::records_from_mon(mon_id).await else {
         return html!(p { (format!("Internal server error")) });
     };
+    // imported disabled monitors are only checked once they are enabled
+    if records.is_empty() {
+        return html!(p { "The monitor was not checked yet" });
+    }
+
+    let Ok(incidents) = database::incident::incidents_from_mon(mon_id).await else {
+        return html!(p { (format!("Internal server error")) });
+    };
 
     html!(
         div {
@@ -33,9 +55,11 @@ async fn render_monitor_info(mon: Monitor, mon_id: u64) -> Markup {
                     th scope="row" { "Service name" }
                     td { (mon.service_name) }
                 }
-                tr {
-                    th scope="row" { "Service location" }
-                    td { (mon.service_data.service_location_str()) }
+                @if shows_location {
+                    tr {
+                        th scope="row" { "Service location" }
+                        td { (mon.service_data.service_location_str()) }
+                    }
                 }
                 tr {
                     th scope="row" { "Enabled" }
@@ -51,12 +75,61 @@ async fn render_monitor_info(mon: Monitor, mon_id: u64) -> Markup {
                 }
             }
 
-            table {
-                caption { "Monitor-specific" }
-                @for (k, v) in mon.service_data.as_hashmap().into_iter().sorted() {
-                    tr {
-                        th { (k) }
-                        td { (v) }
+            @if shows_location {
+                table {
+                    caption { "Monitor-specific" }
+                    @for (k, v) in mon.service_data.as_hashmap().into_iter().sorted() {
+                        tr {
+                            th { (k) }
+                            td { (v) }
+                        }
+                    }
+                }
+            }
+
+            @if can_edit && mon.enabled {
+                p { a href={ "javascript:onCheck(" (mon_id) ")" } { "Check now" } }
+            }
+
+            @if can_edit && mon.config_key.is_none() {
+                details {
+                    summary { "Visibility" };
+                    form #visibilityform autocomplete="off" action={ "javascript:onVisibilityChange(" (mon_id) ")" } {
+                        select #edit-visibility {
+                            @for (value, label) in [(Visibility::Public, "Public"), (Visibility::Private, "Logged in users"), (Visibility::Admin, "Admins")] {
+                                option value=(value.as_str()) selected[mon.visibility == value] { (label) }
+                            }
+                        }
+                        label {
+                            input #edit-hide-location type="checkbox" checked[mon.hide_location];
+                            "Hide the location from guests"
+                        }
+                        br;
+
+                        input type="submit" value="Save";
+                    }
+                }
+            }
+
+            // headers and the body are secret, they can be replaced but are not filled in
+            @if can_edit && mon.config_key.is_none() && matches!(mon.service_data, MonitorData::Http { .. }) {
+                details {
+                    summary { "Replace request headers and body" };
+                    form #editrequestform autocomplete="off" action={ "javascript:onEditRequest(" (mon_id) ")" } {
+                        label {
+                            input #replace-headers type="checkbox";
+                            "Replace headers"
+                        }
+                        textarea #edit-headers placeholder="content-type:application/json" {}
+
+                        label {
+                            input #replace-body type="checkbox";
+                            "Replace body"
+                        }
+                        textarea #edit-body {}
+                        br;
+
+                        input type="submit" value="Save";
                     }
                 }
             }
@@ -86,11 +159,6 @@ async fn render_monitor_info(mon: Monitor, mon_id: u64) -> Markup {
                     td { (last_record.response_time_ms.map(|n| n.to_string()).unwrap_or_else(|| "N/A ".to_string())) "ms" }
                 }
 
-                @let records_last_30d = records
-                    .iter()
-                    .filter(|r| r.time_checked >= 60 * 60 * 24 * 30)
-                    .collect::<Vec<&MonitorRecord>>();
-
                 @for (timespan, t) in [
                     ("4h", 60 * 60 * 4),
                     ("12h", 60 * 60 * 12),
@@ -99,39 +167,28 @@ async fn render_monitor_info(mon: Monitor, mon_id: u64) -> Markup {
                     ("7d", 60 * 60 * 24 * 7),
                     ("14d", 60 * 60 * 24 * 14),
                     ("30d", 60 * 60 * 24 * 30)
-                ].iter().filter(|(_, t)| *t < time_util::current_unix_time() - first_record_time) {
+                ].iter().filter(|(_, t)| *t < time - first_record_time) {
                     tr {
                         th scope="row" { "Last " (timespan) }
-                        @let records = records_last_30d.iter().filter(|r| r.time_checked > time - t).collect::<Vec<&&MonitorRecord>>();
-
-                        @let (amount_ok, amount_ux, amount_down, amount_err) = records.iter().fold((0f32, 0f32, 0f32, 0f32), |mut amounts, r| {
-                            match r.result {
-                                RecordResult::Ok => amounts.0 += 1.,
-                                RecordResult::Unexpected => amounts.1 += 1.,
-                                RecordResult::Down => amounts.2 += 1.,
-                                RecordResult::Err => amounts.3 += 1.,
-                            };
-
-                            amounts
-                        });
-
-                        @let perc_ok = amount_ok / records.len() as f32 * 100.;
-                        @let perc_ux = amount_ux / records.len() as f32 * 100.;
-                        @let perc_down = amount_down / records.len() as f32 * 100.;
-                        @let perc_err = amount_err / records.len() as f32 * 100.;
+                        @let uptime = Uptime::from_records(&records, time - t, time, mon.interval_mins);
+                        @let records = records.iter().filter(|r| r.time_checked > time - t).collect::<Vec<&MonitorRecord>>();
 
                         @let mut statuses: Vec<String> = vec![];
-                        @for (s, p) in [
-                            (RecordResult::Ok, perc_ok),
-                            (RecordResult::Unexpected, perc_ux),
-                            (RecordResult::Down, perc_down),
-                            (RecordResult::Err, perc_err)
+                        @for s in [
+                            RecordResult::Ok,
+                            RecordResult::Unexpected,
+                            RecordResult::Down,
+                            RecordResult::Err
                         ] {
+                            @let p = uptime.perc(&s).unwrap_or_default();
                             @if p > 0. {
                                 @let (msg, color) = result_to_text_color(&s);
                                 @let () = statuses.push(html!(span style={ "color:" (color) } { (format!("{p:.2}")) "% " (msg) }).into_string());
                             }
                         }
+                        @if let Some(p) = uptime.unknown_perc().filter(|p| *p > 0.) {
+                            @let () = statuses.push(html!(span title="No records, the checker was offline" style="color: #888" { "(" (format!("{p:.2}")) "% unknown)" }).into_string());
+                        }
 
                         td { (PreEscaped(statuses.join(" "))) }
 
@@ -140,31 +197,71 @@ async fn render_monitor_info(mon: Monitor, mon_id: u64) -> Markup {
                         @if response_times.is_empty() {
                             td { "N/A" }
                         } @else {
-                            @let lowest_response_time = response_times.iter().min().unwrap();
-                            @let highest_response_time = response_times.iter().max().unwrap();
-                            @let avg_response_time = response_times.iter().sum::<u64>() / records.len() as u64;
+                            @let response_times = response_times.into_iter().sorted().collect::<Vec<u64>>();
 
-                            td { "L: " (lowest_response_time) "ms H: " (highest_response_time) "ms Avg: " (avg_response_time) "ms" }
+                            td {
+                                @for p in [50., 90., 95., 99.] {
+                                    "p" (p) ": " (percentile(&response_times, p)) "ms "
+                                }
+                            }
                         }
                     }
                 }
             }
+
+            h3 { "Charts" }
+            @let (range_name, range_secs, bucket_secs) = CHART_RANGES
+                .iter()
+                .find(|(name, _, _)| *name == chart_range)
+                .unwrap_or(&CHART_RANGES[0]);
+            p {
+                "Time span: "
+                @for (name, _, _) in &CHART_RANGES {
+                    @if name == range_name {
+                        strong { (name) }
+                    } @else {
+                        a href={ "?range=" (name) } { (name) }
+                    }
+                    " "
+                }
+            }
+            (render_response_time_chart(&records, time - range_secs, time))
+            (render_uptime_bar(&records, time, range_secs / bucket_secs, *bucket_secs, mon.interval_mins))
+
+            @if incidents.is_empty() {
+                p { "No incidents recorded" }
+            } @else {
+                (render_incident_list(&incidents, false, can_edit, role).await)
+            }
         }
     )
 }
 
-pub async fn monitor_template(monitor_id: Path<u64>, cookies: CookieJar) -> (StatusCode, Markup) {
-    let is_logged_in = match cookies.get("token") {
-        None => false,
-        Some(c) => database::session::is_valid(c.value())
+// Query q fields
+// range: time span of the charts, one of {24h, 7d, 30d} (24h if not given)
+pub async fn monitor_template(
+    monitor_id: Path<u64>,
+    q: Query<HashMap<String, String>>,
+    cookies: CookieJar,
+) -> (StatusCode, Markup) {
+    let user = match cookies.get("token") {
+        None => None,
+        Some(c) => database::session::get_user(c.value())
             .await
             .unwrap_or_default(),
     };
+    let role = user.map(|u| u.role);
+    let is_logged_in = role.is_some();
+    let can_edit = role.is_some_and(|r| r >= Role::Editor);
 
     let allow_guest = CONFIG.get().unwrap().lock().await.allow_guest;
     let can_view = !(!allow_guest && !is_logged_in);
 
-    let Some(monitor) = database::monitor::get_by_id(*monitor_id).await else {
+    // hidden monitors look like they do not exist
+    let monitor = database::monitor::get_by_id(*monitor_id)
+        .await
+        .filter(|m| m.is_visible_to(role));
+    let Some(monitor) = monitor else {
         let render = html!(
             (DOCTYPE)
             html {
@@ -188,15 +285,18 @@ pub async fn monitor_template(monitor_id: Path<u64>, cookies: CookieJar) -> (Sta
         html {
             head {
                 (HTML_HEADER_GLOB)
+                @if can_edit { script src="/static/admin.js" {}; }
                 @if can_view { title { (monitor.service_name) " - " (CONFIG.get().unwrap().lock().await.instance_name) } }
                 @else { title { "Unauthorized" } }
             }
 
             @if can_view {
-                @let mon_name = if monitor.service_name.is_empty() {
-                    &monitor.service_data.service_location_str()
+                @let mon_name = if !monitor.service_name.is_empty() {
+                    monitor.service_name.clone()
+                } else if monitor.shows_location_to(role) {
+                    monitor.service_data.service_location_str()
                 } else {
-                    &monitor.service_name
+                    format!("#{}", *monitor_id)
                 };
                 @let mon_name = mon_name.split_at_checked(24).map_or(mon_name.as_str(), |s| s.0);
 
@@ -207,7 +307,7 @@ pub async fn monitor_template(monitor_id: Path<u64>, cookies: CookieJar) -> (Sta
 
                     h1 style="margin-bottom: 16px; margin-left: 16px; padding: 16px" { "Monitor info: " (mon_name) }
                 }
-                (render_monitor_info(monitor, *monitor_id).await)
+                (render_monitor_info(monitor, *monitor_id, role, q.get("range").map_or("", String::as_str)).await)
             }
             @else {
                 header { h1 { "Unauthorized" } }
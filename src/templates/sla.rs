@@ -0,0 +1,148 @@
+use crate::{
+    config::CONFIG,
+    database,
+    database::record::RecordResult,
+    sla::{self, SlaReport},
+    templates::{result_to_text_color, HTML_HEADER_GLOB},
+    time_util,
+};
+
+use axum::extract::Query;
+use axum_extra::extract::CookieJar;
+use maud::{html, Markup, DOCTYPE};
+use reqwest::StatusCode;
+use std::collections::HashMap;
+
+fn render_sla_reports(reports: &[SlaReport]) -> Markup {
+    html!(
+        table {
+            caption { "Availability" }
+            thead {
+                tr {
+                    th scope="col" { "Service name" }
+                    th scope="col" { "Availability" }
+                    th scope="col" { "Target" }
+                    th scope="col" { "Downtime" }
+                    th scope="col" { "Unknown" }
+                    th scope="col" { "Error budget left" }
+                }
+            }
+            tbody {
+                @for report in reports {
+                    tr {
+                        td { a href={ "/monitor/" (report.monitor_id) } { (report.service_name) } }
+                        td {
+                            @match (report.availability_perc, report.target_met) {
+                                (Some(a), Some(met)) => {
+                                    @let (_, color) = result_to_text_color(if met { &RecordResult::Ok } else { &RecordResult::Down });
+                                    span style={ "color: " (color) } { (format!("{a:.3}")) "%" }
+                                }
+                                _ => { "N/A" }
+                            }
+                        }
+                        td { (report.target_perc) "%" }
+                        td { (time_util::format_duration(report.downtime_secs)) }
+                        td { (time_util::format_duration(report.unknown_secs)) }
+                        td {
+                            @if report.error_budget_remaining_secs >= 0 {
+                                (time_util::format_duration(report.error_budget_remaining_secs as u64))
+                            } @else {
+                                "exceeded by " (time_util::format_duration(report.error_budget_remaining_secs.unsigned_abs()))
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    )
+}
+
+// Query q fields
+// month: month of the report as YYYY-MM (current month if not given)
+pub async fn sla_template(
+    q: Query<HashMap<String, String>>,
+    cookies: CookieJar,
+) -> (StatusCode, Markup) {
+    let role = match cookies.get("token") {
+        None => None,
+        Some(c) => database::session::get_user(c.value())
+            .await
+            .unwrap_or_default()
+            .map(|u| u.role),
+    };
+    let is_logged_in = role.is_some();
+
+    let (allow_guest, instance_name, target) = {
+        let config = CONFIG.get().unwrap().lock().await;
+        (
+            config.allow_guest,
+            config.instance_name.clone(),
+            config.sla_target,
+        )
+    };
+    let can_view = !(!allow_guest && !is_logged_in);
+
+    let month = q.get("month").cloned().unwrap_or_else(sla::current_month);
+    let reports = if !can_view {
+        Err((StatusCode::UNAUTHORIZED, "Log in to see this".to_string()))
+    } else if sla::parse_month(&month).is_none() {
+        Err((
+            StatusCode::BAD_REQUEST,
+            "Invalid month, it must look like YYYY-MM".to_string(),
+        ))
+    } else {
+        sla::monthly_reports(&month, target, role)
+            .await
+            .map_err(|e| {
+                (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    format!("Failed to create SLA report: {e}"),
+                )
+            })
+    };
+
+    let render = html!(
+        (DOCTYPE)
+        html {
+            head {
+                (HTML_HEADER_GLOB)
+                title { "SLA report - " (instance_name) }
+            }
+
+            body {
+                header style="display: flex; align-items: center;" {
+                    a href="/" {
+                        img.logo src="/static/logo.png" alt="Logo";
+                    }
+
+                    h1 style="margin-bottom: 16px; margin-left: 16px; padding: 16px" { "SLA report" }
+                }
+
+                @if !can_view {
+                    p { "Log in to see this" }
+                } @else {
+                    form method="get" {
+                        label for="month" { "Month " }
+                        input #month name="month" type="month" value=(month);
+                        " "
+                        input type="submit" value="Show";
+                    }
+
+                    @match &reports {
+                        Ok(reports) => { (render_sla_reports(reports)) }
+                        Err((_, e)) => { p { (e) } }
+                    }
+
+                    p { a href={ "/api/sla?month=" (month) } { "JSON" } }
+                }
+            }
+        }
+    );
+
+    let status = match reports {
+        Ok(_) => StatusCode::OK,
+        Err((status, _)) => status,
+    };
+
+    (status, render)
+}
@@ -0,0 +1,170 @@
+use crate::{
+    config::{ConfigStatusPage, CONFIG},
+    database,
+    monitor::Monitor,
+    templates::{chart, result_to_text_color, HTML_HEADER_GLOB},
+    time_util::{self, current_unix_time},
+    uptime,
+};
+
+use std::collections::HashMap;
+
+use axum::extract::Path;
+use lazy_static::lazy_static;
+use maud::{html, Markup, DOCTYPE};
+use reqwest::StatusCode;
+use tokio::sync::Mutex;
+
+static UPTIME_BAR_DAYS: u64 = 90;
+// Status pages are public, the bars are reused for this long instead of reading 90 days of
+// records on every request
+static UPTIME_BARS_CACHE_SECS: u64 = 60;
+
+lazy_static! {
+    // monitor id -> (rendered at, uptime bars)
+    static ref UPTIME_BARS: Mutex<HashMap<u64, (u64, Markup)>> = Mutex::new(HashMap::new());
+}
+
+async fn render_uptime_bars(mon_id: u64, mon: &Monitor) -> Markup {
+    let now = current_unix_time();
+    if let Some((rendered_at, bars)) = UPTIME_BARS.lock().await.get(&mon_id) {
+        if now.saturating_sub(*rendered_at) < UPTIME_BARS_CACHE_SECS {
+            return bars.clone();
+        }
+    }
+
+    let since = now - 60 * 60 * 24 * UPTIME_BAR_DAYS;
+    let records = uptime::records_covering(mon_id, since)
+        .await
+        .unwrap_or_default();
+
+    let bars = chart::render_uptime_bar(
+        &records,
+        now,
+        UPTIME_BAR_DAYS,
+        60 * 60 * 24,
+        mon.interval_mins,
+    );
+    UPTIME_BARS.lock().await.insert(mon_id, (now, bars.clone()));
+
+    bars
+}
+
+async fn render_status_page(page: &ConfigStatusPage) -> Markup {
+    // status pages are public, monitors hidden from guests are left out
+    let mut public = HashMap::new();
+    for id in page.monitor_ids() {
+        if let Some(mon) = database::monitor::get_by_id(id)
+            .await
+            .filter(|m| m.is_visible_to(None))
+        {
+            public.insert(id, mon);
+        }
+    }
+    let public_ids: Vec<u64> = public.keys().copied().collect();
+
+    let since = current_unix_time() - 60 * 60 * 24 * UPTIME_BAR_DAYS;
+    let incidents = database::incident::published_from_mons(&public_ids, since)
+        .await
+        .unwrap_or_default();
+
+    html!(
+        @if !incidents.is_empty() {
+            h2 { "Incidents" }
+            @for incident in &incidents {
+                @let mon_name = page
+                    .groups
+                    .iter()
+                    .flat_map(|g| &g.monitors)
+                    .find(|m| m.id == incident.monitor_id)
+                    .map(|m| m.name.as_str())
+                    .unwrap_or_default();
+                blockquote {
+                    strong { (mon_name) }
+                    @if incident.ended_at.is_none() { " - ongoing" }
+                    br;
+                    small {
+                        (time_util::format_unix_time(incident.started_at))
+                        " (" (time_util::format_duration(incident.duration_secs())) ")"
+                    }
+                    @if let Some(comment) = &incident.comment {
+                        p { (comment) }
+                    }
+                }
+            }
+        }
+
+        @for group in &page.groups {
+            table {
+                caption { (group.name) }
+                tbody {
+                    @for (mon, monitor) in group.monitors.iter().filter_map(|m| Some((m, public.get(&m.id)?))) {
+                        tr {
+                            th scope="row" { (mon.name) }
+                            @if let Ok(last_record) = database::record::util_last_record(mon.id).await {
+                                @let (msg, color) = result_to_text_color(&last_record.result);
+                                td { span style={ "color: " (color) } { (msg) } }
+                            } @else {
+                                td { "N/A" }
+                            }
+                            td { (render_uptime_bars(mon.id, monitor).await) }
+                        }
+                    }
+                }
+            }
+        }
+    )
+}
+
+pub async fn status_page_template(slug: Path<String>) -> (StatusCode, Markup) {
+    // cloned so the config is not locked while rendering
+    let page = CONFIG
+        .get()
+        .unwrap()
+        .lock()
+        .await
+        .status_pages
+        .iter()
+        .find(|p| p.slug == *slug)
+        .cloned();
+    let Some(page) = page else {
+        let render = html!(
+            (DOCTYPE)
+            html {
+                head {
+                    (HTML_HEADER_GLOB)
+                    title { "Not found" }
+                }
+
+                body {
+                    header { h1 { "404 Not Found" } }
+                    p { "A status page with this name was not found" }
+                }
+            }
+        );
+
+        return (StatusCode::NOT_FOUND, render);
+    };
+
+    let render = html!(
+        (DOCTYPE)
+        html {
+            head {
+                (HTML_HEADER_GLOB)
+                title { (page.title) }
+            }
+
+            body {
+                header style="display: flex; align-items: center;" {
+                    img.logo src=(page.logo.as_deref().unwrap_or("/static/logo.png")) alt="Logo";
+
+                    h1 style="margin-bottom: 16px; margin-left: 16px; padding: 16px" { (page.title) }
+                }
+
+                (render_status_page(&page).await)
+            }
+        }
+    );
+
+    (StatusCode::OK, render)
+}
@@ -1,4 +1,4 @@
-use chrono::Utc;
+use chrono::{DateTime, Utc};
 use std::time::UNIX_EPOCH;
 
 pub fn current_unix_time() -> u64 {
@@ -8,13 +8,24 @@ pub fn current_unix_time() -> u64 {
         .as_secs()
 }
 
+pub fn format_unix_time(time: u64) -> String {
+    DateTime::from_timestamp(time as i64, 0)
+        .map(|dt| dt.format("%Y-%m-%d %H:%M UTC").to_string())
+        .unwrap_or_else(|| "invalid time".to_string())
+}
+
 pub fn time_diff_now(time: i64) -> String {
     let current_time = Utc::now().timestamp();
-    let mut diff = current_time - time;
+    let diff = current_time - time;
     if diff < 0 {
         return "in the future (how??)".to_string();
     }
 
+    format_duration(diff as u64)
+}
+
+pub fn format_duration(secs: u64) -> String {
+    let mut diff = secs;
     if diff < 60 {
         if diff == 1 {
             return format!("{diff} second");
@@ -0,0 +1,178 @@
+use anyhow::{anyhow, bail};
+use lazy_static::lazy_static;
+use qrcode::{render::svg, QrCode};
+use subtle::ConstantTimeEq;
+use tokio::sync::Mutex;
+use totp_rs::{Algorithm, Secret, TOTP};
+
+use crate::{config::CONFIG, time_util::current_unix_time};
+
+// RFC 6238 defaults, the only parameters most authenticator apps support
+static DIGITS: usize = 6;
+static STEP_SECS: u64 = 30;
+// Codes of the previous and next time step are accepted as well (clock drift)
+static ALLOWED_SKEW_STEPS: u64 = 1;
+// 160 bit, as recommended by RFC 4226
+static SECRET_BYTES: usize = 20;
+static RECOVERY_CODE_COUNT: usize = 10;
+
+lazy_static! {
+    // Last time step used to log in with `login.totp_secret`, users store theirs in the database
+    static ref CONFIG_LAST_STEP: Mutex<Option<u64>> = Mutex::new(None);
+}
+
+fn totp(secret: &str, issuer: Option<&str>, account_name: &str) -> anyhow::Result<TOTP> {
+    let secret = Secret::Encoded(secret.to_uppercase())
+        .to_bytes()
+        .map_err(|e| anyhow!("Invalid TOTP secret: {e:?}"))?;
+
+    // the issuer and account name may not contain colons
+    TOTP::new(
+        Algorithm::SHA1,
+        DIGITS,
+        0,
+        STEP_SECS,
+        secret,
+        issuer.map(|i| i.replace(':', "")),
+        account_name.replace(':', ""),
+    )
+    .map_err(|e| anyhow!("Invalid TOTP secret: {e}"))
+}
+
+// Random base32 encoded secret
+pub fn generate_secret() -> anyhow::Result<String> {
+    let mut bytes = [0u8; SECRET_BYTES];
+    if let Err(e) = getrandom::getrandom(&mut bytes) {
+        bail!("Failed to generate TOTP secret: {e}");
+    }
+
+    Ok(Secret::Raw(bytes.to_vec()).to_encoded().to_string())
+}
+
+pub fn is_valid_secret(secret: &str) -> bool {
+    totp(secret, None, "").is_ok()
+}
+
+// otpauth:// URI for authenticator apps, `issuer` is shown as name of the account in the app
+pub fn provisioning_uri(secret: &str, issuer: &str, account_name: &str) -> anyhow::Result<String> {
+    Ok(totp(secret, Some(issuer), account_name)?.get_url())
+}
+
+// The provisioning URI as QR code (SVG)
+pub fn provisioning_qr_svg(uri: &str) -> anyhow::Result<String> {
+    let svg = QrCode::new(uri)?
+        .render::<svg::Color>()
+        .min_dimensions(200, 200)
+        .build();
+
+    // only the <svg> element is needed to embed it into HTML
+    Ok(svg
+        .find("<svg")
+        .map(|i| svg[i..].to_string())
+        .unwrap_or(svg))
+}
+
+// Returns the time step of the code if it is valid and newer than `last_step`, so every code
+// can only be used once
+pub fn verify(secret: &str, code: &str, last_step: Option<u64>) -> Option<u64> {
+    let totp = totp(secret, None, "").ok()?;
+    let code = normalize_code(code);
+    let current_step = current_unix_time() / STEP_SECS;
+
+    (current_step.saturating_sub(ALLOWED_SKEW_STEPS)..=current_step + ALLOWED_SKEW_STEPS)
+        .filter(|step| last_step.is_none_or(|last| *step > last))
+        .find(|step| {
+            let expected = totp.generate(step * STEP_SECS);
+            bool::from(expected.as_bytes().ct_eq(code.as_bytes()))
+        })
+}
+
+// Checks a code against `login.totp_secret`, None if it is not set
+pub async fn verify_config(code: &str) -> Option<bool> {
+    let secret = CONFIG
+        .get()
+        .unwrap()
+        .lock()
+        .await
+        .login
+        .totp_secret
+        .clone()?;
+
+    let mut last_step = CONFIG_LAST_STEP.lock().await;
+    let step = verify(&secret, code, *last_step);
+    if step.is_some() {
+        *last_step = step;
+    }
+
+    Some(step.is_some())
+}
+
+// Removes spaces and dashes, which authenticator apps and recovery code lists like to add
+pub fn normalize_code(code: &str) -> String {
+    code.chars()
+        .filter(|c| !c.is_whitespace() && *c != '-')
+        .collect::<String>()
+        .to_lowercase()
+}
+
+// One-time codes to log in if the authenticator is lost, looks like 1a2b3-c4d5e
+pub fn generate_recovery_codes() -> anyhow::Result<Vec<String>> {
+    let mut codes = vec![];
+    for _ in 0..RECOVERY_CODE_COUNT {
+        let mut bytes = [0u8; 5];
+        if let Err(e) = getrandom::getrandom(&mut bytes) {
+            bail!("Failed to generate recovery code: {e}");
+        }
+        let code = hex::encode(bytes);
+        codes.push(format!("{}-{}", &code[..5], &code[5..]));
+    }
+
+    Ok(codes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    static SECRET: &str = "JBSWY3DPEHPK3PXPJBSWY3DPEHPK3PXP";
+
+    fn code_at_step(step: u64) -> String {
+        totp(SECRET, None, "").unwrap().generate(step * STEP_SECS)
+    }
+
+    #[test]
+    fn accepts_codes_within_the_skew() {
+        let step = current_unix_time() / STEP_SECS;
+        assert_eq!(verify(SECRET, &code_at_step(step), None), Some(step));
+        assert_eq!(
+            verify(SECRET, &code_at_step(step - 1), None),
+            Some(step - 1)
+        );
+    }
+
+    #[test]
+    fn rejects_codes_outside_the_skew() {
+        let step = current_unix_time() / STEP_SECS;
+        assert_eq!(verify(SECRET, &code_at_step(step - 5), None), None);
+        assert_eq!(verify(SECRET, &code_at_step(step + 5), None), None);
+        assert_eq!(verify(SECRET, "000000x", None), None);
+        assert_eq!(verify("not base32!", &code_at_step(step), None), None);
+    }
+
+    #[test]
+    fn rejects_replayed_codes() {
+        let step = current_unix_time() / STEP_SECS;
+        let code = code_at_step(step - 1);
+        let used = verify(SECRET, &code, None).unwrap();
+
+        assert_eq!(verify(SECRET, &code, Some(used)), None);
+        // older codes are rejected once a newer one was used
+        assert_eq!(verify(SECRET, &code, Some(used + 1)), None);
+    }
+
+    #[test]
+    fn normalizes_codes() {
+        assert_eq!(normalize_code(" 123 456 "), "123456");
+        assert_eq!(normalize_code("1A2B3-C4D5E"), "1a2b3c4d5e");
+    }
+}
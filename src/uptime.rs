@@ -0,0 +1,325 @@
+use serde::Serialize;
+
+use crate::database::{
+    self,
+    record::{MonitorRecord, RecordResult},
+};
+
+// A record is valid for at most this many check intervals, time after that counts as unknown
+// (the checker was offline or stalled)
+static MAX_RECORD_VALIDITY_INTERVALS: u64 = 2;
+// Gaps between records around a record that its check interval is estimated from, the median
+// ignores single outages and checks run by hand
+static INTERVAL_ESTIMATE_GAPS: usize = 5;
+// Added to the validity of every record, checks are only started every few seconds and can take
+// up to the timeout of the monitor
+static RECORD_VALIDITY_SLACK_SECS: u64 = 90;
+
+// Seconds spent in each state
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct Uptime {
+    pub ok: u64,
+    pub unexpected: u64,
+    pub down: u64,
+    pub err: u64,
+    // No record covers this time
+    pub unknown: u64,
+}
+
+// A time span with the state the monitor was in, None = unknown
+struct Segment {
+    start: u64,
+    end: u64,
+    result: Option<RecordResult>,
+}
+
+// The check interval in seconds at the time of each record, estimated from the gaps between the
+// records around it so past periods keep their interval after it was changed
+// Falls back to the current interval if there is only one record
+// `records` must be sorted by time_checked in descending order
+fn estimated_intervals(records: &[MonitorRecord], interval_mins: u64) -> Vec<u64> {
+    let gaps: Vec<u64> = records
+        .windows(2)
+        .map(|w| w[0].time_checked.saturating_sub(w[1].time_checked))
+        .collect();
+
+    (0..records.len())
+        .map(|i| {
+            let half = INTERVAL_ESTIMATE_GAPS / 2;
+            let window =
+                &gaps[i.saturating_sub(half).min(gaps.len())..(i + half + 1).min(gaps.len())];
+            if window.is_empty() {
+                return 60 * interval_mins;
+            }
+
+            let mut window = window.to_vec();
+            window.sort_unstable();
+            window[window.len() / 2]
+        })
+        .collect()
+}
+
+// Splits the time between `since` and `until` into segments, every record lasts until the next
+// record but at most until its validity ran out
+// `records` must be sorted by time_checked in descending order
+fn segments(records: &[MonitorRecord], since: u64, until: u64, interval_mins: u64) -> Vec<Segment> {
+    let intervals = estimated_intervals(records, interval_mins);
+    let mut segments = vec![];
+    let mut cursor = since;
+    let mut push = |start: u64, end: u64, result: Option<RecordResult>| {
+        if end > start {
+            segments.push(Segment { start, end, result });
+        }
+    };
+
+    for (i, r) in records.iter().enumerate().rev() {
+        let next = if i == 0 {
+            until
+        } else {
+            records[i - 1].time_checked
+        };
+        let start = r.time_checked.max(since);
+        let end = next.min(until);
+        if end <= start {
+            continue;
+        }
+
+        let validity = intervals[i] * MAX_RECORD_VALIDITY_INTERVALS + RECORD_VALIDITY_SLACK_SECS;
+        let valid_end = end.min(r.time_checked + validity).max(start);
+        push(cursor, start, None);
+        push(start, valid_end, Some(r.result));
+        push(valid_end, end, None);
+        cursor = end;
+    }
+    push(cursor, until, None);
+
+    segments
+}
+
+impl Uptime {
+    // `records` must be sorted by time_checked in descending order, the first record before
+    // `since` should be included so the state at `since` is known
+    pub fn from_records(
+        records: &[MonitorRecord],
+        since: u64,
+        until: u64,
+        interval_mins: u64,
+    ) -> Self {
+        let mut uptime = Self::default();
+        for s in segments(records, since, until, interval_mins) {
+            uptime.add(s.result, s.end - s.start);
+        }
+
+        uptime
+    }
+
+    // One uptime per bucket, index 0 is the oldest one, the last bucket ends at `until`
+    pub fn buckets_from_records(
+        records: &[MonitorRecord],
+        until: u64,
+        buckets: u64,
+        bucket_secs: u64,
+        interval_mins: u64,
+    ) -> Vec<Self> {
+        let since = until.saturating_sub(buckets * bucket_secs);
+        let mut uptimes = vec![Self::default(); buckets as usize];
+        for s in segments(records, since, until, interval_mins) {
+            let mut start = s.start;
+            while start < s.end {
+                let bucket = (start - since) / bucket_secs;
+                let end = s.end.min(since + (bucket + 1) * bucket_secs);
+                uptimes[bucket as usize].add(s.result, end - start);
+                start = end;
+            }
+        }
+
+        uptimes
+    }
+
+    fn add(&mut self, result: Option<RecordResult>, secs: u64) {
+        match result {
+            Some(RecordResult::Ok) => self.ok += secs,
+            Some(RecordResult::Unexpected) => self.unexpected += secs,
+            Some(RecordResult::Down) => self.down += secs,
+            Some(RecordResult::Err) => self.err += secs,
+            None => self.unknown += secs,
+        };
+    }
+
+    pub fn get(&self, result: &RecordResult) -> u64 {
+        match result {
+            RecordResult::Ok => self.ok,
+            RecordResult::Unexpected => self.unexpected,
+            RecordResult::Down => self.down,
+            RecordResult::Err => self.err,
+        }
+    }
+
+    // Time in which the state of the monitor is known
+    pub fn known(&self) -> u64 {
+        self.ok + self.unexpected + self.down + self.err
+    }
+
+    pub fn not_ok(&self) -> u64 {
+        self.unexpected + self.down + self.err
+    }
+
+    // Share of the known time spent in this state, None if nothing is known
+    pub fn perc(&self, result: &RecordResult) -> Option<f64> {
+        (self.known() > 0).then(|| self.get(result) as f64 / self.known() as f64 * 100.)
+    }
+
+    // Share of the whole time in which the state is unknown, None if there is no time at all
+    pub fn unknown_perc(&self) -> Option<f64> {
+        let total = self.known() + self.unknown;
+        (total > 0).then(|| self.unknown as f64 / total as f64 * 100.)
+    }
+
+    pub fn availability_perc(&self) -> Option<f64> {
+        self.perc(&RecordResult::Ok)
+    }
+}
+
+// All records checked since `since` and the last one before, to know the state at `since`
+// Sorted by time_checked in descending order
+pub async fn records_covering(mon_id: u64, since: u64) -> anyhow::Result<Vec<MonitorRecord>> {
+    let mut records = database::record::records_from_mon_since(mon_id, since).await?;
+    if let Some(before) = database::record::util_record_before(mon_id, since).await? {
+        records.push(before);
+    }
+
+    Ok(records)
+}
+
+pub async fn for_monitor(
+    mon_id: u64,
+    interval_mins: u64,
+    since: u64,
+    until: u64,
+) -> anyhow::Result<Uptime> {
+    let records = records_covering(mon_id, since).await?;
+
+    Ok(Uptime::from_records(&records, since, until, interval_mins))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // `times` in ascending order, returned in the descending order the functions expect
+    fn records(times: impl IntoIterator<Item = u64>, result: RecordResult) -> Vec<MonitorRecord> {
+        let mut records: Vec<MonitorRecord> = times
+            .into_iter()
+            .map(|time_checked| MonitorRecord {
+                time_checked,
+                result,
+                response_time_ms: None,
+                monitor_id: 1,
+                info: String::new(),
+            })
+            .collect();
+        records.reverse();
+        records
+    }
+
+    #[test]
+    fn regular_checks_cover_the_whole_time() {
+        let records = records((0..10).map(|i| 1000 + i * 60), RecordResult::Ok);
+        let uptime = Uptime::from_records(&records, 1000, 1600, 1);
+
+        assert_eq!(uptime.ok, 600);
+        assert_eq!(uptime.unknown, 0);
+        assert_eq!(uptime.availability_perc(), Some(100.));
+    }
+
+    #[test]
+    fn time_before_the_first_record_is_unknown() {
+        let records = records((0..10).map(|i| 1000 + i * 60), RecordResult::Ok);
+        let uptime = Uptime::from_records(&records, 0, 1600, 1);
+
+        assert_eq!(uptime.ok, 600);
+        assert_eq!(uptime.unknown, 1000);
+        assert_eq!(uptime.unknown_perc(), Some(1000. / 1600. * 100.));
+    }
+
+    #[test]
+    fn records_last_until_the_next_one() {
+        let mut all = records([0, 60], RecordResult::Ok);
+        all.splice(0..0, records([120, 180], RecordResult::Down));
+        let uptime = Uptime::from_records(&all, 0, 240, 1);
+
+        assert_eq!(uptime.ok, 120);
+        assert_eq!(uptime.down, 120);
+        assert_eq!(uptime.not_ok(), 120);
+        assert_eq!(uptime.availability_perc(), Some(50.));
+    }
+
+    #[test]
+    fn outages_of_the_checker_are_unknown() {
+        // checked every minute, nothing between 540 and 3600
+        let records = records(
+            (0..10)
+                .map(|i| i * 60)
+                .chain((0..10).map(|i| 3600 + i * 60)),
+            RecordResult::Ok,
+        );
+        // the interval was changed to an hour since, the old records keep theirs
+        let uptime = Uptime::from_records(&records, 0, 4200, 60);
+
+        let validity = 2 * 60 + RECORD_VALIDITY_SLACK_SECS;
+        assert_eq!(uptime.unknown, 3600 - (540 + validity));
+        assert_eq!(uptime.ok, 4200 - uptime.unknown);
+    }
+
+    #[test]
+    fn past_records_keep_their_interval() {
+        // checked every hour, the interval was lowered to a minute since
+        let records = records((0..8).map(|i| i * 3600), RecordResult::Ok);
+        let uptime = Uptime::from_records(&records, 0, 7 * 3600 + 60, 1);
+
+        assert_eq!(uptime.unknown, 0);
+    }
+
+    #[test]
+    fn single_records_use_the_current_interval() {
+        let records = records([0], RecordResult::Err);
+        let uptime = Uptime::from_records(&records, 0, 10_000, 1);
+
+        let validity = 2 * 60 + RECORD_VALIDITY_SLACK_SECS;
+        assert_eq!(uptime.err, validity);
+        assert_eq!(uptime.unknown, 10_000 - validity);
+        assert_eq!(uptime.availability_perc(), Some(0.));
+    }
+
+    #[test]
+    fn no_records_is_unknown() {
+        let uptime = Uptime::from_records(&[], 0, 600, 1);
+
+        assert_eq!(uptime.unknown, 600);
+        assert_eq!(uptime.availability_perc(), None);
+    }
+
+    #[test]
+    fn buckets_split_the_segments() {
+        let records = records((0..10).map(|i| 1000 + i * 60), RecordResult::Ok);
+        let buckets = Uptime::buckets_from_records(&records, 1600, 4, 150, 1);
+
+        assert_eq!(buckets.len(), 4);
+        for bucket in &buckets {
+            assert_eq!(bucket.ok, 150);
+            assert_eq!(bucket.unknown, 0);
+        }
+    }
+
+    #[test]
+    fn estimates_ignore_single_gaps() {
+        let records = records(
+            [0, 60, 120, 125, 185, 245, 3000, 3060, 3120],
+            RecordResult::Ok,
+        );
+
+        assert!(estimated_intervals(&records, 60)
+            .iter()
+            .all(|interval| *interval == 60));
+    }
+}
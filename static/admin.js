@@ -28,6 +28,120 @@ async function onToggle(id) {
     window.location.reload();
 }
 
+function describeRecord(record) {
+    let time = record.response_time_ms === null ? "" : ` in ${record.response_time_ms}ms`;
+    return `Monitor ${record.monitor_id}: ${record.result}${time} (${record.info})`;
+}
+
+async function onCheck(id) {
+    let res = await fetch(`/api/monitors/${id}/check`, { method: "POST" });
+    if (res.status !== 200) {
+        alert(await res.text());
+        return;
+    }
+
+    alert(describeRecord(await res.json()));
+    window.location.reload();
+}
+
+async function onCheckDown() {
+    let res = await fetch("/api/monitors/check_down", { method: "POST" });
+    if (res.status !== 200) {
+        alert(await res.text());
+        return;
+    }
+
+    let records = await res.json();
+    alert(records.length === 0 ? "All monitors are up" : records.map(describeRecord).join("\n"));
+    window.location.reload();
+}
+
+async function onEditRequest(id) {
+    let params = {};
+    if (elem("replace-headers").checked) {
+        params.hds = elem("edit-headers").value.trim();
+    }
+    if (elem("replace-body").checked) {
+        params.body = btoa(elem("edit-body").value);
+    }
+
+    let res = await fetch(`/api/monitors/${id}/request`, { method: "PATCH", body: new URLSearchParams(params) });
+    alert(await res.text());
+    if (res.status === 200) {
+        document.location.reload();
+    }
+}
+
+async function onVisibilityChange(id) {
+    let visibility = elem("edit-visibility").value;
+    let hideLocation = elem("edit-hide-location").checked;
+
+    let res = await fetch(`/api/monitors/${id}/visibility`, { method: "PATCH", body: new URLSearchParams({ vi: visibility, hl: hideLocation }) });
+    alert(await res.text());
+    if (res.status === 200) {
+        document.location.reload();
+    }
+}
+
+async function onIncidentComment(id) {
+    let comment = prompt("Comment / postmortem for this incident (leave empty to remove):");
+    if (comment === null) {
+        return;
+    }
+
+    let res = await fetch(`/api/incidents/${id}/comment`, { method: "PATCH", body: new URLSearchParams({ cm: comment }) });
+    if (res.status !== 200) {
+        alert(await res.text());
+        return;
+    }
+
+    window.location.reload();
+}
+
+async function onIncidentPublish(id) {
+    let res = await fetch(`/api/incidents/${id}/publish`, { method: "PATCH" });
+    alert(await res.text());
+    window.location.reload();
+}
+
+async function onAddUser() {
+    let username = elem("new-username").value;
+    let password = elem("new-password").value;
+    let role = elem("new-role").value;
+
+    let res = await fetch("/api/users", { method: "PUT", body: new URLSearchParams({ un: username, pw: password, ro: role }) });
+    alert(await res.text());
+    if (res.status === 201) {
+        document.location.reload();
+    }
+}
+
+async function onUserDelete(id) {
+    if (!confirm("Delete this user?")) {
+        return;
+    }
+
+    let res = await fetch(`/api/users/${id}`, { method: "DELETE" });
+    alert(await res.text());
+    window.location.reload();
+}
+
+async function onUserRoleChange(id, role) {
+    let res = await fetch(`/api/users/${id}/role`, { method: "PATCH", body: new URLSearchParams({ ro: role }) });
+    alert(await res.text());
+    window.location.reload();
+}
+
+async function onRevokeSession(id) {
+    if (!confirm("Revoke this session?")) {
+        return;
+    }
+
+    let res = await fetch(`/api/sessions/${id}`, { method: "DELETE" });
+    alert(await res.text());
+    window.location.reload();
+}
+
 function onAddTypeChange() {
     let serviceType = elem("service-type").value;
 
@@ -48,27 +162,24 @@ function onHttpExpectedResponseChange() {
     responseBodyOptions.hidden = responseType.value !== "res";
 }
 
-async function onAdd() {
+// Params of add_monitor_route from the add form
+function addFormParams() {
     let serviceType = elem("service-type").value;
     let serviceName = elem("service-name").value;
     let intervalMins = elem("interval").value;
     let timeoutSecs = elem("timeout").value;
+    let visibility = elem("visibility").value;
+    let hideLocation = elem("hide-location").checked;
 
-    let url = `/api/monitors?ty=${serviceType}&na=${uriEnc(serviceName)}&in=${intervalMins}&to=${timeoutSecs}`;
+    let params = `ty=${serviceType}&na=${uriEnc(serviceName)}&in=${intervalMins}&to=${timeoutSecs}&vi=${visibility}&hl=${hideLocation}`;
 
     switch (serviceType) {
         case "tcp": {
             let socketAddr = elem("sock-addr").value;
             let expectedResponse = elem("tcp-expected-response").value;
-            
-            url += `&sa=${uriEnc(socketAddr)}&exre=${expectedResponse}`;
-            // TODO: add sh and ex
-            let res = await fetch(url, { method: "PUT" });
-            alert(await res.text());
-            if (res.status === 201) {
-                document.location.reload();
-            }
 
+            params += `&sa=${uriEnc(socketAddr)}&exre=${expectedResponse}`;
+            // TODO: add sh and ex
             break;
         }
         case "http": {
@@ -77,42 +188,96 @@ async function onAdd() {
             let headers = elem("headers").value.trim();
             let expectedResponse = elem("http-expected-response").value;
             let requestBody = elem("request-body").value;
-            
-            url += `&url=${uriEnc(serviceUrl)}&hds=${uriEnc(headers)}&exre=${expectedResponse}&body=${btoa(requestBody)}`
-
-            switch (expectedResponse) {
-                case "any": {
-                    let res = await fetch(url, { method: "PUT" });
-                    alert(await res.text());
-
-                    if (res.status === 201) {
-                        document.location.reload();
-                    }
-
-                    break;
-                }
-                case "sc": {
-                    let statusCode = elem("status-code").value;
-                    
-                    url += `&co=${statusCode}`;
-
-                    let res = await fetch(url, { method: "PUT" });
-                    alert(await res.text());
-
-                    if (res.status === 201) {
-                        document.location.reload();
-                    }
-                    
-                    break;
-                }
-                case "res": {
-                    // TODO
-                    break;
-                }
-            }
 
+            params += `&met=${method}&url=${uriEnc(serviceUrl)}&hds=${uriEnc(headers)}&exre=${expectedResponse}&body=${btoa(requestBody)}`;
+
+            let statusCode = elem("status-code").value.trim();
+            if (expectedResponse === "sc" || (expectedResponse === "res" && statusCode !== "")) {
+                params += `&co=${uriEnc(statusCode)}`;
+            }
+            if (expectedResponse === "res") {
+                params += `&bch=${elem("body-cs").value}`;
+            }
             break;
         }
         default: console.log("unknown service type " + serviceType);
     }
-}
\ No newline at end of file
+
+    return params;
+}
+
+async function onAdd() {
+    let res = await fetch("/api/monitors", { method: "PUT", body: new URLSearchParams(addFormParams()) });
+    alert(await res.text());
+    if (res.status === 201) {
+        document.location.reload();
+    }
+}
+
+// Runs the check of the add form once without adding the monitor
+async function onTest() {
+    let output = elem("test-result");
+    output.hidden = false;
+    output.textContent = "Testing...";
+
+    let res = await fetch("/api/monitors/test", { method: "POST", body: new URLSearchParams(addFormParams()) });
+    if (res.status !== 200) {
+        output.textContent = await res.text();
+        return;
+    }
+
+    let test = await res.json();
+    let time = test.response_time_ms === null ? "" : ` in ${test.response_time_ms}ms`;
+    let lines = [`${test.result}${time}: ${test.info}`];
+    let response = test.response;
+    switch (response.type) {
+        case "http": {
+            lines.push("", `Status: ${response.status}`);
+            lines.push(...response.headers.map(([name, value]) => `${name}: ${value}`));
+            lines.push("", `Body (${response.body_bytes} bytes, adler32 ${response.body_adler32}):`, response.body);
+            // the checksum is what the `res` expected response needs
+            elem("body-cs").value = response.body_adler32;
+            break;
+        }
+        case "tcp": {
+            lines.push("", `Received ${response.bytes} bytes:`, response.hex_dump);
+            break;
+        }
+    }
+    output.textContent = lines.join("\n");
+}
+
+async function onAddApiToken() {
+    let name = elem("token-name").value;
+    let scopes = [...document.querySelectorAll(".token-scope:checked")].map(s => s.value).join(",");
+    let expiry = elem("token-expiry").value;
+
+    let res = await fetch("/api/tokens", { method: "PUT", body: new URLSearchParams({ na: name, sc: scopes, ex: expiry }) });
+    if (res.status !== 201) {
+        alert(await res.text());
+        return;
+    }
+
+    prompt("API token was added, copy it now, it will not be shown again:", await res.text());
+    document.location.reload();
+}
+
+async function onRevokeApiToken(id) {
+    if (!confirm("Revoke this API token?")) {
+        return;
+    }
+
+    let res = await fetch(`/api/tokens/${id}`, { method: "DELETE" });
+    alert(await res.text());
+    window.location.reload();
+}
+
+async function onUserTotpReset(id) {
+    if (!confirm("Reset the two-factor authentication of this user? They can log in with their password only afterwards.")) {
+        return;
+    }
+
+    let res = await fetch(`/api/users/${id}/totp`, { method: "DELETE" });
+    alert(await res.text());
+    window.location.reload();
+}
@@ -1,19 +1,220 @@
 async function onLogin() {
-    console.log();
+    let username = document.getElementById("username");
     let password = document.getElementById("password");
-    let res = await fetch(`/api/create_session?pw=${password.value}`, { method: "POST" });
+    let otp = document.getElementById("otp");
+    let res = await fetch("/api/create_session", {
+        method: "POST",
+        body: new URLSearchParams({ un: username.value, pw: password.value, otp: otp.value }),
+    });
+
+    if (res.status !== 200) {
+        let text = await res.text();
+        // the password was correct, ask for the second factor
+        if (text === "TOTP code required") {
+            otp.hidden = false;
+            otp.focus();
+            return;
+        }
 
-    if (res.status === 401) {
         password.value = "";
-        password.placeholder = "wrong password";
+        otp.value = "";
+        password.placeholder = res.status === 401 ? "wrong username, password or code" : text;
+        return;
+    }
+
+    // the session cookie is set by the server
+    document.location.href = "/"
+}
+
+async function onLogout() {
+    await fetch("/api/logout", { method: "POST" });
+    document.location.href = "/"
+}
+
+async function onTotpBegin() {
+    let res = await fetch("/api/totp", { method: "PUT" });
+    if (res.status !== 201) {
+        alert(await res.text());
+    }
+    window.location.reload();
+}
+
+async function onTotpEnable() {
+    let code = document.getElementById("totp-code").value;
+    let res = await fetch("/api/totp", { method: "PATCH", body: new URLSearchParams({ otp: code }) });
+    if (res.status !== 200) {
+        alert(await res.text());
         return;
     }
-    
-    let token = await res.text();
-    let date = new Date();
-    let now = date.getTime();
-    let expiry = now + 60 * 60 * 24 * 7; // 7 days
-    date.setTime(expiry);
-    document.cookie = `token=${token}; expiry=${date.toUTCString()}`;
-    document.location.href = "/admin"
-}
\ No newline at end of file
+
+    // only shown once, the page shows the enabled state after a reload
+    document.getElementById("totpform").hidden = true;
+    let codes = document.getElementById("recovery-codes");
+    codes.textContent = "Two-factor authentication is enabled. Store these recovery codes, each of them can be used once instead of a TOTP code:\n\n" + await res.text();
+    codes.hidden = false;
+}
+
+async function onTotpDisable() {
+    let code = document.getElementById("totp-code").value;
+    let res = await fetch("/api/totp", { method: "DELETE", body: new URLSearchParams({ otp: code }) });
+    alert(await res.text());
+    window.location.reload();
+}
+
+// Live updates of the monitor list, the page works without them but has to be reloaded
+const RESULT_TEXT_COLOR = {
+    Ok: ["Up", "#6fff31"],
+    Unexpected: ["UX", "#f48421"],
+    Down: ["Down", "#cb0b0b"],
+    Err: ["Err", "#550505"],
+};
+const RESULT_BACKGROUND_COLOR = {
+    Ok: "rgba(0, 0, 0, 0)",
+    Unexpected: "rgba(245, 204, 0, 0.1)",
+    Down: "rgba(255, 0, 0, 0.1)",
+    Err: "rgba(125, 21, 21, 0.1)",
+};
+
+// same as format_duration in time_util.rs
+function formatDuration(secs) {
+    let units = [["second", 60], ["minute", 60], ["hour", 24], ["day", 30], ["month", 12]];
+    let diff = secs;
+    for (let [unit, next] of units) {
+        if (diff < next) {
+            return `${diff} ${unit}${diff === 1 ? "" : "s"}`;
+        }
+        diff = Math.floor(diff / next);
+    }
+    return `${diff} year${diff === 1 ? "" : "s"}`;
+}
+
+function updateCheckedAgo() {
+    let now = Math.floor(Date.now() / 1000);
+    for (let row of document.querySelectorAll("tr[data-checked-at]")) {
+        let diff = now - parseInt(row.dataset.checkedAt);
+        row.querySelector(".checked-ago").textContent = diff < 0 ? "in the future (how??)" : formatDuration(diff);
+    }
+}
+
+function updateIncidentDurations() {
+    let now = Math.floor(Date.now() / 1000);
+    for (let row of document.querySelectorAll("tr[data-incident-id]")) {
+        let endedAt = row.dataset.endedAt ? parseInt(row.dataset.endedAt) : now;
+        let duration = formatDuration(endedAt - parseInt(row.dataset.startedAt));
+        row.querySelector(".duration").textContent = row.dataset.endedAt ? duration : `${duration} (ongoing)`;
+    }
+}
+
+// same as format_unix_time in time_util.rs
+function formatUnixTime(secs) {
+    return new Date(secs * 1000).toISOString().slice(0, 16).replace("T", " ") + " UTC";
+}
+
+// number of incidents shown on the main page
+const RECENT_INCIDENTS = 10;
+
+// like the rows of render_incident_list in templates/mod.rs
+function newIncidentRow(incident) {
+    let row = document.createElement("tr");
+    row.dataset.incidentId = incident.id;
+    row.dataset.startedAt = incident.started_at;
+
+    let monitor = document.createElement("a");
+    monitor.href = `/monitor/${incident.monitor_id}`;
+    let name = document.querySelector(`tr[data-monitor-id="${incident.monitor_id}"] .service-name`);
+    monitor.textContent = name && name.textContent ? name.textContent : `#${incident.monitor_id}`;
+
+    let [msg, color] = RESULT_TEXT_COLOR[incident.result];
+    let result = document.createElement("span");
+    result.textContent = msg;
+    result.style.color = color;
+
+    let cells = [monitor, formatUnixTime(incident.started_at), "", result, "", incident.comment || ""];
+    for (let content of cells) {
+        row.insertCell().append(content);
+    }
+    row.cells[2].className = "duration";
+    row.cells[4].className = "info";
+
+    return row;
+}
+
+function onLiveState(state) {
+    let incident = state.incident;
+    if (!incident) {
+        return;
+    }
+
+    let row = document.querySelector(`tr[data-incident-id="${incident.id}"]`);
+    if (!row) {
+        let list = document.getElementById("incidents");
+        if (!list) {
+            return;
+        }
+        let body = list.querySelector("tbody");
+        row = newIncidentRow(incident);
+        body.prepend(row);
+        while (body.rows.length > RECENT_INCIDENTS) {
+            body.lastElementChild.remove();
+        }
+        list.hidden = false;
+    }
+
+    if (incident.ended_at !== null) {
+        row.dataset.endedAt = incident.ended_at;
+    }
+    let info = row.querySelector(".info");
+    info.replaceChildren(incident.first_info);
+    if (incident.last_info !== incident.first_info) {
+        info.append(document.createElement("br"), `Last: ${incident.last_info}`);
+    }
+    updateIncidentDurations();
+}
+
+function onLiveRecord(record) {
+    let row = document.querySelector(`tr[data-monitor-id="${record.monitor_id}"]`);
+    if (!row) {
+        return;
+    }
+    // the first check of a monitor, the row has no result to update yet
+    if (!row.dataset.checkedAt) {
+        window.location.reload();
+        return;
+    }
+
+    let [msg, color] = RESULT_TEXT_COLOR[record.result];
+    let result = row.querySelector(".result");
+    result.textContent = msg;
+    result.title = record.info;
+    result.style.color = color;
+    row.querySelector(".response-time").textContent = record.response_time_ms === null ? "" : ` ${record.response_time_ms}ms`;
+    row.style.backgroundColor = RESULT_BACKGROUND_COLOR[record.result];
+    row.dataset.checkedAt = record.time_checked;
+    updateCheckedAgo();
+}
+
+function startLiveUpdates() {
+    if (!window.EventSource || !document.querySelector("tr[data-monitor-id]")) {
+        return;
+    }
+
+    let events = new EventSource("/api/events");
+    let connected = false;
+    events.addEventListener("open", () => {
+        // records were missed while the connection was down
+        if (connected) {
+            window.location.reload();
+        }
+        connected = true;
+    });
+    events.addEventListener("record", (e) => onLiveRecord(JSON.parse(e.data)));
+    events.addEventListener("state", (e) => onLiveState(JSON.parse(e.data)));
+    events.addEventListener("lagged", () => window.location.reload());
+
+    setInterval(() => {
+        updateCheckedAgo();
+        updateIncidentDurations();
+    }, 10000);
+}
+
+document.addEventListener("DOMContentLoaded", startLiveUpdates);